@@ -0,0 +1,331 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::{Decimal, MathematicalOps};
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::model::{backtest_result::BacktestResult, trade::Trade};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sizing {
+    // fraction of the equity lost when the stop is hit, e.g. 0.01
+    FixedFraction(Decimal),
+    // units bought or sold per trade
+    FixedSize(Decimal),
+    // currency value of the position at entry
+    FixedNotional(Decimal),
+}
+
+pub struct Account {
+    pub starting_balance: Decimal,
+    pub sizing: Sizing,
+    // fixed fraction and fixed notional grow and shrink with the equity,
+    // otherwise they stay sized on the starting balance
+    pub compounding: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct EquityPoint {
+    pub time: DateTime<Tz>,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AccountResult {
+    pub starting_balance: Decimal,
+    // balance after each trade, trades after the account is wiped out are skipped
+    pub equity_curve: Vec<EquityPoint>,
+    // None for a curve shorter than a day or an account that ends at zero
+    pub cagr: Option<Decimal>,
+    pub max_drawdown_pct: Decimal,
+}
+
+impl Account {
+    pub fn simulate(&self, result: &BacktestResult) -> Result<AccountResult> {
+        if self.starting_balance <= Decimal::ZERO {
+            bail!("starting balance must be positive");
+        }
+        match self.sizing {
+            Sizing::FixedFraction(fraction) if fraction >= Decimal::ONE => {
+                bail!("the fraction risked per trade must be below 1")
+            }
+            Sizing::FixedFraction(value)
+            | Sizing::FixedSize(value)
+            | Sizing::FixedNotional(value)
+                if value <= Decimal::ZERO =>
+            {
+                bail!("sizing must be positive")
+            }
+            _ => {}
+        }
+
+        let mut balance = self.starting_balance;
+        let mut equity_curve = vec![];
+        for trade in &result.trades {
+            if balance <= Decimal::ZERO {
+                break;
+            }
+            balance += self.units(trade, balance) * pnl_per_unit(trade);
+            equity_curve.push(EquityPoint {
+                time: trade.close_time,
+                balance: balance.max(Decimal::ZERO),
+            });
+        }
+
+        let cagr = match (result.trades.first(), equity_curve.last()) {
+            (Some(first), Some(last)) => cagr(
+                self.starting_balance,
+                last.balance,
+                last.time - first.open_time,
+            ),
+            _ => None,
+        };
+        Ok(AccountResult {
+            starting_balance: self.starting_balance,
+            max_drawdown_pct: max_drawdown_pct(self.starting_balance, &equity_curve),
+            equity_curve,
+            cagr,
+        })
+    }
+
+    fn units(&self, trade: &Trade, balance: Decimal) -> Decimal {
+        let base = if self.compounding {
+            balance
+        } else {
+            self.starting_balance
+        };
+        match self.sizing {
+            Sizing::FixedFraction(fraction) => {
+                let risk_per_unit = (trade.entry.0 - trade.sl.0).abs();
+                if risk_per_unit.is_zero() {
+                    return Decimal::ZERO;
+                }
+                base * fraction / risk_per_unit
+            }
+            Sizing::FixedSize(size) => size,
+            Sizing::FixedNotional(notional) => {
+                notional * base / self.starting_balance / trade.entry.0
+            }
+        }
+    }
+}
+
+// what one unit made or lost, from the realized R and the distance to the stop
+pub(crate) fn pnl_per_unit(trade: &Trade) -> Decimal {
+    trade.r_multiple() * (trade.entry.0 - trade.sl.0).abs()
+}
+
+pub(crate) fn cagr(start: Decimal, end: Decimal, duration: Duration) -> Option<Decimal> {
+    if duration < Duration::days(1) || end <= Decimal::ZERO {
+        return None;
+    }
+    let years = Decimal::from(duration.num_seconds()) / Decimal::from(365 * 24 * 60 * 60);
+    let growth = (end / start).checked_powd(Decimal::ONE / years)?;
+    Some((growth - Decimal::ONE) * Decimal::ONE_HUNDRED)
+}
+
+// deepest fall from a running peak, the starting balance included, in percent of the peak
+pub(crate) fn max_drawdown_pct(start: Decimal, curve: &[EquityPoint]) -> Decimal {
+    let mut peak = start;
+    let mut deepest = Decimal::ZERO;
+    for point in curve {
+        peak = peak.max(point.balance);
+        deepest = deepest.max((peak - point.balance) / peak * Decimal::ONE_HUNDRED);
+    }
+    deepest
+}
+
+impl AccountResult {
+    pub fn final_balance(&self) -> Decimal {
+        self.equity_curve
+            .last()
+            .map(|p| p.balance)
+            .unwrap_or(self.starting_balance)
+    }
+
+    pub fn return_pct(&self) -> Decimal {
+        (self.final_balance() - self.starting_balance) / self.starting_balance
+            * Decimal::ONE_HUNDRED
+    }
+
+    pub fn write_equity_curve<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "time,balance")?;
+        for point in &self.equity_curve {
+            writeln!(
+                writer,
+                "{},{}",
+                point.time.to_rfc3339(),
+                point.balance.normalize()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AccountResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "balance: {} -> {}, return: {}%, cagr: {}, max drawdown: {}%",
+            self.starting_balance.round_dp(2).normalize(),
+            self.final_balance().round_dp(2).normalize(),
+            self.return_pct().round_dp(2).normalize(),
+            match self.cagr {
+                Some(cagr) => format!("{}%", cagr.round_dp(2).normalize()),
+                None => "-".to_string(),
+            },
+            self.max_drawdown_pct.round_dp(2).normalize(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        decimal::DecimalVec, position::Position, position_direction::PositionDirection,
+        trade_result::TradeResult,
+    };
+    use crate::parse_datetime;
+
+    // long from 100 with the stop at 90 and the target at 120, one trade per day
+    fn result(results: &[TradeResult]) -> BacktestResult {
+        let open_time = parse_datetime("2022-09-30 00:00:00").unwrap();
+        let trades = results
+            .iter()
+            .enumerate()
+            .map(|(ind, result)| {
+                let open_time = open_time + Duration::days(ind as i64);
+                Trade::from_position(
+                    Position {
+                        direction: PositionDirection::Long,
+                        open_time,
+                        entry: DecimalVec::new(100),
+                        sl: DecimalVec::new(90),
+                        tp: DecimalVec::new(120),
+                        at_break_even: false,
+                    },
+                    open_time + Duration::hours(1),
+                    *result,
+                )
+            })
+            .collect();
+        BacktestResult { trades }
+    }
+
+    fn balances(account: &AccountResult) -> Vec<Decimal> {
+        account.equity_curve.iter().map(|p| p.balance).collect()
+    }
+
+    #[test]
+    fn test_fixed_fraction_compounds() {
+        let account = Account {
+            starting_balance: Decimal::from(1000),
+            sizing: Sizing::FixedFraction("0.1".parse().unwrap()),
+            compounding: true,
+        }
+        .simulate(&result(&[
+            TradeResult::Winner,
+            TradeResult::Expense,
+            TradeResult::BreakEven,
+        ]))
+        .unwrap();
+
+        // +2R on 1000 risking 100, then -1R on 1200 risking 120
+        assert_eq!(
+            balances(&account),
+            vec![
+                Decimal::from(1200),
+                Decimal::from(1080),
+                Decimal::from(1080)
+            ]
+        );
+        assert_eq!(account.return_pct(), Decimal::from(8));
+        assert_eq!(account.max_drawdown_pct, Decimal::from(10));
+    }
+
+    #[test]
+    fn test_fixed_fraction_without_compounding() {
+        let account = Account {
+            starting_balance: Decimal::from(1000),
+            sizing: Sizing::FixedFraction("0.1".parse().unwrap()),
+            compounding: false,
+        }
+        .simulate(&result(&[TradeResult::Winner, TradeResult::Expense]))
+        .unwrap();
+
+        assert_eq!(
+            balances(&account),
+            vec![Decimal::from(1200), Decimal::from(1100)]
+        );
+    }
+
+    #[test]
+    fn test_fixed_size_and_notional() {
+        let trades = result(&[TradeResult::Winner, TradeResult::Expense]);
+        let size = Account {
+            starting_balance: Decimal::from(1000),
+            sizing: Sizing::FixedSize(Decimal::from(5)),
+            compounding: true,
+        }
+        .simulate(&trades)
+        .unwrap();
+        // 5 units, +20 and -10 per unit
+        assert_eq!(
+            balances(&size),
+            vec![Decimal::from(1100), Decimal::from(1050)]
+        );
+
+        let notional = Account {
+            starting_balance: Decimal::from(1000),
+            sizing: Sizing::FixedNotional(Decimal::from(500)),
+            compounding: false,
+        }
+        .simulate(&trades)
+        .unwrap();
+        // 500 at 100 is 5 units
+        assert_eq!(balances(&notional), balances(&size));
+    }
+
+    #[test]
+    fn test_wiped_out_account_stops_trading() {
+        let account = Account {
+            starting_balance: Decimal::from(100),
+            sizing: Sizing::FixedSize(Decimal::from(20)),
+            compounding: true,
+        }
+        .simulate(&result(&[TradeResult::Expense, TradeResult::Winner]))
+        .unwrap();
+
+        assert_eq!(balances(&account), vec![Decimal::ZERO]);
+        assert_eq!(account.max_drawdown_pct, Decimal::ONE_HUNDRED);
+        assert_eq!(account.cagr, None);
+    }
+
+    #[test]
+    fn test_cagr() {
+        // doubling in a year is 100%
+        let cagr = cagr(
+            Decimal::from(1000),
+            Decimal::from(2000),
+            Duration::days(365),
+        )
+        .unwrap();
+        assert_eq!(cagr.round_dp(4), Decimal::ONE_HUNDRED);
+        assert_eq!(
+            super::cagr(Decimal::ONE, Decimal::TWO, Duration::hours(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_sizing() {
+        let account = Account {
+            starting_balance: Decimal::from(1000),
+            sizing: Sizing::FixedFraction(Decimal::ONE),
+            compounding: true,
+        };
+        assert!(account.simulate(&result(&[])).is_err());
+    }
+}
@@ -0,0 +1,853 @@
+use anyhow::{anyhow, bail, Context, Result};
+use backtest::account::{Account, Sizing};
+use backtest::config::{
+    parse_time, AmbiguityConfig, BacktestConfig, DataConfig, LoadedData, PortfolioConfig,
+    SessionConfig, StrategyConfig, SweepConfig,
+};
+use backtest::model::{
+    backtest_result::BacktestResult,
+    candle::Candle,
+    cost_model::{CostModel, Slippage},
+    trade_management::{TakeProfit, TradeManagement, Trailing},
+};
+use backtest::monte_carlo::{MonteCarlo, Resampling, Ruin};
+use backtest::optimizer::{format_params, grid, optimize};
+use backtest::resample::Timeframe;
+use backtest::strategies::lib::SwingConfig;
+use backtest::walk_forward::WalkForward;
+use chrono::{Duration, NaiveTime};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+fn parse_decimal(s: &str) -> Result<Decimal, String> {
+    s.parse::<Decimal>().map_err(|e| e.to_string())
+}
+
+// `90d`, `12h` or `30m`
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let error = || format!("expected a number with d, h or m, got {}", s);
+    let (unit, amount) = ['d', 'h', 'm']
+        .into_iter()
+        .find_map(|unit| Some((unit, s.strip_suffix(unit)?)))
+        .ok_or_else(error)?;
+    let amount = amount.parse::<i64>().map_err(|_| error())?;
+    Ok(match unit {
+        'd' => Duration::days(amount),
+        'h' => Duration::hours(amount),
+        _ => Duration::minutes(amount),
+    })
+}
+
+// `1:0.5` closes half of the position at 1R
+fn parse_take_profit(s: &str) -> Result<TakeProfit, String> {
+    let (r, fraction) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected R:FRACTION, got {}", s))?;
+    Ok(TakeProfit {
+        r: parse_decimal(r)?,
+        fraction: parse_decimal(fraction)?,
+    })
+}
+
+fn cli() -> Command {
+    Command::new("backtest")
+        .version("1.0")
+        .about("Runs trading models on historical candles")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("run")
+                .about("Runs a single strategy on a data file")
+                .arg(
+                    Arg::new("data")
+                        .short('d')
+                        .long("data")
+                        .required(true)
+                        .help("Binance kline json (.json) or New York time csv"),
+                )
+                .arg(
+                    Arg::new("timeframe")
+                        .long("timeframe")
+                        .value_parser(Timeframe::from_str)
+                        .help("Resample the data: 15m, 4h, 1d, 1d@HH:MM or a HH:MM-HH:MM session in New York time"),
+                )
+                .arg(
+                    Arg::new("complete-only")
+                        .long("complete-only")
+                        .action(ArgAction::SetTrue)
+                        .requires("timeframe")
+                        .help("Drop resampled bars with missing candles"),
+                )
+                .arg(
+                    Arg::new("strategy")
+                        .short('s')
+                        .long("strategy")
+                        .value_parser(["macro-soup", "sfp", "sfp-ltf"])
+                        .required(true)
+                        .help("Trading model to run"),
+                )
+                .arg(
+                    Arg::new("rr-threshold")
+                        .long("rr-threshold")
+                        .value_parser(parse_decimal)
+                        .required(true)
+                        .help("Minimum risk/reward to take a trade"),
+                )
+                .arg(
+                    Arg::new("session-start")
+                        .long("session-start")
+                        .value_parser(parse_time)
+                        .help("macro-soup: session start in New York time (HH:MM)"),
+                )
+                .arg(
+                    Arg::new("session-end")
+                        .long("session-end")
+                        .value_parser(parse_time)
+                        .help("macro-soup: session end in New York time (HH:MM)"),
+                )
+                .arg(
+                    Arg::new("max-duration-min")
+                        .long("max-duration-min")
+                        .value_parser(clap::value_parser!(i64))
+                        .default_value("30")
+                        .help("macro-soup: minutes after the session to look for a trigger"),
+                )
+                .arg(
+                    Arg::new("be-threshold")
+                        .long("be-threshold")
+                        .value_parser(parse_decimal)
+                        .conflicts_with("break-even")
+                        .help("macro-soup: move the stop to break even after this many R, same as --break-even"),
+                )
+                .arg(
+                    Arg::new("ambiguity")
+                        .long("ambiguity")
+                        .value_parser(["pessimistic", "optimistic", "open-proximity", "lower-timeframe"])
+                        .default_value("pessimistic")
+                        .help("Which of stop and target counts when a candle hits both"),
+                )
+                .arg(
+                    Arg::new("ltf-data")
+                        .long("ltf-data")
+                        .help("Lower timeframe candles for sfp-ltf entries and the lower-timeframe ambiguity policy"),
+                )
+                .arg(
+                    Arg::new("swing-left")
+                        .long("swing-left")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1")
+                        .help("sfp: candles before a swing"),
+                )
+                .arg(
+                    Arg::new("swing-right")
+                        .long("swing-right")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1")
+                        .help("sfp: candles after a swing, it is confirmed once they close"),
+                )
+                .arg(
+                    Arg::new("equal-tolerance")
+                        .long("equal-tolerance")
+                        .value_parser(parse_decimal)
+                        .help("sfp: highs and lows this close after a swing count as equal and keep it"),
+                )
+                .arg(
+                    Arg::new("break-even")
+                        .long("break-even")
+                        .value_parser(parse_decimal)
+                        .help("management: move the stop to the entry after this many R"),
+                )
+                .arg(
+                    Arg::new("trail-r")
+                        .long("trail-r")
+                        .value_parser(parse_decimal)
+                        .conflicts_with_all(["trail-atr", "trail-swing"])
+                        .help("management: trail the stop this many R behind the best price"),
+                )
+                .arg(
+                    Arg::new("trail-atr")
+                        .long("trail-atr")
+                        .value_parser(clap::value_parser!(usize))
+                        .conflicts_with("trail-swing")
+                        .help("management: trail the stop by the average true range of this many candles"),
+                )
+                .arg(
+                    Arg::new("atr-multiple")
+                        .long("atr-multiple")
+                        .value_parser(parse_decimal)
+                        .default_value("2")
+                        .help("management: average true ranges between the best price and the trailing stop"),
+                )
+                .arg(
+                    Arg::new("trail-swing")
+                        .long("trail-swing")
+                        .action(ArgAction::SetTrue)
+                        .help("management: trail the stop behind the last swing"),
+                )
+                .arg(
+                    Arg::new("take-profit")
+                        .long("take-profit")
+                        .value_parser(parse_take_profit)
+                        .action(ArgAction::Append)
+                        .help("management: close part of the position at a level, R:FRACTION (e.g. 1:0.5), repeatable"),
+                )
+                .arg(
+                    Arg::new("time-exit")
+                        .long("time-exit")
+                        .value_parser(parse_duration)
+                        .help("management: close the position after this long (e.g. 4h)"),
+                )
+                .arg(
+                    Arg::new("maker-fee")
+                        .long("maker-fee")
+                        .value_parser(parse_decimal)
+                        .help("costs: percent fee on take profit fills (e.g. 0.02)"),
+                )
+                .arg(
+                    Arg::new("taker-fee")
+                        .long("taker-fee")
+                        .value_parser(parse_decimal)
+                        .help("costs: percent fee on entries and stop fills (e.g. 0.05)"),
+                )
+                .arg(
+                    Arg::new("commission")
+                        .long("commission")
+                        .value_parser(parse_decimal)
+                        .help("costs: commission per contract per fill"),
+                )
+                .arg(
+                    Arg::new("point-value")
+                        .long("point-value")
+                        .value_parser(parse_decimal)
+                        .help("costs: value of one point of one contract, 1 when missing"),
+                )
+                .arg(
+                    Arg::new("slippage-ticks")
+                        .long("slippage-ticks")
+                        .value_parser(parse_decimal)
+                        .requires("tick-size")
+                        .conflicts_with("slippage-pct")
+                        .help("costs: ticks lost on stop fills"),
+                )
+                .arg(
+                    Arg::new("tick-size")
+                        .long("tick-size")
+                        .value_parser(parse_decimal)
+                        .help("costs: price of one tick"),
+                )
+                .arg(
+                    Arg::new("slippage-pct")
+                        .long("slippage-pct")
+                        .value_parser(parse_decimal)
+                        .help("costs: percent of the price lost on stop fills"),
+                )
+                .arg(
+                    Arg::new("funding-rate")
+                        .long("funding-rate")
+                        .value_parser(parse_decimal)
+                        .help("costs: percent funding per 8 hours, paid by longs"),
+                )
+                .arg(
+                    Arg::new("check-lookahead")
+                        .long("check-lookahead")
+                        .action(ArgAction::SetTrue)
+                        .help("Replay every candle without the candles after it and fail if the orders change, slow on long series"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Write the trades and statistics as json"),
+                )
+                .arg(
+                    Arg::new("trade-log")
+                        .long("trade-log")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Write the trades as csv"),
+                )
+                .arg(
+                    Arg::new("monte-carlo")
+                        .long("monte-carlo")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Resample the trades this many times"),
+                )
+                .arg(
+                    Arg::new("resampling")
+                        .long("resampling")
+                        .value_parser(["shuffle", "bootstrap"])
+                        .default_value("shuffle")
+                        .help("monte carlo: reorder the trades or draw them with replacement"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0")
+                        .help("monte carlo: random seed"),
+                )
+                .arg(
+                    Arg::new("risk-per-trade")
+                        .long("risk-per-trade")
+                        .value_parser(parse_decimal)
+                        .help("monte carlo: fraction of the account risked per trade for the risk of ruin (e.g. 0.01)"),
+                )
+                .arg(
+                    Arg::new("ruin-level")
+                        .long("ruin-level")
+                        .value_parser(parse_decimal)
+                        .default_value("0.5")
+                        .help("monte carlo: fraction of the starting account that counts as ruin"),
+                )
+                .arg(
+                    Arg::new("balance")
+                        .long("balance")
+                        .value_parser(parse_decimal)
+                        .help("Simulate an account with this starting balance"),
+                )
+                .arg(
+                    Arg::new("risk-fraction")
+                        .long("risk-fraction")
+                        .value_parser(parse_decimal)
+                        .conflicts_with_all(["fixed-size", "fixed-notional"])
+                        .help("account: fraction of the equity lost at the stop (e.g. 0.01)"),
+                )
+                .arg(
+                    Arg::new("fixed-size")
+                        .long("fixed-size")
+                        .value_parser(parse_decimal)
+                        .conflicts_with("fixed-notional")
+                        .help("account: units per trade"),
+                )
+                .arg(
+                    Arg::new("fixed-notional")
+                        .long("fixed-notional")
+                        .value_parser(parse_decimal)
+                        .help("account: position value at entry"),
+                )
+                .arg(
+                    Arg::new("no-compounding")
+                        .long("no-compounding")
+                        .action(ArgAction::SetTrue)
+                        .help("account: size on the starting balance instead of the equity"),
+                )
+                .arg(
+                    Arg::new("equity-curve")
+                        .long("equity-curve")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("account: write the balance after each trade as csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Runs every run of a config file, writes <name>.json and <name>.csv per run")
+                .arg(
+                    Arg::new("config")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Backtest config (.toml or .json)"),
+                ),
+        )
+        .subcommand(
+            Command::new("portfolio")
+                .about("Runs the legs of a portfolio config on a shared account")
+                .arg(
+                    Arg::new("config")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Portfolio config (.toml or .json)"),
+                )
+                .arg(
+                    Arg::new("trade-log")
+                        .long("trade-log")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Write every trade of every leg as csv"),
+                )
+                .arg(
+                    Arg::new("equity-curve")
+                        .long("equity-curve")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Write the balance after each trade as csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("optimize")
+                .about("Runs every parameter combination of a sweep config and ranks them")
+                .arg(
+                    Arg::new("config")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Sweep config (.toml or .json)"),
+                )
+                .arg(
+                    Arg::new("top")
+                        .long("top")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10")
+                        .help("Number of best runs to print"),
+                ),
+        )
+        .subcommand(
+            Command::new("walk-forward")
+                .about("Optimizes a sweep config on rolling in sample windows and trades the best parameters out of sample")
+                .arg(
+                    Arg::new("config")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Sweep config (.toml or .json), the window table goes to its output"),
+                )
+                .arg(
+                    Arg::new("in-sample")
+                        .long("in-sample")
+                        .value_parser(parse_duration)
+                        .required(true)
+                        .help("In sample window length (e.g. 90d)"),
+                )
+                .arg(
+                    Arg::new("out-of-sample")
+                        .long("out-of-sample")
+                        .value_parser(parse_duration)
+                        .required(true)
+                        .help("Out of sample window length (e.g. 30d)"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Write the stitched out of sample result as json"),
+                ),
+        )
+}
+
+fn cost_model(matches: &ArgMatches) -> CostModel {
+    let decimal = |name: &str| matches.get_one::<Decimal>(name).copied();
+    let default = CostModel::default();
+    CostModel {
+        maker_fee_pct: decimal("maker-fee").unwrap_or(default.maker_fee_pct),
+        taker_fee_pct: decimal("taker-fee").unwrap_or(default.taker_fee_pct),
+        commission: decimal("commission").unwrap_or(default.commission),
+        point_value: decimal("point-value").unwrap_or(default.point_value),
+        slippage: match (
+            decimal("slippage-ticks"),
+            decimal("tick-size"),
+            decimal("slippage-pct"),
+        ) {
+            (Some(ticks), Some(tick_size), _) => Some(Slippage::Ticks { ticks, tick_size }),
+            (_, _, Some(pct)) => Some(Slippage::Pct { pct }),
+            _ => None,
+        },
+        funding_rate_pct: decimal("funding-rate").unwrap_or(default.funding_rate_pct),
+    }
+}
+
+fn trade_management(matches: &ArgMatches) -> TradeManagement {
+    let trailing = if let Some(r) = matches.get_one::<Decimal>("trail-r") {
+        Some(Trailing::FixedR { r: *r })
+    } else if let Some(period) = matches.get_one::<usize>("trail-atr") {
+        Some(Trailing::Atr {
+            period: *period,
+            multiple: *matches
+                .get_one::<Decimal>("atr-multiple")
+                .expect("atr-multiple has a default"),
+        })
+    } else if matches.get_flag("trail-swing") {
+        Some(Trailing::Swing)
+    } else {
+        None
+    };
+    TradeManagement {
+        break_even_r: matches.get_one::<Decimal>("break-even").copied(),
+        trailing,
+        take_profits: matches
+            .get_many::<TakeProfit>("take-profit")
+            .map(|tps| tps.copied().collect())
+            .unwrap_or_default(),
+        time_exit_min: matches
+            .get_one::<Duration>("time-exit")
+            .map(|d| d.num_minutes()),
+    }
+}
+
+fn swing_config(matches: &ArgMatches) -> SwingConfig {
+    SwingConfig {
+        left: *matches
+            .get_one::<usize>("swing-left")
+            .expect("swing-left has a default"),
+        right: *matches
+            .get_one::<usize>("swing-right")
+            .expect("swing-right has a default"),
+        equal_tolerance: matches.get_one::<Decimal>("equal-tolerance").copied(),
+        ..SwingConfig::default()
+    }
+}
+
+fn ambiguity_config(matches: &ArgMatches) -> Result<AmbiguityConfig> {
+    let policy = matches
+        .get_one::<String>("ambiguity")
+        .expect("ambiguity has a default");
+    Ok(match policy.as_str() {
+        "optimistic" => AmbiguityConfig::Optimistic,
+        "open-proximity" => AmbiguityConfig::OpenProximity,
+        "lower-timeframe" => AmbiguityConfig::LowerTimeframe {
+            data: DataConfig {
+                path: matches
+                    .get_one::<String>("ltf-data")
+                    .ok_or_else(|| anyhow!("lower-timeframe needs --ltf-data"))?
+                    .into(),
+                format: None,
+                timeframe: None,
+                complete_only: false,
+            },
+        },
+        _ => AmbiguityConfig::Pessimistic,
+    })
+}
+
+fn strategy_config(matches: &ArgMatches) -> Result<StrategyConfig> {
+    let rr_threshold = *matches
+        .get_one::<Decimal>("rr-threshold")
+        .expect("rr-threshold is a required argument");
+    let strategy = matches
+        .get_one::<String>("strategy")
+        .expect("strategy is a required argument");
+
+    let config = match strategy.as_str() {
+        "macro-soup" => StrategyConfig::MacroSoup {
+            rr_threshold,
+            session: SessionConfig {
+                start: *matches
+                    .get_one::<NaiveTime>("session-start")
+                    .ok_or_else(|| anyhow!("macro-soup needs --session-start"))?,
+                end: *matches
+                    .get_one::<NaiveTime>("session-end")
+                    .ok_or_else(|| anyhow!("macro-soup needs --session-end"))?,
+            },
+            max_duration_min: *matches
+                .get_one::<i64>("max-duration-min")
+                .expect("max-duration-min has a default"),
+            be_threshold: matches.get_one::<Decimal>("be-threshold").copied(),
+            management: trade_management(matches),
+            costs: cost_model(matches),
+            ambiguity: ambiguity_config(matches)?,
+        },
+        "sfp" => StrategyConfig::Sfp {
+            rr_treshold: rr_threshold,
+            swings: swing_config(matches),
+            management: trade_management(matches),
+            costs: cost_model(matches),
+            ambiguity: ambiguity_config(matches)?,
+        },
+        "sfp-ltf" => StrategyConfig::SfpLtf {
+            rr_treshold: rr_threshold,
+            ltf: DataConfig {
+                path: matches
+                    .get_one::<String>("ltf-data")
+                    .ok_or_else(|| anyhow!("sfp-ltf needs --ltf-data"))?
+                    .into(),
+                format: None,
+                timeframe: None,
+                complete_only: false,
+            },
+            swings: swing_config(matches),
+            management: trade_management(matches),
+            costs: cost_model(matches),
+            ambiguity: ambiguity_config(matches)?,
+        },
+        other => bail!("unknown strategy: {}", other),
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let strategy = strategy_config(matches)?;
+    let data = DataConfig {
+        path: matches
+            .get_one::<String>("data")
+            .expect("data is a required argument")
+            .into(),
+        format: None,
+        timeframe: matches.get_one::<Timeframe>("timeframe").copied(),
+        complete_only: matches.get_flag("complete-only"),
+    };
+    let candles = data.load()?;
+    if candles.is_empty() {
+        bail!("{} contains no candles", data.path.display());
+    }
+
+    let model = strategy.build(candles, &LoadedData::default())?;
+    if matches.get_flag("check-lookahead") {
+        let found = model
+            .check_lookahead()
+            .ok_or_else(|| anyhow!("this strategy doesn't run on the candle engine"))?;
+        for lookahead in &found {
+            eprintln!("{}", lookahead);
+        }
+        if !found.is_empty() {
+            bail!(
+                "the strategy reads future candles in {} places",
+                found.len()
+            );
+        }
+    }
+    let result = model.execute();
+    println!("{}", result);
+
+    if let Some(path) = matches.get_one::<PathBuf>("json") {
+        write_json(&result, path)?;
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("trade-log") {
+        write_trade_log(&result, path)?;
+    }
+    if let Some(iterations) = matches.get_one::<usize>("monte-carlo") {
+        let monte_carlo = MonteCarlo {
+            iterations: *iterations,
+            resampling: match matches
+                .get_one::<String>("resampling")
+                .expect("resampling has a default")
+                .as_str()
+            {
+                "bootstrap" => Resampling::Bootstrap,
+                _ => Resampling::Shuffle,
+            },
+            seed: *matches.get_one::<u64>("seed").expect("seed has a default"),
+            ruin: matches
+                .get_one::<Decimal>("risk-per-trade")
+                .map(|risk_per_trade| Ruin {
+                    risk_per_trade: *risk_per_trade,
+                    ruin_level: *matches
+                        .get_one::<Decimal>("ruin-level")
+                        .expect("ruin-level has a default"),
+                }),
+        };
+        println!("{}", monte_carlo.run(&result)?);
+    }
+    if let Some(balance) = matches.get_one::<Decimal>("balance") {
+        let sizing = if let Some(fraction) = matches.get_one::<Decimal>("risk-fraction") {
+            Sizing::FixedFraction(*fraction)
+        } else if let Some(size) = matches.get_one::<Decimal>("fixed-size") {
+            Sizing::FixedSize(*size)
+        } else if let Some(notional) = matches.get_one::<Decimal>("fixed-notional") {
+            Sizing::FixedNotional(*notional)
+        } else {
+            bail!("--balance needs --risk-fraction, --fixed-size or --fixed-notional");
+        };
+        let account = Account {
+            starting_balance: *balance,
+            sizing,
+            compounding: !matches.get_flag("no-compounding"),
+        }
+        .simulate(&result)?;
+        println!("{}", account);
+
+        if let Some(path) = matches.get_one::<PathBuf>("equity-curve") {
+            let mut writer = create_output(path)?;
+            account
+                .write_equity_curve(&mut writer)
+                .and_then(|_| writer.flush())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json(result: &BacktestResult, path: &Path) -> Result<()> {
+    let mut writer = create_output(path)?;
+    serde_json::to_writer_pretty(&mut writer, result)
+        .map_err(io::Error::from)
+        .and_then(|_| writer.flush())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_trade_log(result: &BacktestResult, path: &Path) -> Result<()> {
+    let mut writer = create_output(path)?;
+    result
+        .write_trade_log(&mut writer)
+        .and_then(|_| writer.flush())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn batch(matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<PathBuf>("config")
+        .expect("config is a required argument");
+    let config = BacktestConfig::from_path(path)?;
+
+    fs::create_dir_all(&config.output_dir)
+        .with_context(|| format!("Failed to create {}", config.output_dir.display()))?;
+
+    // runs often share a data file, load each one only once
+    let mut candles: HashMap<PathBuf, Vec<Candle>> = HashMap::new();
+    let loaded = LoadedData::default();
+    for run in &config.runs {
+        if !candles.contains_key(&run.data.path) {
+            candles.insert(run.data.path.clone(), run.data.source().load()?);
+        }
+        let result: BacktestResult =
+            run.execute(run.data.resample(candles[&run.data.path].clone()), &loaded)?;
+        println!("{}: {}", run.name, result);
+
+        write_json(
+            &result,
+            &config.output_dir.join(format!("{}.json", run.name)),
+        )?;
+        write_trade_log(
+            &result,
+            &config.output_dir.join(format!("{}.csv", run.name)),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn portfolio(matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<PathBuf>("config")
+        .expect("config is a required argument");
+    let config = PortfolioConfig::from_path(path)?;
+
+    let result = config.portfolio().run(&config.legs()?)?;
+    print!("{}", result);
+
+    if let Some(path) = matches.get_one::<PathBuf>("trade-log") {
+        write_trade_log(&result.combined, path)?;
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("equity-curve") {
+        let mut writer = create_output(path)?;
+        result
+            .account
+            .write_equity_curve(&mut writer)
+            .and_then(|_| writer.flush())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn sweep(matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<PathBuf>("config")
+        .expect("config is a required argument");
+    let top = *matches.get_one::<usize>("top").expect("top has a default");
+    let config = SweepConfig::from_path(path)?;
+
+    let candles = config.data.load()?;
+    if candles.is_empty() {
+        bail!("{} contains no candles", config.data.path.display());
+    }
+    let sets = grid(&config.params)?;
+    println!("running {} parameter sets", sets.len());
+
+    let optimization = optimize(
+        &config.template(),
+        sets,
+        &candles,
+        config.metric,
+        config.threads,
+    );
+
+    for (params, reason) in &optimization.rejected {
+        println!("rejected {}: {}", format_params(params), reason);
+    }
+    for run in optimization.runs.iter().take(top) {
+        println!("{}: {}", format_params(&run.params), run.result);
+    }
+
+    let mut writer = create_output(&config.output)?;
+    optimization
+        .write_table(&mut writer)
+        .and_then(|_| writer.flush())
+        .with_context(|| format!("Failed to write {}", config.output.display()))
+}
+
+fn walk_forward(matches: &ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<PathBuf>("config")
+        .expect("config is a required argument");
+    let config = SweepConfig::from_path(path)?;
+    let walk_forward = WalkForward {
+        in_sample: *matches
+            .get_one::<Duration>("in-sample")
+            .expect("in-sample is a required argument"),
+        out_of_sample: *matches
+            .get_one::<Duration>("out-of-sample")
+            .expect("out-of-sample is a required argument"),
+    };
+
+    let candles = config.data.load()?;
+    let sets = grid(&config.params)?;
+    let result = walk_forward.run(
+        &config.template(),
+        &sets,
+        &candles,
+        config.metric,
+        config.threads,
+    )?;
+    if result.windows.is_empty() {
+        bail!("the data is shorter than one in sample window");
+    }
+
+    for w in &result.windows {
+        println!(
+            "{} - {}: {} -> {}",
+            w.window.out_of_sample_start.format("%Y-%m-%d %H:%M"),
+            w.window.out_of_sample_end.format("%Y-%m-%d %H:%M"),
+            w.params
+                .as_ref()
+                .map(format_params)
+                .unwrap_or_else(|| "no trades in sample".to_string()),
+            w.out_of_sample
+        );
+    }
+    println!("out of sample: {}", result.result);
+
+    let mut writer = create_output(&config.output)?;
+    result
+        .write_table(&mut writer)
+        .and_then(|_| writer.flush())
+        .with_context(|| format!("Failed to write {}", config.output.display()))?;
+    if let Some(path) = matches.get_one::<PathBuf>("json") {
+        write_json(&result.result, path)?;
+    }
+    Ok(())
+}
+
+fn create_output(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+fn main() -> Result<()> {
+    let matches = cli().get_matches();
+
+    match matches.subcommand() {
+        Some(("run", matches)) => run(matches),
+        Some(("batch", matches)) => batch(matches),
+        Some(("portfolio", matches)) => portfolio(matches),
+        Some(("optimize", matches)) => sweep(matches),
+        Some(("walk-forward", matches)) => walk_forward(matches),
+        _ => unreachable!("subcommand is required"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90d"), Ok(Duration::days(90)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::minutes(30)));
+        for bad in ["", "d", "5", "5s", "5µ", "µm"] {
+            assert!(parse_duration(bad).is_err(), "{}", bad);
+        }
+    }
+}
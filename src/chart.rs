@@ -12,49 +12,22 @@ use charming::{
     Chart,
 };
 use rust_decimal::Decimal;
-use std::str::FromStr;
 
+use crate::data::{CandleSource, LoadError};
+use crate::engine::OnCandles;
+use crate::{model::trading_model::TradingModel, strategies::macro_soup::MacroSoup};
 use crate::{
-    model::{backtest_result::BacktestResult, decimal::DecimalVec, session::Session},
+    model::{
+        ambiguity_policy::AmbiguityPolicy, backtest_result::BacktestResult, cost_model::CostModel,
+        decimal::DecimalVec, session::Session, trade_management::TradeManagement,
+    },
     parse_datetime,
 };
-use crate::{
-    model::{binance_klines_item::BinanceKlinesItem, candle_stick::CandleStick},
-    read_csv,
-};
-use crate::{
-    model::{candle_ny::CandleNY, trading_model::TradingModel},
-    strategies::macro_soup::MacroSoup,
-};
 
 fn execute<T: TradingModel>(model: T) -> BacktestResult {
     model.execute()
 }
 
-// TODO: extract to data loader
-fn load_data() -> Vec<CandleStick> {
-    let raw_data: Vec<BinanceKlinesItem> =
-        serde_json::from_str(include_str!("../assets/eth15.json")).unwrap();
-    // serde_json::from_str(include_str!("../assets/ETHUSDT_15m.json")).unwrap();
-
-    raw_data
-        .iter()
-        .enumerate()
-        .map(|(_, v)| CandleStick {
-            open_time: v.open_time as i64,
-            open: DecimalVec(Decimal::from_str(v.open.as_str()).unwrap()),
-            high: DecimalVec(Decimal::from_str(v.high.as_str()).unwrap()),
-            low: DecimalVec(Decimal::from_str(v.low.as_str()).unwrap()),
-            close: DecimalVec(Decimal::from_str(v.close.as_str()).unwrap()),
-            close_time: v.close_time as i64,
-        })
-        .collect::<Vec<_>>()
-}
-
-fn load_csv() -> Vec<CandleNY> {
-    read_csv("/Users/jupposessho/develop/play/rust/backtest/assets/NDX_full_1min.txt").unwrap()
-}
-
 // fn round_to_nearest_15_minute(dt: DateTime<Tz>) -> (u32, u32) {
 //     let minute = dt.minute();
 //     let rounded_minute = (minute / 15) * 15;
@@ -128,7 +101,7 @@ fn load_csv() -> Vec<CandleNY> {
 //     }
 // }
 
-pub fn chart() -> Chart {
+pub fn chart(source: &dyn CandleSource) -> Result<Chart, LoadError> {
     // let candlesticks = load_data();
 
     // let category_data = candlesticks
@@ -157,18 +130,23 @@ pub fn chart() -> Chart {
     // let result = execute(sfp);
     // println!("============result {:#?}", result);
 
-    let candlesticks = load_csv();
+    let candlesticks = source.load()?;
 
-    let sfp = MacroSoup {
+    let sfp = OnCandles {
         candles: candlesticks.clone(),
-        rr_threshold: Decimal::from(3),
-        be_threshold: Some(DecimalVec::new(2)),
-
-        session: Session {
-            start: parse_datetime("2022-09-30 09:50:00").unwrap().time(),
-            end: parse_datetime("2022-09-30 10:10:00").unwrap().time(),
+        strategy: MacroSoup {
+            rr_threshold: Decimal::from(3),
+            be_threshold: Some(DecimalVec::new(2)),
+
+            session: Session {
+                start: parse_datetime("2022-09-30 09:50:00").unwrap().time(),
+                end: parse_datetime("2022-09-30 10:10:00").unwrap().time(),
+            },
+            max_duration_min: 30,
+            management: TradeManagement::default(),
+            costs: CostModel::default(),
+            ambiguity: AmbiguityPolicy::default(),
         },
-        max_duration_min: 30,
     };
     let result = execute(sfp);
     println!("============result {:#?}", result);
@@ -185,7 +163,7 @@ pub fn chart() -> Chart {
         })
         .collect::<Vec<_>>();
 
-    Chart::new()
+    Ok(Chart::new()
         .legend(
             Legend::new()
                 .bottom(10)
@@ -238,5 +216,5 @@ pub fn chart() -> Chart {
                 .end(100)
                 .min_value_span(10),
         )
-        .series(Candlestick::new().data(data.clone()))
+        .series(Candlestick::new().data(data.clone())))
 }
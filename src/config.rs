@@ -0,0 +1,959 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveTime;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::data::{self, BinanceJsonSource, CandleSource, NyCsvSource};
+use crate::engine::OnCandles;
+use crate::model::{
+    ambiguity_policy::AmbiguityPolicy, backtest_result::BacktestResult, candle::Candle,
+    cost_model::CostModel, decimal::DecimalVec, position_direction::PositionDirection,
+    session::Session, trade_management::TradeManagement, trading_model::TradingModel,
+    trigger_type::TriggerType,
+};
+use crate::mtf::Mtf;
+use crate::optimizer::{Metric, ModelFactory, ParamRange, ParamSet};
+use crate::portfolio::{Leg, Portfolio};
+use crate::resample::{infer_interval, resample, Timeframe};
+use crate::strategies::{
+    lib::SwingConfig, macro_soup::MacroSoup, mayne::Mayne, sfp::Sfp, sfp_ltf::SfpLtf,
+};
+
+// A checked in backtest setup, e.g.
+//
+// output_dir = "results"
+//
+// [[runs]]
+// name = "ndx-0950"
+// data = { path = "assets/NDX_full_1min.txt" }
+// strategy = { type = "macro-soup", rr_threshold = 3, be_threshold = 2, max_duration_min = 30, session = { start = "09:50", end = "10:10" } }
+//
+// [runs.strategy.costs]
+// commission = 2.1
+// point_value = 20
+// slippage = { type = "ticks", ticks = 1, tick_size = 0.25 }
+#[derive(Debug, Deserialize)]
+pub struct BacktestConfig {
+    pub output_dir: PathBuf,
+    pub runs: Vec<RunConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunConfig {
+    pub name: String,
+    pub data: DataConfig,
+    pub strategy: StrategyConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DataConfig {
+    pub path: PathBuf,
+    // inferred from the extension when missing
+    pub format: Option<DataFormat>,
+    // resamples the file, e.g. `15m`, `4h`, `1d@18:00` or a `09:30-16:00` session
+    #[serde(default, deserialize_with = "timeframe")]
+    pub timeframe: Option<Timeframe>,
+    // drops resampled bars with missing candles, the last one is often still forming
+    #[serde(default)]
+    pub complete_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DataFormat {
+    BinanceJson,
+    NyCsv,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum StrategyConfig {
+    MacroSoup {
+        rr_threshold: Decimal,
+        session: SessionConfig,
+        max_duration_min: i64,
+        be_threshold: Option<Decimal>,
+        #[serde(default)]
+        management: TradeManagement,
+        #[serde(default)]
+        costs: CostModel,
+        #[serde(default)]
+        ambiguity: AmbiguityConfig,
+    },
+    Sfp {
+        rr_treshold: Decimal,
+        #[serde(default)]
+        swings: SwingConfig,
+        #[serde(default)]
+        management: TradeManagement,
+        #[serde(default)]
+        costs: CostModel,
+        #[serde(default)]
+        ambiguity: AmbiguityConfig,
+    },
+    // swings on the run's data, entries on `ltf`
+    SfpLtf {
+        rr_treshold: Decimal,
+        ltf: DataConfig,
+        #[serde(default)]
+        swings: SwingConfig,
+        #[serde(default)]
+        management: TradeManagement,
+        #[serde(default)]
+        costs: CostModel,
+        #[serde(default)]
+        ambiguity: AmbiguityConfig,
+    },
+    // one planned trade, `direction` is "long" or "short", `trigger_type` "close" or "wick"
+    Mayne {
+        #[serde(deserialize_with = "direction")]
+        direction: PositionDirection,
+        trigger_type: TriggerType,
+        trigger_level: Decimal,
+        invalidation_level: Decimal,
+        tp: Decimal,
+        rr_threshold: Decimal,
+        #[serde(default)]
+        management: TradeManagement,
+        #[serde(default)]
+        costs: CostModel,
+        #[serde(default)]
+        ambiguity: AmbiguityConfig,
+    },
+}
+
+// `{ type = "open-proximity" }` or `{ type = "lower-timeframe", data = { path = "..." } }`
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum AmbiguityConfig {
+    #[default]
+    Pessimistic,
+    Optimistic,
+    OpenProximity,
+    LowerTimeframe {
+        data: DataConfig,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SessionConfig {
+    #[serde(deserialize_with = "time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "time")]
+    pub end: NaiveTime,
+}
+
+pub fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+        .map_err(|_| format!("expected HH:MM, got {}", s))
+}
+
+fn time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_time(&s).map_err(serde::de::Error::custom)
+}
+
+fn direction<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PositionDirection, D::Error> {
+    match String::deserialize(deserializer)?.as_str() {
+        "long" => Ok(PositionDirection::Long),
+        "short" => Ok(PositionDirection::Short),
+        other => Err(serde::de::Error::custom(format!(
+            "expected long or short, got {}",
+            other
+        ))),
+    }
+}
+
+fn timeframe<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Timeframe>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse())
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+// Several strategies on a shared account, e.g.
+//
+// starting_balance = 10000
+// risk_fraction = 0.01
+// max_positions = 2
+// max_total_risk = 0.03
+//
+// [[legs]]
+// name = "eth-sfp"
+// symbol = "ETHUSDT"
+// data = { path = "assets/eth15.json" }
+// strategy = { type = "sfp", rr_treshold = 2 }
+#[derive(Debug, Deserialize)]
+pub struct PortfolioConfig {
+    pub starting_balance: Decimal,
+    pub risk_fraction: Decimal,
+    pub max_positions: Option<usize>,
+    pub max_total_risk: Option<Decimal>,
+    pub legs: Vec<LegConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegConfig {
+    pub name: String,
+    pub symbol: String,
+    pub data: DataConfig,
+    pub strategy: StrategyConfig,
+}
+
+// A parameter sweep, e.g.
+//
+// data = { path = "assets/NDX_full_1min.txt" }
+// metric = "expectancy"
+// output = "results/sweep.csv"
+// strategy = { type = "macro-soup", rr_threshold = 3, max_duration_min = 30, session = { start = "09:50", end = "10:10" } }
+//
+// [params]
+// rr_threshold = { start = 2, end = 5, step = 0.5 }
+// be_threshold = [1, 1.5, 2]
+// "session.start" = ["09:30", "09:50"]
+#[derive(Debug, Deserialize)]
+pub struct SweepConfig {
+    pub data: DataConfig,
+    // the strategy config the parameters are written into
+    pub strategy: Value,
+    pub params: BTreeMap<String, ParamRange>,
+    pub metric: Metric,
+    pub output: PathBuf,
+    pub threads: Option<usize>,
+}
+
+// `.toml` or `.json`
+fn read_config<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => {
+            toml::from_str(&content).with_context(|| format!("Invalid config {}", path.display()))
+        }
+        Some("json") => serde_json::from_str(&content)
+            .with_context(|| format!("Invalid config {}", path.display())),
+        _ => bail!("Unsupported config format: {}", path.display()),
+    }
+}
+
+impl BacktestConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let config: BacktestConfig = read_config(path.as_ref())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (ind, run) in self.runs.iter().enumerate() {
+            if self.runs[..ind].iter().any(|r| r.name == run.name) {
+                bail!("Duplicate run name: {}", run.name);
+            }
+            run.strategy
+                .validate()
+                .with_context(|| format!("Invalid run {}", run.name))?;
+        }
+        Ok(())
+    }
+}
+
+impl DataConfig {
+    pub fn source(&self) -> Box<dyn CandleSource> {
+        match self.format {
+            Some(DataFormat::BinanceJson) => Box::new(BinanceJsonSource::new(&self.path)),
+            Some(DataFormat::NyCsv) => Box::new(NyCsvSource::new(&self.path)),
+            None => data::from_path(&self.path),
+        }
+    }
+
+    pub fn load(&self) -> Result<Vec<Candle>> {
+        Ok(self.resample(self.source().load()?))
+    }
+
+    // applies the timeframe to candles loaded from `source`
+    pub fn resample(&self, candles: Vec<Candle>) -> Vec<Candle> {
+        let (Some(timeframe), Some(interval)) = (self.timeframe, infer_interval(&candles)) else {
+            return candles;
+        };
+        resample(candles, interval, timeframe)
+            .into_iter()
+            .filter(|bar| bar.complete || !self.complete_only)
+            .map(|bar| bar.candle)
+            .collect()
+    }
+}
+
+// Lower timeframe series loaded on first use and shared by every model built with them, a sweep
+// or a walk forward builds one model per parameter set and window.
+#[derive(Default)]
+pub struct LoadedData {
+    series: Mutex<Vec<(DataConfig, Arc<Vec<Candle>>)>>,
+}
+
+impl LoadedData {
+    pub fn get(&self, data: &DataConfig) -> Result<Arc<Vec<Candle>>> {
+        // held while loading, threads asking for the same file wait instead of loading it again
+        let mut series = self.series.lock().unwrap();
+        if let Some((_, candles)) = series.iter().find(|(d, _)| d == data) {
+            return Ok(candles.clone());
+        }
+        let candles = Arc::new(data.load()?);
+        series.push((data.clone(), candles.clone()));
+        Ok(candles)
+    }
+}
+
+impl AmbiguityConfig {
+    pub fn policy(&self, loaded: &LoadedData) -> Result<AmbiguityPolicy> {
+        Ok(match self {
+            AmbiguityConfig::Pessimistic => AmbiguityPolicy::Pessimistic,
+            AmbiguityConfig::Optimistic => AmbiguityPolicy::Optimistic,
+            AmbiguityConfig::OpenProximity => AmbiguityPolicy::OpenProximity,
+            AmbiguityConfig::LowerTimeframe { data } => {
+                AmbiguityPolicy::LowerTimeframe(loaded.get(data)?)
+            }
+        })
+    }
+}
+
+impl StrategyConfig {
+    pub fn validate(&self) -> Result<()> {
+        let (management, costs) = match self {
+            StrategyConfig::MacroSoup {
+                management, costs, ..
+            }
+            | StrategyConfig::Sfp {
+                management, costs, ..
+            }
+            | StrategyConfig::SfpLtf {
+                management, costs, ..
+            }
+            | StrategyConfig::Mayne {
+                management, costs, ..
+            } => (management, costs),
+        };
+        management.validate()?;
+        costs.validate()?;
+        if let StrategyConfig::Sfp { swings, .. } | StrategyConfig::SfpLtf { swings, .. } = self {
+            swings.validate()?;
+        }
+        if let StrategyConfig::Mayne {
+            direction,
+            trigger_level,
+            invalidation_level,
+            tp,
+            ..
+        } = self
+        {
+            let ordered = match direction {
+                PositionDirection::Long => invalidation_level < trigger_level && trigger_level < tp,
+                PositionDirection::Short => {
+                    invalidation_level > trigger_level && trigger_level > tp
+                }
+            };
+            if !ordered {
+                bail!("the trigger level must be between the invalidation level and tp");
+            }
+        }
+        if let StrategyConfig::MacroSoup {
+            session,
+            max_duration_min,
+            be_threshold,
+            management,
+            ..
+        } = self
+        {
+            if session.start >= session.end {
+                bail!("session start must be before session end");
+            }
+            if *max_duration_min <= 0 {
+                bail!("max_duration_min must be positive");
+            }
+            if be_threshold.is_some() && management.break_even_r.is_some() {
+                bail!("be_threshold and management.break_even_r both set the break even, keep one");
+            }
+        }
+        Ok(())
+    }
+
+    // fails when lower timeframe data can't be loaded or doesn't line up with the candles
+    pub fn build(
+        &self,
+        candles: Vec<Candle>,
+        loaded: &LoadedData,
+    ) -> Result<Box<dyn TradingModel>> {
+        Ok(match self {
+            StrategyConfig::MacroSoup {
+                rr_threshold,
+                session,
+                max_duration_min,
+                be_threshold,
+                management,
+                costs,
+                ambiguity,
+            } => Box::new(OnCandles {
+                candles,
+                strategy: MacroSoup {
+                    rr_threshold: *rr_threshold,
+                    session: Session {
+                        start: session.start,
+                        end: session.end,
+                    },
+                    max_duration_min: *max_duration_min,
+                    be_threshold: be_threshold.map(DecimalVec),
+                    management: management.clone(),
+                    costs: *costs,
+                    ambiguity: ambiguity.policy(loaded)?,
+                },
+            }),
+            StrategyConfig::Sfp {
+                rr_treshold,
+                swings,
+                management,
+                costs,
+                ambiguity,
+            } => Box::new(OnCandles {
+                candles,
+                strategy: Sfp {
+                    rr_treshold: *rr_treshold,
+                    swings: *swings,
+                    management: management.clone(),
+                    costs: *costs,
+                    ambiguity: ambiguity.policy(loaded)?,
+                },
+            }),
+            StrategyConfig::SfpLtf {
+                rr_treshold,
+                ltf,
+                swings,
+                management,
+                costs,
+                ambiguity,
+            } => Box::new(SfpLtf {
+                rr_treshold: *rr_treshold,
+                mtf: Mtf::new(candles, loaded.get(ltf)?)?,
+                swings: *swings,
+                management: management.clone(),
+                costs: *costs,
+                ambiguity: ambiguity.policy(loaded)?,
+            }),
+            StrategyConfig::Mayne {
+                direction,
+                trigger_type,
+                trigger_level,
+                invalidation_level,
+                tp,
+                rr_threshold,
+                management,
+                costs,
+                ambiguity,
+            } => Box::new(OnCandles {
+                candles,
+                strategy: Mayne {
+                    direction: *direction,
+                    trigger_type: *trigger_type,
+                    trigger_level: DecimalVec(*trigger_level),
+                    invalidation_level: DecimalVec(*invalidation_level),
+                    tp: DecimalVec(*tp),
+                    rr_threshold: *rr_threshold,
+                    management: management.clone(),
+                    costs: *costs,
+                    ambiguity: ambiguity.policy(loaded)?,
+                },
+            }),
+        })
+    }
+}
+
+impl PortfolioConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let config: PortfolioConfig = read_config(path.as_ref())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.legs.is_empty() {
+            bail!("A portfolio needs at least one leg");
+        }
+        for (ind, leg) in self.legs.iter().enumerate() {
+            if self.legs[..ind].iter().any(|l| l.name == leg.name) {
+                bail!("Duplicate leg name: {}", leg.name);
+            }
+            leg.strategy
+                .validate()
+                .with_context(|| format!("Invalid leg {}", leg.name))?;
+        }
+        Ok(())
+    }
+
+    pub fn portfolio(&self) -> Portfolio {
+        Portfolio {
+            starting_balance: self.starting_balance,
+            risk_fraction: self.risk_fraction,
+            max_positions: self.max_positions,
+            max_total_risk: self.max_total_risk,
+        }
+    }
+
+    // loads each data file once, legs often share one
+    pub fn legs(&self) -> Result<Vec<Leg>> {
+        let mut candles: HashMap<&Path, Vec<Candle>> = HashMap::new();
+        let loaded = LoadedData::default();
+        let mut legs = vec![];
+        for leg in &self.legs {
+            let path = leg.data.path.as_path();
+            if !candles.contains_key(path) {
+                candles.insert(path, leg.data.source().load()?);
+            }
+            let data = leg.data.resample(candles[path].clone());
+            if data.is_empty() {
+                bail!("{} contains no candles", path.display());
+            }
+            legs.push(Leg {
+                name: leg.name.clone(),
+                symbol: leg.symbol.clone(),
+                model: leg.strategy.build(data, &loaded)?,
+            });
+        }
+        Ok(legs)
+    }
+}
+
+impl SweepConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let config: SweepConfig = read_config(path.as_ref())?;
+        config.template().config(&ParamSet::new())?;
+        Ok(config)
+    }
+
+    pub fn template(&self) -> StrategyTemplate {
+        StrategyTemplate::new(self.strategy.clone())
+    }
+}
+
+// a strategy config with holes: parameters overwrite the fields at their dotted path
+pub struct StrategyTemplate {
+    pub base: Value,
+    // the lower timeframe data of every model built from it
+    loaded: LoadedData,
+}
+
+impl StrategyTemplate {
+    pub fn new(base: Value) -> Self {
+        StrategyTemplate {
+            base,
+            loaded: LoadedData::default(),
+        }
+    }
+
+    pub fn config(&self, params: &ParamSet) -> Result<StrategyConfig> {
+        let mut config = self.base.clone();
+        for (name, value) in params {
+            let mut field = &mut config;
+            for key in name.split('.') {
+                field = field
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow!("{} is not a table", name))?
+                    .entry(key)
+                    .or_insert(Value::Null);
+            }
+            *field = value.clone();
+        }
+        let config: StrategyConfig = serde_json::from_value(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl ModelFactory for StrategyTemplate {
+    fn build(&self, params: &ParamSet, candles: Vec<Candle>) -> Result<Box<dyn TradingModel>> {
+        self.config(params)?.build(candles, &self.loaded)
+    }
+}
+
+impl RunConfig {
+    pub fn execute(&self, candles: Vec<Candle>, loaded: &LoadedData) -> Result<BacktestResult> {
+        if candles.is_empty() {
+            return Err(anyhow!("{} contains no candles", self.data.path.display()));
+        }
+        Ok(self.strategy.build(candles, loaded)?.execute())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::cost_model::Slippage;
+    use crate::model::trade_management::{TakeProfit, Trailing};
+    use crate::strategies::lib::SwingPruning;
+
+    const TOML: &str = r#"
+        output_dir = "results"
+
+        [[runs]]
+        name = "ndx-0950"
+        data = { path = "assets/NDX_full_1min.txt" }
+
+        [runs.strategy]
+        type = "macro-soup"
+        rr_threshold = 3
+        be_threshold = 1.5
+        max_duration_min = 30
+        session = { start = "09:50", end = "10:10" }
+
+        [[runs]]
+        name = "eth-sfp"
+        data = { path = "assets/eth15.json", format = "binance-json" }
+        strategy = { type = "sfp", rr_treshold = 2, ambiguity = { type = "open-proximity" }, costs = { taker_fee_pct = 0.05, slippage = { type = "pct", pct = 0.01 } } }
+    "#;
+
+    #[test]
+    fn test_parse_toml() {
+        let config: BacktestConfig = toml::from_str(TOML).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.output_dir, PathBuf::from("results"));
+        assert_eq!(config.runs.len(), 2);
+        assert_eq!(config.runs[0].data.format, None);
+        assert_eq!(
+            config.runs[0].strategy,
+            StrategyConfig::MacroSoup {
+                rr_threshold: Decimal::from(3),
+                session: SessionConfig {
+                    start: NaiveTime::from_hms_opt(9, 50, 0).unwrap(),
+                    end: NaiveTime::from_hms_opt(10, 10, 0).unwrap(),
+                },
+                max_duration_min: 30,
+                be_threshold: Some("1.5".parse().unwrap()),
+                management: TradeManagement::default(),
+                costs: CostModel::default(),
+                ambiguity: AmbiguityConfig::Pessimistic,
+            }
+        );
+        assert_eq!(config.runs[1].data.format, Some(DataFormat::BinanceJson));
+        assert_eq!(
+            config.runs[1].strategy,
+            StrategyConfig::Sfp {
+                rr_treshold: Decimal::from(2),
+                swings: SwingConfig::default(),
+                management: TradeManagement::default(),
+                costs: CostModel {
+                    taker_fee_pct: "0.05".parse().unwrap(),
+                    slippage: Some(Slippage::Pct {
+                        pct: "0.01".parse().unwrap()
+                    }),
+                    ..CostModel::default()
+                },
+                ambiguity: AmbiguityConfig::OpenProximity,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_json_without_be_threshold() {
+        let config: BacktestConfig = serde_json::from_str(
+            r#"{
+                "output_dir": "results",
+                "runs": [{
+                    "name": "ndx",
+                    "data": { "path": "ndx.txt", "format": "ny-csv" },
+                    "strategy": {
+                        "type": "macro-soup",
+                        "rr_threshold": 2,
+                        "max_duration_min": 15,
+                        "session": { "start": "08:50", "end": "09:10" }
+                    }
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        match &config.runs[0].strategy {
+            StrategyConfig::MacroSoup { be_threshold, .. } => assert_eq!(*be_threshold, None),
+            other => panic!("unexpected strategy: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resampled_data() {
+        let data: DataConfig =
+            toml::from_str("path = \"assets/eth15.json\"\ntimeframe = \"1h\"").unwrap();
+        assert_eq!(
+            data.timeframe,
+            Some(Timeframe::Fixed(chrono::Duration::hours(1)))
+        );
+
+        let candles = data.source().load().unwrap();
+        let hours = data.load().unwrap();
+        assert!(hours.len() >= candles.len() / 4);
+        assert!(hours.len() <= candles.len() / 4 + 2);
+        assert!(hours
+            .windows(2)
+            .all(|w| w[1].open_time - w[0].open_time == chrono::Duration::hours(1)));
+
+        let complete = DataConfig {
+            complete_only: true,
+            ..data
+        };
+        assert!(complete.load().unwrap().len() <= hours.len());
+        assert!(toml::from_str::<DataConfig>("path = \"x\"\ntimeframe = \"7x\"").is_err());
+    }
+
+    #[test]
+    fn test_loaded_data_is_shared() {
+        let loaded = LoadedData::default();
+        let data: DataConfig = toml::from_str("path = \"assets/eth15.json\"").unwrap();
+        let first = loaded.get(&data).unwrap();
+        assert!(Arc::ptr_eq(&first, &loaded.get(&data.clone()).unwrap()));
+
+        let hours = DataConfig {
+            timeframe: Some(Timeframe::Fixed(chrono::Duration::hours(1))),
+            ..data
+        };
+        assert!(!Arc::ptr_eq(&first, &loaded.get(&hours).unwrap()));
+        let missing: DataConfig = toml::from_str("path = \"assets/missing.json\"").unwrap();
+        assert!(loaded.get(&missing).is_err());
+    }
+
+    #[test]
+    fn test_parse_mayne() {
+        let strategy: StrategyConfig = toml::from_str(
+            r#"
+            type = "mayne"
+            direction = "short"
+            trigger_type = "wick"
+            trigger_level = 100
+            invalidation_level = 110
+            tp = 70
+            rr_threshold = 2
+            "#,
+        )
+        .unwrap();
+        strategy.validate().unwrap();
+        assert_eq!(
+            strategy,
+            StrategyConfig::Mayne {
+                direction: PositionDirection::Short,
+                trigger_type: TriggerType::Wick,
+                trigger_level: Decimal::from(100),
+                invalidation_level: Decimal::from(110),
+                tp: Decimal::from(70),
+                rr_threshold: Decimal::from(2),
+                management: TradeManagement::default(),
+                costs: CostModel::default(),
+                ambiguity: AmbiguityConfig::Pessimistic,
+            }
+        );
+
+        let above_trigger = StrategyConfig::Mayne {
+            direction: PositionDirection::Short,
+            trigger_type: TriggerType::Wick,
+            trigger_level: Decimal::from(100),
+            invalidation_level: Decimal::from(110),
+            tp: Decimal::from(110),
+            rr_threshold: Decimal::from(2),
+            management: TradeManagement::default(),
+            costs: CostModel::default(),
+            ambiguity: AmbiguityConfig::Pessimistic,
+        };
+        assert!(above_trigger.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_swings() {
+        let strategy: StrategyConfig = toml::from_str(
+            r#"
+            type = "sfp"
+            rr_treshold = 2
+            swings = { left = 2, right = 3, equal_tolerance = 0.5, pruning = "broken" }
+            "#,
+        )
+        .unwrap();
+        strategy.validate().unwrap();
+
+        let StrategyConfig::Sfp { swings, .. } = strategy else {
+            panic!("unexpected strategy: {:?}", strategy);
+        };
+        assert_eq!(
+            swings,
+            SwingConfig {
+                left: 2,
+                right: 3,
+                equal_tolerance: Some("0.5".parse().unwrap()),
+                pruning: SwingPruning::Broken,
+            }
+        );
+
+        let no_right: StrategyConfig = toml::from_str(
+            r#"
+            type = "sfp"
+            rr_treshold = 2
+            swings = { right = 0 }
+            "#,
+        )
+        .unwrap();
+        assert!(no_right.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_management() {
+        let strategy: StrategyConfig = toml::from_str(
+            r#"
+            type = "sfp"
+            rr_treshold = 2
+
+            [management]
+            break_even_r = 1
+            trailing = { type = "atr", period = 14, multiple = 2.5 }
+            take_profits = [{ r = 1, fraction = 0.5 }]
+            time_exit_min = 240
+            "#,
+        )
+        .unwrap();
+        strategy.validate().unwrap();
+
+        let StrategyConfig::Sfp { management, .. } = strategy else {
+            panic!("unexpected strategy: {:?}", strategy);
+        };
+        assert_eq!(
+            management,
+            TradeManagement {
+                break_even_r: Some(Decimal::ONE),
+                trailing: Some(Trailing::Atr {
+                    period: 14,
+                    multiple: "2.5".parse().unwrap(),
+                }),
+                take_profits: vec![TakeProfit {
+                    r: Decimal::ONE,
+                    fraction: "0.5".parse().unwrap(),
+                }],
+                time_exit_min: Some(240),
+            }
+        );
+
+        let overbooked = TradeManagement {
+            take_profits: vec![
+                TakeProfit {
+                    r: Decimal::ONE,
+                    fraction: "0.6".parse().unwrap(),
+                };
+                2
+            ],
+            ..TradeManagement::default()
+        };
+        assert!(overbooked.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_duplicate_run_names() {
+        let config: BacktestConfig = toml::from_str(&TOML.replace("eth-sfp", "ndx-0950")).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_portfolio() {
+        let config: PortfolioConfig = toml::from_str(
+            r#"
+            starting_balance = 10000
+            risk_fraction = 0.01
+            max_positions = 2
+
+            [[legs]]
+            name = "eth-sfp"
+            symbol = "ETHUSDT"
+            data = { path = "assets/eth15.json" }
+            strategy = { type = "sfp", rr_treshold = 2 }
+
+            [[legs]]
+            name = "eth-sfp-4h"
+            symbol = "ETHUSDT"
+            data = { path = "assets/eth15.json", timeframe = "4h" }
+            strategy = { type = "sfp", rr_treshold = 3 }
+        "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.max_positions, Some(2));
+        assert_eq!(config.max_total_risk, None);
+        let legs = config.legs().unwrap();
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[1].symbol, "ETHUSDT");
+
+        let result = config.portfolio().run(&legs).unwrap();
+        assert_eq!(result.symbols.len(), 1);
+        assert_eq!(result.correlation.len(), 2);
+    }
+
+    #[test]
+    fn test_template_overrides_nested_params() {
+        let template = StrategyTemplate::new(serde_json::json!({
+            "type": "macro-soup",
+            "rr_threshold": 3,
+            "max_duration_min": 30,
+            "session": { "start": "09:50", "end": "10:10" },
+        }));
+        let params: ParamSet = serde_json::from_value(serde_json::json!({
+            "session.start": "09:30",
+            "be_threshold": "1.5",
+            "max_duration_min": 15,
+        }))
+        .unwrap();
+
+        match template.config(&params).unwrap() {
+            StrategyConfig::MacroSoup {
+                session,
+                be_threshold,
+                max_duration_min,
+                ..
+            } => {
+                assert_eq!(session.start, NaiveTime::from_hms_opt(9, 30, 0).unwrap());
+                assert_eq!(be_threshold, Some("1.5".parse().unwrap()));
+                assert_eq!(max_duration_min, 15);
+            }
+            other => panic!("unexpected strategy: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_sweep_toml() {
+        let config: SweepConfig = toml::from_str(
+            r#"
+            data = { path = "assets/eth15.json" }
+            metric = "drawdown-adjusted"
+            output = "results/sweep.csv"
+            strategy = { type = "sfp", rr_treshold = 2 }
+
+            [params]
+            rr_treshold = { start = 1, end = 3, step = 0.5 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.metric, Metric::DrawdownAdjusted);
+        assert_eq!(crate::optimizer::grid(&config.params).unwrap().len(), 5);
+        assert!(config.template().config(&ParamSet::new()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_session_order() {
+        let config: BacktestConfig = toml::from_str(&TOML.replace("10:10", "09:40")).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_be_threshold_with_break_even() {
+        let config: BacktestConfig = toml::from_str(&TOML.replace(
+            "session = { start = \"09:50\", end = \"10:10\" }",
+            "session = { start = \"09:50\", end = \"10:10\" }\n        management = { break_even_r = 1 }",
+        ))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::model::{binance_klines_item::BinanceKlinesItem, candle::Candle};
+
+use super::binance_json;
+use super::provider::MarketDataProvider;
+use super::rest::{FetchError, RateLimit, RestClient};
+
+// the binance `/klines` endpoint, spot and usd-m futures answer the same way
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binance {
+    // up to `/klines`
+    pub base_url: String,
+    pub rate_limit: RateLimit,
+}
+
+// a page of up to 1000 spot klines weighs 2
+const SPOT: RateLimit = RateLimit {
+    request_weight: 2,
+    per_minute: 6000,
+};
+
+// a page of more than 1000 usd-m klines weighs 10
+const USD_M: RateLimit = RateLimit {
+    request_weight: 10,
+    per_minute: 2400,
+};
+
+impl Binance {
+    // the usd-m limits for a `/fapi/` url, the spot ones otherwise
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        let rate_limit = if base_url.contains("/fapi/") {
+            USD_M
+        } else {
+            SPOT
+        };
+        Binance {
+            base_url,
+            rate_limit,
+        }
+    }
+
+    pub fn spot() -> Self {
+        Binance::new("https://api.binance.com/api/v1")
+    }
+
+    pub fn usd_m() -> Self {
+        Binance::new("https://fapi.binance.com/fapi/v1")
+    }
+
+    // one page of the raw klines, the way the loader stores them
+    pub async fn klines(
+        &self,
+        rest: &RestClient,
+        symbol: &str,
+        interval: &str,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> Result<Vec<BinanceKlinesItem>, FetchError> {
+        let url = self.klines_url(symbol, interval, start_time, end_time)?;
+        let body = rest.get(&url, self.rate_limit).await?;
+        serde_json::from_str(&body).map_err(|e| FetchError::Parse {
+            message: e.to_string(),
+        })
+    }
+}
+
+impl MarketDataProvider for Binance {
+    fn klines_url(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> Result<String, FetchError> {
+        let mut url = format!(
+            "{}/klines?symbol={}&interval={}&startTime={}&limit=1500",
+            self.base_url, symbol, interval, start_time
+        );
+        if let Some(end_time) = end_time {
+            url.push_str(&format!("&endTime={}", end_time));
+        }
+        Ok(url)
+    }
+
+    fn parse_klines(&self, body: &str, _interval: &str) -> Result<Vec<Candle>, FetchError> {
+        binance_json::parse_klines(body).map_err(|e| FetchError::Parse {
+            message: e.to_string(),
+        })
+    }
+
+    fn rate_limit(&self) -> RateLimit {
+        self.rate_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::decimal::DecimalVec;
+    use crate::to_new_york_time;
+
+    #[test]
+    fn test_klines_url() {
+        assert_eq!(
+            Binance::usd_m()
+                .klines_url("BTCUSDT", "1h", 1000, Some(2000))
+                .unwrap(),
+            "https://fapi.binance.com/fapi/v1/klines?symbol=BTCUSDT&interval=1h&startTime=1000&limit=1500&endTime=2000"
+        );
+        assert_eq!(
+            Binance::new("http://localhost:8080/api/v1/")
+                .klines_url("ETHUSDT", "15m", 1000, None)
+                .unwrap(),
+            "http://localhost:8080/api/v1/klines?symbol=ETHUSDT&interval=15m&startTime=1000&limit=1500"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_by_market() {
+        assert_eq!(Binance::spot().rate_limit, SPOT);
+        assert_eq!(Binance::usd_m().rate_limit, USD_M);
+        assert_eq!(
+            Binance::new("http://localhost:8080/fapi/v1").rate_limit,
+            USD_M
+        );
+    }
+
+    #[test]
+    fn test_parse_spot_fixture() {
+        let candles = Binance::spot()
+            .parse_klines(
+                include_str!("../../assets/fixtures/binance_spot_klines.json"),
+                "1h",
+            )
+            .unwrap();
+
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[0].open_time, to_new_york_time(1704067200));
+        assert_eq!(candles[0].close, DecimalVec("42475.23".parse().unwrap()));
+        assert_eq!(candles[0].number_of_trades, 47134);
+        assert!(candles[0].close_time < candles[1].open_time);
+    }
+
+    #[test]
+    fn test_parse_usd_m_fixture() {
+        let candles = Binance::usd_m()
+            .parse_klines(
+                include_str!("../../assets/fixtures/binance_usdm_klines.json"),
+                "1h",
+            )
+            .unwrap();
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].high, DecimalVec("42800".parse().unwrap()));
+        assert_eq!(candles[1].volume, DecimalVec("10632.017".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_error_body() {
+        let result =
+            Binance::spot().parse_klines(r#"{"code":-1121,"msg":"Invalid symbol."}"#, "1h");
+        assert!(matches!(result, Err(FetchError::Parse { .. })));
+    }
+}
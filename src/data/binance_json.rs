@@ -0,0 +1,126 @@
+use chrono::DateTime;
+use chrono_tz::{America::New_York, Tz};
+use serde::de::{self, IgnoredAny};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::model::{candle::Candle, decimal::DecimalVec};
+
+use super::{CandleSource, LoadError};
+
+// kline json as written by the `loader` binary (the raw binance `/klines` response)
+pub struct BinanceJsonSource {
+    pub path: PathBuf,
+}
+
+impl BinanceJsonSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        BinanceJsonSource { path: path.into() }
+    }
+
+    pub fn parse(&self, content: &str) -> Result<Vec<Candle>, LoadError> {
+        parse_klines(content).map_err(|e| LoadError::Parse {
+            file: self.path.clone(),
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        })
+    }
+}
+
+impl CandleSource for BinanceJsonSource {
+    fn load(&self) -> Result<Vec<Candle>, LoadError> {
+        let content = fs::read_to_string(&self.path).map_err(|source| LoadError::Io {
+            file: self.path.clone(),
+            source,
+        })?;
+        self.parse(&content)
+    }
+}
+
+// a `/klines` response or a file of them
+pub(crate) fn parse_klines(content: &str) -> serde_json::Result<Vec<Candle>> {
+    let rows: Vec<KlineRow> = serde_json::from_str(content)?;
+    Ok(rows.into_iter().map(Candle::from).collect())
+}
+
+// typed mirror of `BinanceKlinesItem`, so a bad value fails while serde_json still knows its position
+#[derive(Deserialize)]
+struct KlineRow(
+    #[serde(deserialize_with = "millis")] DateTime<Tz>,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    #[serde(deserialize_with = "millis")] DateTime<Tz>,
+    IgnoredAny,
+    u64,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+);
+
+impl From<KlineRow> for Candle {
+    fn from(row: KlineRow) -> Self {
+        Candle {
+            open_time: row.0,
+            open: row.1,
+            high: row.2,
+            low: row.3,
+            close: row.4,
+            volume: row.5,
+            close_time: row.6,
+            number_of_trades: row.8,
+        }
+    }
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Tz>, D::Error> {
+    let millis = i64::deserialize(deserializer)?;
+    DateTime::from_timestamp_millis(millis)
+        .map(|t| t.with_timezone(&New_York))
+        .ok_or_else(|| de::Error::custom(format!("invalid timestamp: {}", millis)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> BinanceJsonSource {
+        BinanceJsonSource::new("klines.json")
+    }
+
+    #[test]
+    fn test_parse_klines() {
+        let candles = source()
+            .parse(include_str!("../../assets/eth15.json"))
+            .unwrap();
+        assert_eq!(candles.len(), 129);
+        assert_eq!(candles[0].open, DecimalVec("3061.15".parse().unwrap()));
+        assert_eq!(candles[0].number_of_trades, 9274);
+        assert!(candles[0].close_time < candles[1].open_time);
+    }
+
+    #[test]
+    fn test_parse_bad_price_reports_position() {
+        let content = "[\n  [1713591000000, \"3061.15\", \"oops\", \"3059.01\", \"3068.54\", \"2524.15\",\n   1713591899999, \"7737222.04\", 9274, \"1095.87\", \"3359166.49\", \"0\"]\n]";
+        match source().parse(content) {
+            Err(LoadError::Parse {
+                file, line, column, ..
+            }) => {
+                assert_eq!(file, PathBuf::from("klines.json"));
+                assert_eq!(line, 2);
+                assert_eq!(column, 35);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|c| c.len())),
+        }
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let result = BinanceJsonSource::new("does/not/exist.json").load();
+        assert!(matches!(result, Err(LoadError::Io { .. })));
+    }
+}
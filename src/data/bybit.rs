@@ -0,0 +1,212 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::America::New_York;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::model::{candle::Candle, decimal::DecimalVec};
+
+use super::provider::{interval_millis, MarketDataProvider};
+use super::rest::{FetchError, RateLimit};
+
+// most klines bybit sends per request
+const LIMIT: u64 = 1000;
+
+// the bybit v5 `/market/kline` endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bybit {
+    pub base_url: String,
+    pub category: BybitCategory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BybitCategory {
+    Spot,
+    // usdt and usdc perpetuals and futures
+    Linear,
+    Inverse,
+}
+
+impl Bybit {
+    pub fn new(category: BybitCategory) -> Self {
+        Bybit {
+            base_url: "https://api.bybit.com".to_string(),
+            category,
+        }
+    }
+}
+
+impl MarketDataProvider for Bybit {
+    // bybit counts back from the end, so the page always gets one
+    fn klines_url(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> Result<String, FetchError> {
+        let (name, length) = bybit_interval(interval)?;
+        let page_end = start_time + LIMIT * length - 1;
+        let end = end_time.map_or(page_end, |end| end.min(page_end));
+        let category = match self.category {
+            BybitCategory::Spot => "spot",
+            BybitCategory::Linear => "linear",
+            BybitCategory::Inverse => "inverse",
+        };
+        Ok(format!(
+            "{}/v5/market/kline?category={}&symbol={}&interval={}&start={}&end={}&limit={}",
+            self.base_url, category, symbol, name, start_time, end, LIMIT
+        ))
+    }
+
+    fn parse_klines(&self, body: &str, interval: &str) -> Result<Vec<Candle>, FetchError> {
+        let (_, length) = bybit_interval(interval)?;
+        let parse = |message: String| FetchError::Parse { message };
+        let response: Response = serde_json::from_str(body).map_err(|e| parse(e.to_string()))?;
+        if response.ret_code != 0 {
+            return Err(FetchError::Rejected {
+                status: 200,
+                body: format!("{} {}", response.ret_code, response.ret_msg),
+            });
+        }
+
+        let mut candles = response
+            .result
+            .list
+            .into_iter()
+            .map(|row| {
+                let open_time = row
+                    .0
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_millis)
+                    .ok_or_else(|| parse(format!("invalid start time: {}", row.0)))?
+                    .with_timezone(&New_York);
+                Ok(Candle {
+                    open_time,
+                    // the last millisecond of the kline, like binance
+                    close_time: open_time + Duration::milliseconds(length as i64 - 1),
+                    open: row.1,
+                    high: row.2,
+                    low: row.3,
+                    close: row.4,
+                    volume: row.5,
+                    number_of_trades: 0,
+                })
+            })
+            .collect::<Result<Vec<_>, FetchError>>()?;
+        // newest first on the wire
+        candles.reverse();
+        Ok(candles)
+    }
+
+    // bybit allows 600 requests per 5 seconds and sends no weight, this stays well under it
+    fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            request_weight: 1,
+            per_minute: 600,
+        }
+    }
+}
+
+// the bybit name and the length of a binance interval
+fn bybit_interval(interval: &str) -> Result<(&'static str, u64), FetchError> {
+    let name = match interval {
+        "1m" => "1",
+        "3m" => "3",
+        "5m" => "5",
+        "15m" => "15",
+        "30m" => "30",
+        "1h" => "60",
+        "2h" => "120",
+        "4h" => "240",
+        "6h" => "360",
+        "12h" => "720",
+        "1d" => "D",
+        "1w" => "W",
+        _ => {
+            return Err(FetchError::Unsupported {
+                message: format!("bybit has no {} klines", interval),
+            })
+        }
+    };
+    let length = interval_millis(interval).expect("every bybit interval has a fixed length");
+    Ok((name, length))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    ret_code: i64,
+    ret_msg: String,
+    result: KlineList,
+}
+
+// empty on an error
+#[derive(Deserialize)]
+struct KlineList {
+    #[serde(default)]
+    list: Vec<KlineRow>,
+}
+
+// start, open, high, low, close, volume and turnover
+#[derive(Deserialize)]
+struct KlineRow(
+    String,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    DecimalVec,
+    IgnoredAny,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_new_york_time;
+
+    #[test]
+    fn test_klines_url_covers_one_page() {
+        let bybit = Bybit::new(BybitCategory::Linear);
+        assert_eq!(
+            bybit.klines_url("BTCUSDT", "1h", 1704067200000, None).unwrap(),
+            "https://api.bybit.com/v5/market/kline?category=linear&symbol=BTCUSDT&interval=60&start=1704067200000&end=1707667199999&limit=1000"
+        );
+        assert!(bybit
+            .klines_url("BTCUSDT", "1h", 1704067200000, Some(1704070000000))
+            .unwrap()
+            .contains("&end=1704070000000&"));
+        assert!(matches!(
+            bybit.klines_url("BTCUSDT", "8h", 0, None),
+            Err(FetchError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_fixture_oldest_first() {
+        let candles = Bybit::new(BybitCategory::Linear)
+            .parse_klines(include_str!("../../assets/fixtures/bybit_kline.json"), "1h")
+            .unwrap();
+
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[0].open_time, to_new_york_time(1704067200));
+        assert_eq!(candles[0].open, DecimalVec("42308.4".parse().unwrap()));
+        assert_eq!(candles[2].close, DecimalVec("42598.7".parse().unwrap()));
+        assert_eq!(
+            candles[0].close_time + Duration::milliseconds(1),
+            candles[1].open_time
+        );
+    }
+
+    #[test]
+    fn test_parse_error_fixture() {
+        let result = Bybit::new(BybitCategory::Spot)
+            .parse_klines(include_str!("../../assets/fixtures/bybit_error.json"), "1h");
+        match result {
+            Err(FetchError::Rejected { body, .. }) => {
+                assert_eq!(body, "10001 Not supported symbols")
+            }
+            _ => panic!("expected a rejection"),
+        }
+    }
+}
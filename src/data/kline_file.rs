@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::model::binance_klines_item::BinanceKlinesItem;
+
+use super::LoadError;
+
+// the raw klines the `loader` binary keeps, see `BinanceJsonSource` for reading them as candles
+pub struct KlineFile {
+    pub path: PathBuf,
+}
+
+impl KlineFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        KlineFile { path: path.into() }
+    }
+
+    pub fn read(&self) -> Result<Vec<BinanceKlinesItem>, LoadError> {
+        let content = fs::read_to_string(&self.path).map_err(|source| LoadError::Io {
+            file: self.path.clone(),
+            source,
+        })?;
+        serde_json::from_str(&content).map_err(|e| LoadError::Parse {
+            file: self.path.clone(),
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        })
+    }
+
+    // writes next to the file and renames it over, a reader never sees half a file
+    pub fn write(&self, klines: &[BinanceKlinesItem]) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(klines)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+// sorted by open time, a fetched kline replaces a stored one with the same open time
+pub fn merge(
+    stored: Vec<BinanceKlinesItem>,
+    fetched: Vec<BinanceKlinesItem>,
+) -> Vec<BinanceKlinesItem> {
+    let mut klines: Vec<BinanceKlinesItem> = fetched.into_iter().chain(stored).collect();
+    // the sort is stable, the fetched copy stays first and dedup keeps it
+    klines.sort_by_key(|k| k.open_time);
+    klines.dedup_by_key(|k| k.open_time);
+    klines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{BinanceJsonSource, CandleSource};
+
+    fn kline(open_time: u64, close: &str) -> BinanceKlinesItem {
+        serde_json::from_value(serde_json::json!([
+            open_time,
+            "1",
+            "2",
+            "0.5",
+            close,
+            "10",
+            open_time + 59_999,
+            "10",
+            3,
+            "5",
+            "5",
+            "0"
+        ]))
+        .unwrap()
+    }
+
+    fn open_times(klines: &[BinanceKlinesItem]) -> Vec<u64> {
+        klines.iter().map(|k| k.open_time).collect()
+    }
+
+    #[test]
+    fn test_merge_drops_duplicates() {
+        let stored = vec![kline(0, "1"), kline(60_000, "1"), kline(120_000, "1")];
+        let fetched = vec![kline(120_000, "2"), kline(180_000, "2")];
+        let klines = merge(stored, fetched);
+
+        assert_eq!(open_times(&klines), vec![0, 60_000, 120_000, 180_000]);
+        assert_eq!(klines[2].close, "2");
+    }
+
+    #[test]
+    fn test_written_klines_load_as_candles() {
+        let path = std::env::temp_dir().join(format!("kline_file_{}.json", std::process::id()));
+        let file = KlineFile::new(&path);
+        file.write(&[kline(1713591000000, "1.5"), kline(1713591060000, "2")])
+            .unwrap();
+
+        assert_eq!(
+            open_times(&file.read().unwrap()),
+            vec![1713591000000, 1713591060000]
+        );
+        let candles = BinanceJsonSource::new(&path).load().unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].number_of_trades, 3);
+        fs::remove_file(&path).unwrap();
+    }
+}
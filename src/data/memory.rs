@@ -0,0 +1,19 @@
+use crate::model::candle::Candle;
+
+use super::{CandleSource, LoadError};
+
+pub struct MemorySource {
+    pub candles: Vec<Candle>,
+}
+
+impl MemorySource {
+    pub fn new(candles: Vec<Candle>) -> Self {
+        MemorySource { candles }
+    }
+}
+
+impl CandleSource for MemorySource {
+    fn load(&self) -> Result<Vec<Candle>, LoadError> {
+        Ok(self.candles.clone())
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::model::candle::Candle;
+
+pub mod binance_api;
+pub mod binance_json;
+pub mod bybit;
+pub mod kline_file;
+pub mod memory;
+pub mod ny_csv;
+pub mod provider;
+pub mod rest;
+
+pub use binance_json::BinanceJsonSource;
+pub use kline_file::KlineFile;
+pub use memory::MemorySource;
+pub use ny_csv::NyCsvSource;
+pub use provider::MarketDataProvider;
+
+pub trait CandleSource {
+    fn load(&self) -> Result<Vec<Candle>, LoadError>;
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        file: PathBuf,
+        source: io::Error,
+    },
+    // line and column are 1-based, the way editors show them
+    Parse {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { file, source } => write!(f, "{}: {}", file.display(), source),
+            LoadError::Parse {
+                file,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", file.display(), line, column, message),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Parse { .. } => None,
+        }
+    }
+}
+
+// picks the source by extension: `.json` is binance klines, everything else the NY csv
+pub fn from_path(path: impl AsRef<Path>) -> Box<dyn CandleSource> {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Box::new(BinanceJsonSource::new(path)),
+        _ => Box::new(NyCsvSource::new(path)),
+    }
+}
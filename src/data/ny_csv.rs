@@ -0,0 +1,150 @@
+use chrono::Duration;
+use rust_decimal::Decimal;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+
+use crate::model::{candle::Candle, candle_ny::CandleNY, decimal::DecimalVec};
+use crate::parse_datetime;
+
+use super::{CandleSource, LoadError};
+
+// `datetime,open,high,low,close` rows, datetime in New York time (`%Y-%m-%d %H:%M:%S`)
+pub struct NyCsvSource {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl NyCsvSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        NyCsvSource {
+            path: path.into(),
+            interval: Duration::minutes(1),
+        }
+    }
+
+    pub fn parse<R: BufRead>(&self, reader: R) -> Result<Vec<Candle>, LoadError> {
+        let mut candles = Vec::new();
+
+        for (ind, line) in reader.lines().enumerate() {
+            let line = line.map_err(|source| self.io_error(source))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = self.parse_row(ind + 1, &line)?;
+            candles.push(row.to_candle(self.interval));
+        }
+
+        Ok(candles)
+    }
+
+    fn parse_row(&self, line_number: usize, line: &str) -> Result<CandleNY, LoadError> {
+        // (column, field) pairs, the column is where the field starts in the line
+        let mut fields = vec![];
+        let mut column = 1;
+        for field in line.split(',') {
+            fields.push((column, field.trim()));
+            column += field.chars().count() + 1;
+        }
+
+        if fields.len() != 5 {
+            return Err(self.parse_error(
+                line_number,
+                1,
+                format!("expected 5 columns, found {}", fields.len()),
+            ));
+        }
+
+        let decimal = |(column, field): (usize, &str)| {
+            field
+                .parse::<Decimal>()
+                .map(DecimalVec)
+                .map_err(|e| self.parse_error(line_number, column, format!("{}: {}", field, e)))
+        };
+
+        Ok(CandleNY {
+            open_time: parse_datetime(fields[0].1)
+                .map_err(|e| self.parse_error(line_number, fields[0].0, e.to_string()))?,
+            open: decimal(fields[1])?,
+            high: decimal(fields[2])?,
+            low: decimal(fields[3])?,
+            close: decimal(fields[4])?,
+        })
+    }
+
+    fn io_error(&self, source: io::Error) -> LoadError {
+        LoadError::Io {
+            file: self.path.clone(),
+            source,
+        }
+    }
+
+    fn parse_error(&self, line: usize, column: usize, message: String) -> LoadError {
+        LoadError::Parse {
+            file: self.path.clone(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl CandleSource for NyCsvSource {
+    fn load(&self) -> Result<Vec<Candle>, LoadError> {
+        let file = File::open(&self.path).map_err(|source| self.io_error(source))?;
+        self.parse(io::BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Vec<Candle>, LoadError> {
+        NyCsvSource::new("ndx.txt").parse(content.as_bytes())
+    }
+
+    fn error_position(result: Result<Vec<Candle>, LoadError>) -> (usize, usize) {
+        match result {
+            Err(LoadError::Parse { line, column, .. }) => (line, column),
+            other => panic!("unexpected result: {:?}", other.map(|c| c.len())),
+        }
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let candles = parse(
+            "2022-09-30 09:30:00,11000.5,11010,10990.25,11005\n\
+             2022-09-30 09:31:00,11005,11020,11000,11018.75\n",
+        )
+        .unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            candles[0].open_time,
+            parse_datetime("2022-09-30 09:30:00").unwrap()
+        );
+        assert_eq!(candles[0].close_time, candles[1].open_time);
+        assert_eq!(candles[1].close, DecimalVec("11018.75".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_bad_decimal_reports_column() {
+        let result = parse(
+            "2022-09-30 09:30:00,11000.5,11010,10990.25,11005\n\
+             2022-09-30 09:31:00,11005,11020,x,11018.75\n",
+        );
+        assert_eq!(error_position(result), (2, 33));
+    }
+
+    #[test]
+    fn test_parse_bad_datetime_reports_column() {
+        let result = parse("2022-09-30T09:30,11000.5,11010,10990.25,11005\n");
+        assert_eq!(error_position(result), (1, 1));
+    }
+
+    #[test]
+    fn test_parse_wrong_column_count() {
+        let result = parse("2022-09-30 09:30:00,11000.5,11010,10990.25\n");
+        assert_eq!(error_position(result), (1, 1));
+    }
+}
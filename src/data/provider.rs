@@ -0,0 +1,84 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::model::candle::Candle;
+
+use super::rest::{FetchError, RateLimit};
+
+// An exchange's kline endpoint. Intervals are named the binance way (`1m`, `4h`, `1d`) and times
+// are milliseconds since the epoch, `RestClient::candles` sends the request.
+pub trait MarketDataProvider {
+    // one page of klines opening from `start_time`, up to `end_time` when given
+    fn klines_url(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> Result<String, FetchError>;
+
+    // the candles of a response, oldest first
+    fn parse_klines(&self, body: &str, interval: &str) -> Result<Vec<Candle>, FetchError>;
+
+    // what one `klines_url` request weighs against the exchange's limit
+    fn rate_limit(&self) -> RateLimit;
+}
+
+// the length of a binance interval, None for months and names binance doesn't have
+pub(crate) fn interval_millis(interval: &str) -> Option<u64> {
+    let (amount, unit) = interval.split_at(interval.len().saturating_sub(1));
+    let unit = match unit {
+        "m" => 60_000,
+        "h" => 60 * 60_000,
+        "d" => 24 * 60 * 60_000,
+        "w" => 7 * 24 * 60 * 60_000,
+        _ => return None,
+    };
+    Some(amount.parse::<u64>().ok().filter(|a| *a > 0)? * unit)
+}
+
+// Epoch milliseconds, a date like `2024-01-01` or a time like `2024-01-01T09:30-04:00`. Dates and
+// times without an offset are utc, the way the exchanges count.
+pub fn parse_millis(s: &str) -> Result<u64, String> {
+    if let Ok(millis) = s.parse::<u64>() {
+        return Ok(millis);
+    }
+    let time = DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(&s.replace('Z', "+00:00"), "%Y-%m-%dT%H:%M%:z"))
+        .map(|t| t.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .map_err(|_| {
+            format!(
+                "expected epoch milliseconds, 2024-01-01 or 2024-01-01T09:30-04:00, got {}",
+                s
+            )
+        })?;
+    u64::try_from(time.and_utc().timestamp_millis()).map_err(|_| format!("{} is before 1970", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_millis() {
+        assert_eq!(parse_millis("1713591000000"), Ok(1713591000000));
+        assert_eq!(parse_millis("2024-01-01"), Ok(1704067200000));
+        assert_eq!(parse_millis("2024-01-01T09:30-04:00"), Ok(1704115800000));
+        assert_eq!(parse_millis("2024-01-01T13:30:00Z"), Ok(1704115800000));
+        assert_eq!(parse_millis("2024-01-01T13:30Z"), Ok(1704115800000));
+        assert_eq!(parse_millis("2024-01-01T13:30"), Ok(1704115800000));
+        assert!(parse_millis("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_interval_millis() {
+        assert_eq!(interval_millis("15m"), Some(15 * 60_000));
+        assert_eq!(interval_millis("4h"), Some(4 * 60 * 60_000));
+        assert_eq!(interval_millis("1M"), None);
+        assert_eq!(interval_millis("0m"), None);
+    }
+}
@@ -0,0 +1,373 @@
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::model::candle::Candle;
+
+use super::provider::MarketDataProvider;
+
+// a second more for the difference between our clock and the exchange's
+const CLOCK_SKEW_MILLIS: u64 = 1000;
+
+// Gets for the exchange apis. Requests wait for the next minute when the weight the exchange
+// reports would go over the provider's limit, rate limits, server errors and dropped connections
+// are retried. Clones share the client and the weight, use one per exchange.
+#[derive(Clone)]
+pub struct RestClient {
+    client: Client,
+    // attempts after the first one before giving up
+    pub retries: u32,
+    // the wait before the first retry, doubled for every following one. A `Retry-After` wins.
+    pub backoff: Duration,
+    // the weight used in `minute`: the requests sent, or the last response's count when higher
+    used_weight: Arc<AtomicU32>,
+    // the clock minute since the epoch `used_weight` counts
+    minute: Arc<AtomicU64>,
+}
+
+// what a provider's kline request costs against the weight it allows per minute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub request_weight: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    // a status retrying won't fix, e.g. 400 for an unknown symbol
+    Rejected { status: u16, body: String },
+    // the response isn't a list of klines
+    Parse { message: String },
+    // an interval or market the exchange doesn't have
+    Unsupported { message: String },
+    // still failing after every retry
+    GaveUp { attempts: u32, last: String },
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Rejected { status, body } => {
+                write!(
+                    f,
+                    "the exchange rejected the request with {}: {}",
+                    status, body
+                )
+            }
+            FetchError::Parse { message } => write!(f, "unexpected klines response: {}", message),
+            FetchError::Unsupported { message } => write!(f, "{}", message),
+            FetchError::GaveUp { attempts, last } => {
+                write!(
+                    f,
+                    "gave up after {} attempts, the last one: {}",
+                    attempts, last
+                )
+            }
+        }
+    }
+}
+
+impl Error for FetchError {}
+
+impl RestClient {
+    pub fn new() -> reqwest::Result<Self> {
+        Ok(RestClient {
+            client: Client::builder().timeout(Duration::from_secs(10)).build()?,
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            used_weight: Arc::new(AtomicU32::new(0)),
+            minute: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    // one page of candles from `provider`, see `MarketDataProvider::klines_url`
+    pub async fn candles<P: MarketDataProvider + ?Sized>(
+        &self,
+        provider: &P,
+        symbol: &str,
+        interval: &str,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let url = provider.klines_url(symbol, interval, start_time, end_time)?;
+        let body = self.get(&url, provider.rate_limit()).await?;
+        provider.parse_klines(&body, interval)
+    }
+
+    // the body of a successful response
+    pub async fn get(&self, url: &str, limit: RateLimit) -> Result<String, FetchError> {
+        let mut attempt = 0;
+        loop {
+            while let Some(wait) = self.reserve(limit, now_millis()) {
+                tokio::time::sleep(wait).await;
+            }
+
+            let (retry_after, last) = match self.client.get(url).send().await {
+                Ok(response) => {
+                    if let Some(used) = header(&response, "x-mbx-used-weight-1m") {
+                        self.used_weight.fetch_max(used, Ordering::SeqCst);
+                    }
+                    let retry_after =
+                        header(&response, RETRY_AFTER.as_str()).map(Duration::from_secs);
+                    let status = response.status();
+                    match response.text().await {
+                        Ok(body) if status.is_success() => return Ok(body),
+                        Ok(body) if retryable(status) => {
+                            (retry_after, format!("{}: {}", status, body))
+                        }
+                        Ok(body) => {
+                            return Err(FetchError::Rejected {
+                                status: status.as_u16(),
+                                body,
+                            })
+                        }
+                        Err(e) => (None, e.to_string()),
+                    }
+                }
+                Err(e) => (None, e.to_string()),
+            };
+
+            if attempt == self.retries {
+                return Err(FetchError::GaveUp {
+                    attempts: attempt + 1,
+                    last,
+                });
+            }
+            tokio::time::sleep(retry_after.unwrap_or(self.backoff * 2u32.pow(attempt))).await;
+            attempt += 1;
+        }
+    }
+
+    // Counts a request against the minute of `now` or says how long to wait for the next one.
+    // Counted before it's sent, so concurrent downloads don't all take the last slot.
+    fn reserve(&self, limit: RateLimit, now: u64) -> Option<Duration> {
+        let minute = now.saturating_sub(CLOCK_SKEW_MILLIS) / 60_000;
+        let counted = self.minute.load(Ordering::SeqCst);
+        // only the first request of a new minute starts the count over
+        if minute > counted
+            && self
+                .minute
+                .compare_exchange(counted, minute, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            self.used_weight.store(0, Ordering::SeqCst);
+        }
+        let used = self
+            .used_weight
+            .fetch_add(limit.request_weight, Ordering::SeqCst);
+        weight_wait(used, limit, now)
+    }
+}
+
+// 429 is a rate limit, 418 a ban for ignoring one
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::IM_A_TEAPOT
+        || status.is_server_error()
+}
+
+fn header<T: FromStr>(response: &reqwest::Response, name: &str) -> Option<T> {
+    response.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// the exchanges count the weight per clock minute, a request that doesn't fit waits for the next
+fn weight_wait(used: u32, limit: RateLimit, now_millis: u64) -> Option<Duration> {
+    if used + limit.request_weight <= limit.per_minute {
+        return None;
+    }
+    let since = now_millis.saturating_sub(CLOCK_SKEW_MILLIS) % 60_000;
+    Some(Duration::from_millis(60_000 - since))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{RawQuery, State};
+    use axum::http::{HeaderMap, HeaderName, HeaderValue};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use std::collections::VecDeque;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    const BODY: &str = "[]";
+    const LIMIT: RateLimit = RateLimit {
+        request_weight: 2,
+        per_minute: 6000,
+    };
+
+    // status, headers and body
+    type Scripted = (u16, Vec<(&'static str, &'static str)>, &'static str);
+
+    // answers with the scripted responses in order, the last one over and over
+    #[derive(Default)]
+    struct Mock {
+        responses: Mutex<VecDeque<Scripted>>,
+        // the query strings
+        requests: Mutex<Vec<String>>,
+    }
+
+    async fn respond(State(mock): State<Arc<Mock>>, RawQuery(query): RawQuery) -> Response {
+        mock.requests
+            .lock()
+            .unwrap()
+            .push(query.unwrap_or_default());
+        let mut responses = mock.responses.lock().unwrap();
+        let (status, headers, body) = if responses.len() > 1 {
+            responses.pop_front().unwrap()
+        } else {
+            responses[0].clone()
+        };
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        (StatusCode::from_u16(status).unwrap(), map, body).into_response()
+    }
+
+    // the client and the url of the mock
+    fn serve(responses: Vec<Scripted>) -> (RestClient, String, Arc<Mock>) {
+        let mock = Arc::new(Mock {
+            responses: Mutex::new(responses.into()),
+            ..Mock::default()
+        });
+        let app = Router::new()
+            .route("/klines", get(respond))
+            .with_state(mock.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/klines?symbol=ETHUSDT",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let mut client = RestClient::new().unwrap();
+        client.backoff = Duration::from_millis(10);
+        (client, url, mock)
+    }
+
+    fn requests(mock: &Mock) -> usize {
+        mock.requests.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_honors_retry_after() {
+        let (client, url, mock) = serve(vec![
+            (429, vec![("retry-after", "1")], "too many requests"),
+            (200, vec![], BODY),
+        ]);
+        let start = Instant::now();
+
+        assert_eq!(client.get(&url, LIMIT).await.unwrap(), BODY);
+        assert_eq!(requests(&mock), 2);
+        assert_eq!(mock.requests.lock().unwrap()[1], "symbol=ETHUSDT");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_give_up_after_the_retries() {
+        let (mut client, url, mock) = serve(vec![(502, vec![], "bad gateway")]);
+        client.retries = 2;
+
+        match client.get(&url, LIMIT).await {
+            Err(FetchError::GaveUp { attempts, last }) => {
+                assert_eq!(attempts, 3);
+                assert!(last.contains("bad gateway"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(requests(&mock), 3);
+    }
+
+    #[tokio::test]
+    async fn test_bad_request_is_not_retried() {
+        let (client, url, mock) = serve(vec![(
+            400,
+            vec![],
+            r#"{"code":-1121,"msg":"Invalid symbol."}"#,
+        )]);
+
+        let result = client.get(&url, LIMIT).await;
+        assert!(matches!(
+            result,
+            Err(FetchError::Rejected { status: 400, .. })
+        ));
+        assert_eq!(requests(&mock), 1);
+    }
+
+    #[tokio::test]
+    async fn test_used_weight_is_tracked() {
+        let (client, url, _) = serve(vec![(200, vec![("x-mbx-used-weight-1m", "42")], BODY)]);
+
+        client.get(&url, LIMIT).await.unwrap();
+        assert_eq!(client.used_weight.load(Ordering::SeqCst), 42);
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_the_weight() {
+        let (client, url, _) = serve(vec![(200, vec![], BODY)]);
+        let other = client.clone();
+
+        client.get(&url, LIMIT).await.unwrap();
+        other.get(&url, LIMIT).await.unwrap();
+        // without a weight header both requests count
+        assert_eq!(
+            client.used_weight.load(Ordering::SeqCst),
+            2 * LIMIT.request_weight
+        );
+    }
+
+    #[test]
+    fn test_weight_wait_until_the_next_minute() {
+        assert_eq!(weight_wait(100, LIMIT, 90_000), None);
+        assert_eq!(weight_wait(5998, LIMIT, 90_000), None);
+        assert_eq!(
+            weight_wait(5999, LIMIT, 90_000),
+            Some(Duration::from_millis(31_000))
+        );
+    }
+
+    #[test]
+    fn test_weight_resets_once_per_minute() {
+        let client = RestClient::new().unwrap();
+        let futures = RateLimit {
+            request_weight: 10,
+            per_minute: 2400,
+        };
+        let other = client.clone();
+        for _ in 0..120 {
+            assert_eq!(client.reserve(futures, 90_000), None);
+            assert_eq!(other.reserve(futures, 95_000), None);
+        }
+        assert_eq!(client.used_weight.load(Ordering::SeqCst), 2400);
+        assert_eq!(
+            client.reserve(futures, 100_000),
+            Some(Duration::from_millis(21_000))
+        );
+
+        // the next minute counts from the first request in it, the others add to it
+        assert_eq!(client.reserve(futures, 121_000), None);
+        assert_eq!(other.reserve(futures, 122_000), None);
+        assert_eq!(client.used_weight.load(Ordering::SeqCst), 20);
+    }
+}
@@ -0,0 +1,975 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use std::fmt;
+
+use crate::model::{
+    ambiguity_policy::AmbiguityPolicy, backtest_result::BacktestResult, candle::Candle,
+    cost_model::CostModel, decimal::DecimalVec, position::Position,
+    position_direction::PositionDirection, trade::Trade, trade_management::TradeManagement,
+    trading_model::TradingModel,
+};
+use crate::strategies::management::ManagedPosition;
+
+// What a strategy sees on every candle: the candles up to now and nothing after.
+pub struct CandleView<'a> {
+    // oldest first, the last one just closed
+    pub candles: &'a [Candle],
+    // false while a position is open, working orders wait until it closes
+    pub flat: bool,
+}
+
+impl CandleView<'_> {
+    pub fn actual(&self) -> Candle {
+        *self.candles.last().expect("a view has at least one candle")
+    }
+}
+
+pub trait CandleModel {
+    // called on every closed candle to place or cancel orders
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderType {
+    // fills at the close of the candle it was placed on, dropped when not flat then
+    Market,
+    // fills at the price or better once a later candle trades there
+    Limit(DecimalVec),
+    // fills once a later candle trades through the price, at the open when it gaps past it
+    Stop(DecimalVec),
+    // turns into a limit order at `limit` once a later candle trades through `stop`
+    StopLimit { stop: DecimalVec, limit: DecimalVec },
+}
+
+// An entry with its bracket: once the entry fills, the stop and the target are one cancels
+// the other, and every other working entry is cancelled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Order {
+    pub direction: PositionDirection,
+    pub order_type: OrderType,
+    pub sl: DecimalVec,
+    pub tp: DecimalVec,
+    // cancelled when still working at the close of the candle closing at this time
+    pub expires: Option<DateTime<Tz>>,
+}
+
+impl Order {
+    pub fn market(direction: PositionDirection, sl: DecimalVec, tp: DecimalVec) -> Order {
+        Order {
+            direction,
+            order_type: OrderType::Market,
+            sl,
+            tp,
+            expires: None,
+        }
+    }
+
+    fn position(&self, open_time: DateTime<Tz>, entry: DecimalVec) -> Position {
+        Position {
+            direction: self.direction,
+            open_time,
+            entry,
+            sl: self.sl,
+            tp: self.tp,
+            at_break_even: false,
+        }
+    }
+
+    // reward to risk when filled at `entry`, None when `entry` is at or past the stop
+    pub fn rr(&self, entry: DecimalVec) -> Option<DecimalVec> {
+        if !self.protects(entry) {
+            return None;
+        }
+        Some(match self.direction {
+            PositionDirection::Short => (entry - self.tp) / (self.sl - entry),
+            PositionDirection::Long => (self.tp - entry) / (entry - self.sl),
+        })
+    }
+
+    // whether `entry` is on the right side of the stop, a position filled at or past it has
+    // no risk to measure R by
+    fn protects(&self, entry: DecimalVec) -> bool {
+        match self.direction {
+            PositionDirection::Long => self.sl < entry,
+            PositionDirection::Short => self.sl > entry,
+        }
+    }
+
+    // where and whether the order fills on `candle`, and what a stop limit turns into when
+    // the candle only triggers it
+    fn fill(&self, candle: Candle) -> Fill {
+        match self.trigger(candle) {
+            Fill::At(price) if !self.protects(price) => Fill::PastStop,
+            fill => fill,
+        }
+    }
+
+    fn trigger(&self, candle: Candle) -> Fill {
+        let long = self.direction == PositionDirection::Long;
+        match self.order_type {
+            OrderType::Market => Fill::At(candle.close),
+            OrderType::Limit(price) => match long {
+                true if candle.low <= price => Fill::At(min(candle.open, price)),
+                false if candle.high >= price => Fill::At(max(candle.open, price)),
+                _ => Fill::None,
+            },
+            OrderType::Stop(price) => match long {
+                true if candle.high >= price => Fill::At(max(candle.open, price)),
+                false if candle.low <= price => Fill::At(min(candle.open, price)),
+                _ => Fill::None,
+            },
+            OrderType::StopLimit { stop, limit } => match self.as_stop(stop).trigger(candle) {
+                Fill::At(price) if (long && price <= limit) || (!long && price >= limit) => {
+                    Fill::At(price)
+                }
+                // gapped past the limit, which of the rest of the candle came first is unknown
+                Fill::At(_) => Fill::Triggered(OrderType::Limit(limit)),
+                _ => Fill::None,
+            },
+        }
+    }
+
+    fn as_stop(&self, price: DecimalVec) -> Order {
+        Order {
+            order_type: OrderType::Stop(price),
+            ..*self
+        }
+    }
+
+    // the price that triggers the order, None for a market order
+    fn price(&self) -> Option<DecimalVec> {
+        match self.order_type {
+            OrderType::Market => None,
+            OrderType::Limit(price) | OrderType::Stop(price) => Some(price),
+            OrderType::StopLimit { stop, .. } => Some(stop),
+        }
+    }
+}
+
+enum Fill {
+    None,
+    At(DecimalVec),
+    Triggered(OrderType),
+    // would fill at or past its own stop, the setup is gone
+    PastStop,
+}
+
+fn min(a: DecimalVec, b: DecimalVec) -> DecimalVec {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+fn max(a: DecimalVec, b: DecimalVec) -> DecimalVec {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+// The entries a strategy has working, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderBook {
+    orders: Vec<Order>,
+}
+
+impl OrderBook {
+    pub fn place(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.orders.clear();
+    }
+
+    pub fn working(&self) -> &[Order] {
+        &self.orders
+    }
+
+    // The entry filled on `candle` and its price. When several fill, the one nearest the open
+    // is taken as the first, the rest are cancelled with it. An entry the candle gaps to or
+    // past its stop is cancelled without a fill.
+    fn fill(&mut self, candle: Candle) -> Option<(Order, DecimalVec)> {
+        self.orders
+            .retain(|o| !matches!(o.fill(candle), Fill::PastStop));
+        let mut filled: Option<(Order, DecimalVec)> = None;
+        for order in &mut self.orders {
+            match order.fill(candle) {
+                Fill::At(price) => {
+                    let distance = |o: &Order| o.price().map(|p| (p - candle.open).0.abs());
+                    match filled {
+                        Some((first, _)) if distance(&first) <= distance(order) => {}
+                        _ => filled = Some((*order, price)),
+                    }
+                }
+                Fill::Triggered(order_type) => order.order_type = order_type,
+                Fill::None | Fill::PastStop => {}
+            }
+        }
+        if filled.is_some() {
+            self.orders.clear();
+        }
+        filled
+    }
+
+    fn expire(&mut self, close_time: DateTime<Tz>) {
+        self.orders.retain(|o| match o.expires {
+            Some(expires) => expires > close_time,
+            None => true,
+        });
+    }
+
+    fn take_market(&mut self) -> Option<Order> {
+        let ind = self
+            .orders
+            .iter()
+            .position(|o| o.order_type == OrderType::Market)?;
+        Some(self.orders.remove(ind))
+    }
+}
+
+// Working orders a strategy left on a candle that it didn't leave once the candles after it
+// were cut off, so it read them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lookahead {
+    pub open_time: DateTime<Tz>,
+    pub full: Vec<Order>,
+    pub cut: Vec<Order>,
+}
+
+impl fmt::Display for Lookahead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the orders on the candle opening at {} depend on later candles: {:?} with them, {:?} without",
+            self.open_time.format("%Y-%m-%d %H:%M"),
+            self.full,
+            self.cut
+        )
+    }
+}
+
+// One position at a time. A market order enters at the close of its candle, the others on the
+// first later candle trading at their price, and the position is managed from the candle after.
+pub fn run<M: CandleModel>(
+    candles: &[Candle],
+    model: &mut M,
+    management: &TradeManagement,
+    costs: &CostModel,
+    ambiguity: &AmbiguityPolicy,
+) -> BacktestResult {
+    let mut runner = Runner::new(candles, model, management, costs, ambiguity);
+    while runner.next_close().is_some() {
+        runner.step(&mut |_| true);
+    }
+    runner.finish()
+}
+
+// A strategy on the engine holds its settings and no series. The model it builds gets the
+// candles only through the views, so it can't read one that hasn't closed yet.
+pub trait CandleStrategy {
+    type Model: CandleModel + 'static;
+
+    fn model(&self) -> Self::Model;
+    fn management(&self) -> TradeManagement;
+    fn costs(&self) -> &CostModel;
+    fn ambiguity(&self) -> &AmbiguityPolicy;
+}
+
+// a strategy on the series it runs on
+pub struct OnCandles<S> {
+    pub candles: Vec<Candle>,
+    pub strategy: S,
+}
+
+impl<S: CandleStrategy> TradingModel for OnCandles<S> {
+    fn execute(&self) -> BacktestResult {
+        run(
+            &self.candles,
+            &mut self.strategy.model(),
+            &self.strategy.management(),
+            self.strategy.costs(),
+            self.strategy.ambiguity(),
+        )
+    }
+
+    fn check_lookahead(&self) -> Option<Vec<Lookahead>> {
+        Some(check_lookahead(
+            &self.candles,
+            |_| self.strategy.model(),
+            &self.strategy.management(),
+            self.strategy.costs(),
+            self.strategy.ambiguity(),
+        ))
+    }
+
+    fn runner(&self) -> Option<Runner<'_>> {
+        Some(Runner::new(
+            &self.candles,
+            self.strategy.model(),
+            &self.strategy.management(),
+            self.strategy.costs(),
+            self.strategy.ambiguity(),
+        ))
+    }
+}
+
+// The debug mode of `run`. A view can't reach future candles, but a model built from a series
+// can still read it from its own fields, so every candle is replayed on the series cut right
+// after it with a model built from that cut, and the orders the model left on it compared.
+// `build` gets the candles the model may know about.
+//
+// Every candle takes a run up to it, the cost grows with the square of the series.
+pub fn check_lookahead<M: CandleModel>(
+    candles: &[Candle],
+    build: impl Fn(&[Candle]) -> M,
+    management: &TradeManagement,
+    costs: &CostModel,
+    ambiguity: &AmbiguityPolicy,
+) -> Vec<Lookahead> {
+    // the orders right after the model's turn on every candle
+    let orders = |candles: &[Candle]| {
+        let mut runner = Runner::new(candles, build(candles), management, costs, ambiguity);
+        (0..candles.len())
+            .map(|_| {
+                let step = runner.step(&mut |_| true);
+                step.decision
+                    .unwrap_or_else(|| runner.orders.working().to_vec())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    orders(candles)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(ind, full)| {
+            let cut = orders(&candles[..=ind]).pop().unwrap_or_default();
+            (cut != full).then(|| Lookahead {
+                open_time: candles[ind].open_time,
+                full,
+                cut,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Opened(Position),
+    // with costs applied
+    Closed(Trade),
+}
+
+#[derive(Debug, Default)]
+pub struct Step {
+    // in the order they happened
+    pub events: Vec<Event>,
+    // the orders right after the model changed them, market orders included
+    pub decision: Option<Vec<Order>>,
+}
+
+// A model on one series stepped a candle at a time, so several can share a timeline.
+pub struct Runner<'a> {
+    candles: &'a [Candle],
+    model: Box<dyn CandleModel + 'a>,
+    management: TradeManagement,
+    costs: CostModel,
+    ambiguity: AmbiguityPolicy,
+    // the candle the next step takes
+    ind: usize,
+    position: Option<ManagedPosition>,
+    orders: OrderBook,
+    trades: Vec<Trade>,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(
+        candles: &'a [Candle],
+        model: impl CandleModel + 'a,
+        management: &TradeManagement,
+        costs: &CostModel,
+        ambiguity: &AmbiguityPolicy,
+    ) -> Self {
+        Runner {
+            candles,
+            model: Box::new(model),
+            management: management.clone(),
+            costs: *costs,
+            ambiguity: ambiguity.clone(),
+            ind: 0,
+            position: None,
+            orders: OrderBook::default(),
+            trades: vec![],
+        }
+    }
+
+    // close time of the candle the next step takes, None once the series is done
+    pub fn next_close(&self) -> Option<DateTime<Tz>> {
+        self.candles.get(self.ind).map(|c| c.close_time)
+    }
+
+    // Takes the next candle. `admit` sees every entry about to be filled, one it turns down
+    // cancels the working orders the way a fill would.
+    pub fn step(&mut self, admit: &mut dyn FnMut(&Position) -> bool) -> Step {
+        let mut step = Step::default();
+        let Some(&candle) = self.candles.get(self.ind) else {
+            return step;
+        };
+        let history = &self.candles[..self.ind];
+        self.ind += 1;
+
+        if let Some(managed) = &mut self.position {
+            if let Some(trade) = managed.update(candle, &self.ambiguity) {
+                self.close(trade, &mut step);
+            }
+        } else if let Some((order, price)) = self.orders.fill(candle) {
+            let position = order.position(candle.open_time, price);
+            if admit(&position) {
+                step.events.push(Event::Opened(position));
+                let mut managed = ManagedPosition::new(position, &self.management, history);
+                match managed.stopped_on_entry(candle) {
+                    Some(trade) => self.close(trade, &mut step),
+                    None => self.position = Some(managed),
+                }
+            }
+        }
+        self.orders.expire(candle.close_time);
+
+        let view = CandleView {
+            candles: &self.candles[..self.ind],
+            flat: self.position.is_none(),
+        };
+        let before = self.orders.clone();
+        self.model.on_candle(&view, &mut self.orders);
+        if self.orders != before {
+            step.decision = Some(self.orders.working().to_vec());
+        }
+
+        while let Some(order) = self.orders.take_market() {
+            let position = order.position(candle.close_time, candle.close);
+            // a stop at or past the close is dropped like a market order in a trade
+            if self.position.is_none() && order.protects(candle.close) {
+                if admit(&position) {
+                    step.events.push(Event::Opened(position));
+                    self.position = Some(ManagedPosition::new(
+                        position,
+                        &self.management,
+                        view.candles,
+                    ));
+                }
+                self.orders.cancel_all();
+            }
+        }
+        step
+    }
+
+    // the closed trades, a position still open at the end is left out
+    pub fn finish(self) -> BacktestResult {
+        BacktestResult {
+            trades: self.trades,
+        }
+    }
+
+    fn close(&mut self, trade: Trade, step: &mut Step) {
+        let trade = self.costs.apply(trade);
+        self.trades.push(trade.clone());
+        step.events.push(Event::Closed(trade));
+        self.position = None;
+    }
+}
+
+impl<M: CandleModel + ?Sized> CandleModel for &mut M {
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+        (**self).on_candle(view, orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::trade_result::TradeResult;
+    use crate::to_new_york_time;
+
+    fn bar(ind: i64, open: i32, high: i32, low: i32, close: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(ind * 60),
+            close_time: to_new_york_time(ind * 60 + 59),
+            open: DecimalVec::new(open),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(close),
+            volume: DecimalVec::new(1),
+            number_of_trades: 1,
+        }
+    }
+
+    // climbs by one every candle
+    fn series() -> Vec<Candle> {
+        (0..6)
+            .map(|ind| {
+                bar(
+                    ind,
+                    100 + ind as i32,
+                    101 + ind as i32,
+                    100 + ind as i32,
+                    101 + ind as i32,
+                )
+            })
+            .collect()
+    }
+
+    fn order(direction: PositionDirection, order_type: OrderType, sl: i32, tp: i32) -> Order {
+        Order {
+            direction,
+            order_type,
+            sl: DecimalVec::new(sl),
+            tp: DecimalVec::new(tp),
+            expires: None,
+        }
+    }
+
+    fn long_market() -> Order {
+        Order::market(
+            PositionDirection::Long,
+            DecimalVec::new(90),
+            DecimalVec::new(104),
+        )
+    }
+
+    // places orders on the candles they are keyed by
+    struct Placer {
+        orders: Vec<(usize, Order)>,
+    }
+
+    impl CandleModel for Placer {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            for (ind, order) in &self.orders {
+                if *ind == view.candles.len() - 1 {
+                    orders.place(*order);
+                }
+            }
+        }
+    }
+
+    fn run_placer(candles: &[Candle], orders: Vec<(usize, Order)>) -> BacktestResult {
+        run(
+            candles,
+            &mut Placer { orders },
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        )
+    }
+
+    // goes long on the second candle, records how much it was shown
+    struct Recorder {
+        shown: Vec<(usize, bool)>,
+    }
+
+    impl CandleModel for Recorder {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            self.shown.push((view.candles.len(), view.flat));
+            if view.candles.len() == 2 {
+                orders.place(long_market());
+            }
+        }
+    }
+
+    // goes long when the next candle is higher, reading the series it was built with
+    struct Peeker {
+        candles: Vec<Candle>,
+    }
+
+    impl CandleModel for Peeker {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            let ind = view.candles.len() - 1;
+            if self
+                .candles
+                .get(ind + 1)
+                .is_some_and(|next| ind == 1 && next.high > view.actual().high)
+            {
+                orders.place(long_market());
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_shows_only_the_past() {
+        let candles = series();
+        let mut recorder = Recorder { shown: vec![] };
+        let result = run(
+            &candles,
+            &mut recorder,
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+
+        // in the trade from the third candle until the fifth trades through the target
+        assert_eq!(
+            recorder.shown,
+            vec![
+                (1, true),
+                (2, true),
+                (3, false),
+                (4, false),
+                (5, true),
+                (6, true)
+            ]
+        );
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].open_time, candles[1].close_time);
+        assert_eq!(result.trades[0].entry, candles[1].close);
+        assert_eq!(result.trades[0].close_time, candles[4].close_time);
+    }
+
+    #[test]
+    fn test_limit_fills_at_the_price_or_better() {
+        let candles = [
+            bar(0, 100, 101, 99, 100),
+            bar(1, 100, 101, 98, 99),
+            // opens below the limit
+            bar(2, 95, 97, 94, 96),
+        ];
+        let limit = |price| {
+            order(
+                PositionDirection::Long,
+                OrderType::Limit(DecimalVec::new(price)),
+                90,
+                120,
+            )
+        };
+
+        let mut orders = OrderBook::default();
+        orders.place(limit(98));
+        assert_eq!(
+            orders.fill(candles[1]),
+            Some((limit(98), DecimalVec::new(98)))
+        );
+        orders.place(limit(97));
+        assert_eq!(
+            orders.fill(candles[2]),
+            Some((limit(97), DecimalVec::new(95)))
+        );
+    }
+
+    #[test]
+    fn test_stop_fills_at_the_open_after_a_gap() {
+        let stop = order(
+            PositionDirection::Short,
+            OrderType::Stop(DecimalVec::new(98)),
+            105,
+            80,
+        );
+        let mut orders = OrderBook::default();
+        orders.place(stop);
+        assert_eq!(orders.fill(bar(0, 100, 101, 99, 100)), None);
+        assert_eq!(
+            orders.fill(bar(1, 96, 97, 90, 91)),
+            Some((stop, DecimalVec::new(96)))
+        );
+    }
+
+    #[test]
+    fn test_gap_to_or_through_the_stop_cancels_the_entry() {
+        let limit = order(
+            PositionDirection::Long,
+            OrderType::Limit(DecimalVec::new(98)),
+            95,
+            120,
+        );
+        let mut orders = OrderBook::default();
+        orders.place(limit);
+        // opens right at the stop
+        assert_eq!(orders.fill(bar(0, 95, 97, 93, 96)), None);
+        assert!(orders.working().is_empty());
+        orders.place(limit);
+        // opens below it
+        assert_eq!(orders.fill(bar(1, 92, 97, 91, 96)), None);
+        assert!(orders.working().is_empty());
+
+        let candles = [
+            bar(0, 100, 101, 99, 100),
+            bar(1, 92, 97, 91, 96),
+            bar(2, 96, 125, 96, 124),
+        ];
+        let result = run_placer(&candles, vec![(0, limit)]);
+        assert!(result.trades.is_empty());
+        // a market order with the stop above the close
+        let result = run_placer(
+            &candles,
+            vec![(
+                1,
+                Order::market(
+                    PositionDirection::Long,
+                    DecimalVec::new(96),
+                    DecimalVec::new(110),
+                ),
+            )],
+        );
+        assert!(result.trades.is_empty());
+    }
+
+    #[test]
+    fn test_stop_limit_past_the_limit_turns_into_a_limit() {
+        let stop_limit = order(
+            PositionDirection::Long,
+            OrderType::StopLimit {
+                stop: DecimalVec::new(102),
+                limit: DecimalVec::new(103),
+            },
+            95,
+            120,
+        );
+        let mut orders = OrderBook::default();
+        orders.place(stop_limit);
+        // gaps over the limit
+        assert_eq!(orders.fill(bar(0, 105, 106, 104, 105)), None);
+        assert_eq!(
+            orders.working()[0].order_type,
+            OrderType::Limit(DecimalVec::new(103))
+        );
+        assert_eq!(
+            orders
+                .fill(bar(1, 105, 105, 102, 103))
+                .map(|(_, price)| price),
+            Some(DecimalVec::new(103))
+        );
+    }
+
+    #[test]
+    fn test_one_entry_cancels_the_others() {
+        let candles = [
+            bar(0, 100, 101, 99, 100),
+            // trades through both entries, the long is nearer the open
+            bar(1, 101, 104, 97, 103),
+            bar(2, 103, 112, 95, 110),
+            bar(3, 110, 111, 96, 97),
+        ];
+        let orders = vec![
+            (
+                0,
+                order(
+                    PositionDirection::Long,
+                    OrderType::Stop(DecimalVec::new(102)),
+                    90,
+                    111,
+                ),
+            ),
+            (
+                0,
+                order(
+                    PositionDirection::Short,
+                    OrderType::Stop(DecimalVec::new(98)),
+                    110,
+                    80,
+                ),
+            ),
+        ];
+
+        let mut placer = Placer {
+            orders: orders.clone(),
+        };
+        let mut runner = Runner::new(
+            &candles,
+            &mut placer,
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        runner.step(&mut |_| true);
+        let step = runner.step(&mut |_| true);
+        let opened: Vec<&Event> = step
+            .events
+            .iter()
+            .filter(|e| matches!(e, Event::Opened(_)))
+            .collect();
+        assert_eq!(opened.len(), 1);
+
+        let result = run_placer(&candles, orders);
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.direction, PositionDirection::Long);
+        assert_eq!(trade.open_time, candles[1].open_time);
+        assert_eq!(trade.entry, DecimalVec::new(102));
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.close_time, candles[2].close_time);
+    }
+
+    #[test]
+    fn test_stop_inside_the_fill_candle_is_hit() {
+        let candles = [bar(0, 100, 101, 99, 100), bar(1, 100, 112, 94, 111)];
+        let result = run_placer(
+            &candles,
+            vec![(
+                0,
+                order(
+                    PositionDirection::Long,
+                    OrderType::Limit(DecimalVec::new(98)),
+                    95,
+                    110,
+                ),
+            )],
+        );
+
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].result, TradeResult::Expense);
+        assert_eq!(result.trades[0].close_time, candles[1].close_time);
+    }
+
+    #[test]
+    fn test_expired_orders_are_cancelled() {
+        let candles = [
+            bar(0, 100, 101, 99, 100),
+            bar(1, 100, 101, 99, 100),
+            bar(2, 100, 101, 95, 96),
+        ];
+        let limit = Order {
+            expires: Some(candles[1].close_time),
+            ..order(
+                PositionDirection::Long,
+                OrderType::Limit(DecimalVec::new(97)),
+                90,
+                120,
+            )
+        };
+        let mut orders = OrderBook::default();
+        orders.place(limit);
+        orders.expire(candles[0].close_time);
+        assert_eq!(orders.working().len(), 1);
+        orders.expire(candles[1].close_time);
+        assert!(orders.working().is_empty());
+    }
+
+    #[test]
+    fn test_market_order_in_a_trade_is_dropped() {
+        let candles = series();
+        let result = run_placer(&candles, vec![(0, long_market()), (1, long_market())]);
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].open_time, candles[0].close_time);
+    }
+
+    #[test]
+    fn test_ambiguous_exit_is_flagged() {
+        let candles = [bar(0, 100, 101, 99, 100), bar(1, 115, 125, 85, 100)];
+        let result = run_placer(
+            &candles,
+            vec![(
+                0,
+                Order::market(
+                    PositionDirection::Long,
+                    DecimalVec::new(95),
+                    DecimalVec::new(110),
+                ),
+            )],
+        );
+
+        assert_eq!(result.trades[0].result, TradeResult::Expense);
+        assert!(result.trades[0].ambiguous);
+        assert_eq!(result.trades[0].close_time, candles[1].close_time);
+    }
+
+    #[test]
+    fn test_check_lookahead_passes_a_fair_model() {
+        let found = check_lookahead(
+            &series(),
+            |_| Recorder { shown: vec![] },
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        assert!(found.is_empty());
+    }
+
+    // places a limit far below the price on every candle but the last of the series it was
+    // built with
+    struct BeforeTheEnd {
+        candles: Vec<Candle>,
+    }
+
+    impl CandleModel for BeforeTheEnd {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            let ind = view.candles.len() - 1;
+            if ind + 1 < self.candles.len() {
+                orders.place(order(
+                    PositionDirection::Long,
+                    OrderType::Limit(DecimalVec::new(ind as i32)),
+                    -1,
+                    120,
+                ));
+            }
+        }
+    }
+
+    // places a limit far below the price on the last candle of the series it was built with
+    struct AtTheEnd {
+        candles: Vec<Candle>,
+    }
+
+    impl CandleModel for AtTheEnd {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            if view.candles.len() == self.candles.len() {
+                orders.place(order(
+                    PositionDirection::Long,
+                    OrderType::Limit(DecimalVec::new(1)),
+                    -1,
+                    120,
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_lookahead_replays_every_candle() {
+        let candles: Vec<Candle> = (0..20).map(|ind| bar(ind, 100, 101, 99, 100)).collect();
+        let before_the_end = check_lookahead(
+            &candles,
+            |cut| BeforeTheEnd {
+                candles: cut.to_vec(),
+            },
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        // every candle but the last reads a later one
+        assert_eq!(before_the_end.len(), 19);
+
+        // the same read on the candles the model leaves its orders alone on
+        let at_the_end = check_lookahead(
+            &candles,
+            |cut| AtTheEnd {
+                candles: cut.to_vec(),
+            },
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        assert_eq!(at_the_end.len(), 19);
+        assert!(at_the_end.iter().all(|l| l.full.is_empty()));
+    }
+
+    #[test]
+    fn test_check_lookahead_reports_a_peek() {
+        let candles = series();
+        let found = check_lookahead(
+            &candles,
+            |cut| Peeker {
+                candles: cut.to_vec(),
+            },
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        assert_eq!(
+            found,
+            vec![Lookahead {
+                open_time: candles[1].open_time,
+                full: vec![long_market()],
+                cut: vec![],
+            }]
+        );
+    }
+}
@@ -4,8 +4,9 @@ use axum::{
     routing::get,
     Router,
 };
-use backtest::chart::chart;
+use backtest::{chart::chart, data};
 use charming::HtmlRenderer;
+use std::env;
 
 #[tokio::main]
 async fn main() {
@@ -23,6 +24,12 @@ async fn render(
     let renderer = HtmlRenderer::new(format!("{type} - {name}"), 1000, 800)
         .theme(charming::theme::Theme::Westeros);
 
-    let chart = chart();
-    Html(renderer.render(&chart).unwrap()).into_response()
+    // data file to chart, e.g. `cargo run --bin gallery -- assets/NDX_full_1min.txt`
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets/eth15.json".to_string());
+    match chart(data::from_path(path).as_ref()) {
+        Ok(chart) => Html(renderer.render(&chart).unwrap()).into_response(),
+        Err(e) => Html(format!("Failed to load data: {}", e)).into_response(),
+    }
 }
@@ -0,0 +1,507 @@
+use rust_decimal::{Decimal, MathematicalOps};
+use std::collections::VecDeque;
+
+use crate::model::candle::Candle;
+use crate::resample::{period, Period, Timeframe};
+
+// Fed one closed candle at a time, None while it's warming up.
+pub trait Indicator {
+    type Output;
+
+    fn next(&mut self, candle: &Candle) -> Option<Self::Output>;
+}
+
+// the indicator over a whole series, the value at `ind` belongs to `candles[ind]`
+pub fn series<I: Indicator>(mut indicator: I, candles: &[Candle]) -> Vec<Option<I::Output>> {
+    candles.iter().map(|c| indicator.next(c)).collect()
+}
+
+// simple moving average of the closes
+pub struct Sma {
+    period: usize,
+    window: VecDeque<Decimal>,
+    sum: Decimal,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "an average needs at least one value");
+        Sma {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: Decimal::ZERO,
+        }
+    }
+
+    pub fn update(&mut self, value: Decimal) -> Option<Decimal> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        (self.window.len() == self.period).then(|| self.sum / Decimal::from(self.period))
+    }
+}
+
+impl Indicator for Sma {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        self.update(candle.close.0)
+    }
+}
+
+// exponential moving average of the closes, seeded with the simple average of the first
+// `period` closes
+pub struct Ema {
+    alpha: Decimal,
+    seed: Sma,
+    value: Option<Decimal>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Ema {
+            alpha: Decimal::TWO / Decimal::from(period + 1),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    pub fn update(&mut self, value: Decimal) -> Option<Decimal> {
+        self.value = match self.value {
+            Some(previous) => Some(previous + self.alpha * (value - previous)),
+            None => self.seed.update(value),
+        };
+        self.value
+    }
+}
+
+impl Indicator for Ema {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        self.update(candle.close.0)
+    }
+}
+
+// average true range with Wilder's smoothing
+pub struct Atr {
+    period: Decimal,
+    seed: Sma,
+    previous_close: Option<Decimal>,
+    value: Option<Decimal>,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Atr {
+            period: Decimal::from(period),
+            seed: Sma::new(period),
+            previous_close: None,
+            value: None,
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        let (high, low) = (candle.high.0, candle.low.0);
+        // the first candle has no close to gap from
+        let true_range = match self.previous_close.replace(candle.close.0) {
+            Some(close) => (high - low)
+                .max((high - close).abs())
+                .max((low - close).abs()),
+            None => high - low,
+        };
+        self.value = match self.value {
+            Some(previous) => {
+                Some((previous * (self.period - Decimal::ONE) + true_range) / self.period)
+            }
+            None => self.seed.update(true_range),
+        };
+        self.value
+    }
+}
+
+// relative strength index of the closes with Wilder's smoothing, 0 to 100
+pub struct Rsi {
+    period: Decimal,
+    gains: Sma,
+    losses: Sma,
+    previous: Option<Decimal>,
+    // average gain and loss
+    averages: Option<(Decimal, Decimal)>,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Rsi {
+            period: Decimal::from(period),
+            gains: Sma::new(period),
+            losses: Sma::new(period),
+            previous: None,
+            averages: None,
+        }
+    }
+
+    pub fn update(&mut self, value: Decimal) -> Option<Decimal> {
+        let previous = self.previous.replace(value)?;
+        let change = value - previous;
+        let gain = change.max(Decimal::ZERO);
+        let loss = (-change).max(Decimal::ZERO);
+
+        self.averages = match self.averages {
+            Some((gains, losses)) => {
+                let n = self.period;
+                Some((
+                    (gains * (n - Decimal::ONE) + gain) / n,
+                    (losses * (n - Decimal::ONE) + loss) / n,
+                ))
+            }
+            None => self.gains.update(gain).zip(self.losses.update(loss)),
+        };
+
+        let (gains, losses) = self.averages?;
+        Some(if losses.is_zero() && gains.is_zero() {
+            Decimal::from(50)
+        } else if losses.is_zero() {
+            Decimal::ONE_HUNDRED
+        } else {
+            Decimal::ONE_HUNDRED - Decimal::ONE_HUNDRED / (Decimal::ONE + gains / losses)
+        })
+    }
+}
+
+impl Indicator for Rsi {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        self.update(candle.close.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BollingerBands {
+    pub upper: Decimal,
+    pub middle: Decimal,
+    pub lower: Decimal,
+}
+
+// the simple average of the closes and `k` population standard deviations around it
+pub struct Bollinger {
+    k: Decimal,
+    sma: Sma,
+    window: VecDeque<Decimal>,
+}
+
+impl Bollinger {
+    pub fn new(period: usize, k: Decimal) -> Self {
+        Bollinger {
+            k,
+            sma: Sma::new(period),
+            window: VecDeque::with_capacity(period + 1),
+        }
+    }
+}
+
+impl Indicator for Bollinger {
+    type Output = BollingerBands;
+
+    fn next(&mut self, candle: &Candle) -> Option<BollingerBands> {
+        let close = candle.close.0;
+        let middle = self.sma.update(close);
+        self.window.push_back(close);
+        if self.window.len() > self.sma.period {
+            self.window.pop_front();
+        }
+
+        let middle = middle?;
+        let variance = self
+            .window
+            .iter()
+            .map(|x| (x - middle) * (x - middle))
+            .sum::<Decimal>()
+            / Decimal::from(self.window.len());
+        let width = self.k * variance.sqrt().unwrap_or_default();
+        Some(BollingerBands {
+            upper: middle + width,
+            middle,
+            lower: middle - width,
+        })
+    }
+}
+
+// Volume weighted average of the typical price (high + low + close) / 3, restarting with every
+// `anchor` period, e.g. each New York day or each session. None outside a session. While a
+// period has no volume at all, like the New York csv candles, every candle weighs the same.
+pub struct Vwap {
+    anchor: Timeframe,
+    period: Option<Period>,
+    volume: Decimal,
+    weighted: Decimal,
+    count: Decimal,
+    typical: Decimal,
+}
+
+impl Vwap {
+    pub fn new(anchor: Timeframe) -> Self {
+        Vwap {
+            anchor,
+            period: None,
+            volume: Decimal::ZERO,
+            weighted: Decimal::ZERO,
+            count: Decimal::ZERO,
+            typical: Decimal::ZERO,
+        }
+    }
+}
+
+impl Indicator for Vwap {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        let actual = period(self.anchor, candle.open_time)?;
+        if self.period != Some(actual) {
+            *self = Vwap::new(self.anchor);
+            self.period = Some(actual);
+        }
+
+        let typical = (candle.high.0 + candle.low.0 + candle.close.0) / Decimal::from(3);
+        self.volume += candle.volume.0;
+        self.weighted += typical * candle.volume.0;
+        self.count += Decimal::ONE;
+        self.typical += typical;
+        Some(if self.volume.is_zero() {
+            self.typical / self.count
+        } else {
+            self.weighted / self.volume
+        })
+    }
+}
+
+// highest high of the last `period` candles, the actual one included
+pub struct Highest {
+    period: usize,
+    window: VecDeque<Decimal>,
+}
+
+impl Highest {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "a window needs at least one candle");
+        Highest {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+        }
+    }
+}
+
+impl Indicator for Highest {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        self.window.push_back(candle.high.0);
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        (self.window.len() == self.period).then(|| self.window.iter().copied().max())?
+    }
+}
+
+// lowest low of the last `period` candles, the actual one included
+pub struct Lowest {
+    period: usize,
+    window: VecDeque<Decimal>,
+}
+
+impl Lowest {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "a window needs at least one candle");
+        Lowest {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+        }
+    }
+}
+
+impl Indicator for Lowest {
+    type Output = Decimal;
+
+    fn next(&mut self, candle: &Candle) -> Option<Decimal> {
+        self.window.push_back(candle.low.0);
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        (self.window.len() == self.period).then(|| self.window.iter().copied().min())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveTime};
+
+    use super::*;
+    use crate::model::decimal::DecimalVec;
+    use crate::parse_datetime;
+
+    // one minute candles from `start`, (high, low, close) each, with a volume of 1
+    fn candles(start: &str, prices: &[(i32, i32, i32)]) -> Vec<Candle> {
+        let start = parse_datetime(start).unwrap();
+        prices
+            .iter()
+            .enumerate()
+            .map(|(ind, (high, low, close))| Candle {
+                open_time: start + Duration::minutes(ind as i64),
+                close_time: start + Duration::minutes(ind as i64 + 1),
+                open: DecimalVec::new(*close),
+                high: DecimalVec::new(*high),
+                low: DecimalVec::new(*low),
+                close: DecimalVec::new(*close),
+                volume: DecimalVec::new(1),
+                number_of_trades: 0,
+            })
+            .collect()
+    }
+
+    fn closes(closes: &[i32]) -> Vec<Candle> {
+        let prices: Vec<(i32, i32, i32)> = closes.iter().map(|c| (*c, *c, *c)).collect();
+        candles("2024-04-22 09:30:00", &prices)
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn some(values: &[&str]) -> Vec<Option<Decimal>> {
+        values
+            .iter()
+            .map(|v| (!v.is_empty()).then(|| d(v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_sma() {
+        assert_eq!(
+            series(Sma::new(3), &closes(&[1, 2, 3, 4, 8])),
+            some(&["", "", "2", "3", "5"])
+        );
+    }
+
+    #[test]
+    fn test_ema() {
+        // seeded with 2, then alpha 0.5
+        assert_eq!(
+            series(Ema::new(3), &closes(&[1, 2, 3, 6, 2])),
+            some(&["", "", "2", "4", "3"])
+        );
+    }
+
+    #[test]
+    fn test_atr() {
+        let candles = candles(
+            "2024-04-22 09:30:00",
+            &[
+                (102, 98, 100),
+                (110, 104, 108),
+                (109, 107, 108),
+                (109, 107, 108),
+            ],
+        );
+        // true ranges 4, 10 from the gap up, 2 and 2
+        assert_eq!(
+            series(Atr::new(2), &candles),
+            some(&["", "7", "4.5", "3.25"])
+        );
+    }
+
+    #[test]
+    fn test_rsi() {
+        assert_eq!(
+            series(Rsi::new(2), &closes(&[10, 11, 12, 12])),
+            some(&["", "", "100", "100"])
+        );
+        assert_eq!(
+            series(Rsi::new(2), &closes(&[10, 10, 10])),
+            some(&["", "", "50"])
+        );
+        // average gain 1.5 and loss 0.5, then 0.75 and 1.25
+        let rsi = series(Rsi::new(2), &closes(&[10, 13, 12, 10]));
+        assert_eq!(rsi[2], Some(d("75")));
+        assert_eq!(rsi[3], Some(d("37.5")));
+    }
+
+    #[test]
+    fn test_bollinger() {
+        let bands = series(Bollinger::new(4, Decimal::TWO), &closes(&[2, 4, 4, 6, 6]));
+        assert_eq!(bands[2], None);
+        // mean 4 and variance 2
+        let b = bands[3].unwrap();
+        assert_eq!(b.middle, Decimal::from(4));
+        assert_eq!(
+            (b.upper - b.middle).round_dp(6),
+            (Decimal::TWO * Decimal::TWO.sqrt().unwrap()).round_dp(6)
+        );
+        assert_eq!(b.upper - b.middle, b.middle - b.lower);
+        // 4, 4, 6, 6 are one away from the mean of 5
+        assert_eq!(
+            bands[4],
+            Some(BollingerBands {
+                upper: Decimal::from(7),
+                middle: Decimal::from(5),
+                lower: Decimal::from(3),
+            })
+        );
+    }
+
+    #[test]
+    fn test_vwap_resets_each_new_york_day() {
+        let mut candles = candles(
+            "2024-04-22 23:58:00",
+            &[(12, 9, 9), (15, 12, 12), (6, 3, 3)],
+        );
+        candles[1].volume = DecimalVec::new(2);
+        let vwap = series(
+            Vwap::new(Timeframe::Daily {
+                start: NaiveTime::MIN,
+            }),
+            &candles,
+        );
+        // typical prices 10 and 13 weighted 1 and 2, then a new day
+        assert_eq!(vwap, some(&["10", "12", "4"]));
+    }
+
+    #[test]
+    fn test_vwap_session() {
+        let mut candles = candles(
+            "2024-04-22 09:29:00",
+            &[(12, 9, 9), (15, 12, 12), (6, 3, 3)],
+        );
+        for c in &mut candles {
+            c.volume = DecimalVec::new(0);
+        }
+        let session = Timeframe::Session {
+            start: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+        };
+        // no volume, the typical prices weigh the same
+        assert_eq!(
+            series(Vwap::new(session), &candles),
+            some(&["", "13", "8.5"])
+        );
+    }
+
+    #[test]
+    fn test_highest_and_lowest() {
+        let candles = candles(
+            "2024-04-22 09:30:00",
+            &[(10, 5, 7), (12, 6, 8), (11, 4, 9), (9, 7, 8)],
+        );
+        assert_eq!(
+            series(Highest::new(2), &candles),
+            some(&["", "12", "12", "11"])
+        );
+        assert_eq!(series(Lowest::new(3), &candles), some(&["", "", "4", "4"]));
+    }
+}
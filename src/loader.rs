@@ -1,10 +1,15 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use backtest::data::binance_api::Binance;
+use backtest::data::kline_file::{self, KlineFile};
+use backtest::data::provider::parse_millis;
+use backtest::data::rest::{FetchError, RestClient};
 use backtest::model::binance_klines_item::BinanceKlinesItem;
-use clap::{Arg, Command};
+use chrono::DateTime;
+use clap::{Arg, ArgAction, Command};
 use dialoguer::Confirm;
 use std::fs;
 use std::path::Path;
-use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio;
 
 #[tokio::main]
@@ -17,9 +22,16 @@ async fn main() -> Result<()> {
             Arg::new("start-time")
                 .short('s')
                 .long("start-time")
-                .value_parser(clap::value_parser!(u64))
-                .required(true)
-                .help("Start time in milliseconds since Unix epoch"),
+                .value_parser(parse_millis)
+                .required_unless_present("update")
+                .help("Start time in milliseconds since Unix epoch, or a date like 2024-01-01 or 2024-01-01T09:30-04:00"),
+        )
+        .arg(
+            Arg::new("end-time")
+                .short('e')
+                .long("end-time")
+                .value_parser(parse_millis)
+                .help("Last open time to fetch, in the formats of --start-time"),
         )
         .arg(
             Arg::new("symbol")
@@ -27,7 +39,9 @@ async fn main() -> Result<()> {
                 .long("symbol")
                 .value_parser(clap::value_parser!(String))
                 .required(true)
-                .help("Trading pair symbol (e.g., BTCUSDT)"),
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Trading pair symbol (e.g., BTCUSDT), repeat it or separate with commas for several"),
         )
         .arg(
             Arg::new("interval")
@@ -40,21 +54,38 @@ async fn main() -> Result<()> {
                 .required(true)
                 .help("Candlestick time frame (e.g., 1m, 5m, 1h, 1d)"),
         )
+        .arg(
+            Arg::new("update")
+                .short('u')
+                .long("update")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Append the klines after the last stored one instead of overwriting the file",
+                ),
+        )
+        .arg(
+            Arg::new("base-url")
+                .long("base-url")
+                .default_value("https://api.binance.com/api/v1")
+                .help("Klines endpoint without /klines, e.g. https://fapi.binance.com/fapi/v1 for usd-m futures or https://testnet.binance.vision/api/v1"),
+        )
         .get_matches();
 
-    let mut start_time = *matches
-        .get_one::<u64>("start-time")
-        .expect("start-time is a required argument");
-    let symbol = matches
-        .get_one::<String>("symbol")
-        .expect("symbol is a required argument");
+    let symbols: Vec<&String> = matches
+        .get_many::<String>("symbol")
+        .expect("symbol is a required argument")
+        .collect();
     let interval = matches
         .get_one::<String>("interval")
         .expect("interval is a required argument");
+    let end_time = matches.get_one::<u64>("end-time").copied();
+    let base_url = matches
+        .get_one::<String>("base-url")
+        .expect("base-url has a default");
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()?;
+    // one client for every symbol, they share its weight
+    let rest = RestClient::new()?;
+    let binance = Binance::new(base_url);
 
     // Ensure the assets directory exists
     let assets_dir = Path::new("assets");
@@ -62,67 +93,140 @@ async fn main() -> Result<()> {
         fs::create_dir(assets_dir)?;
     }
 
-    // File path
-    let file_path = assets_dir.join(format!("{}_{}.json", symbol, interval));
-
-    // Check if file exists and prompt for overwrite
-    if file_path.exists() {
-        if !Confirm::new()
-            .with_prompt(format!(
-                "{} already exists. Do you want to overwrite it?",
+    // the prompts come first, the downloads then run together
+    let mut downloads = vec![];
+    for symbol in symbols {
+        let file_path = assets_dir.join(format!("{}_{}.json", symbol, interval));
+        let file = KlineFile::new(&file_path);
+        let update = matches.get_flag("update") && file_path.exists();
+        let stored = if update { file.read()? } else { Vec::new() };
+
+        let last_close_time = stored.iter().map(|k| k.close_time).max();
+        let start_time = match (last_close_time, matches.get_one::<u64>("start-time")) {
+            // binance pages from the close time the same way below
+            (Some(close_time), _) => close_time,
+            (None, Some(start_time)) => *start_time,
+            (None, None) => bail!(
+                "{} holds no klines yet, --start-time is needed",
                 file_path.display()
-            ))
-            .default(false)
-            .interact()?
+            ),
+        };
+
+        // Check if file exists and prompt for overwrite
+        if file_path.exists()
+            && !update
+            && !Confirm::new()
+                .with_prompt(format!(
+                    "{} already exists. Do you want to overwrite it?",
+                    file_path.display()
+                ))
+                .default(false)
+                .interact()?
         {
-            println!("Operation cancelled. File not overwritten.");
-            return Ok(());
+            println!("{} skipped, file not overwritten.", symbol);
+            continue;
         }
-    }
-    let mut all_klines: Vec<BinanceKlinesItem> = Vec::new();
-
-    loop {
-        let url = format!(
-            "https://api.binance.com/api/v1/klines?symbol={}&interval={}&startTime={}&limit=1500",
-            symbol, interval, start_time
-        );
 
-        let response = client.get(&url).send().await?;
-
-        let status = response.status();
-        let text = response.text().await?;
-
-        if status.is_success() {
-            let klines: Vec<BinanceKlinesItem> =
-                serde_json::from_str(&text).unwrap_or_else(|err| {
-                    println!("Failed to parse response: {}", err);
-                    Vec::new()
-                });
+        let fetch = tokio::spawn(fetch(
+            rest.clone(),
+            binance.clone(),
+            symbol.clone(),
+            interval.clone(),
+            start_time,
+            end_time,
+        ));
+        downloads.push((file, stored, fetch));
+    }
 
-            if klines.is_empty() {
-                println!("No more data available.");
-                break;
+    let mut failed = vec![];
+    for (file, stored, fetch) in downloads {
+        let (fetched, outcome) = fetch.await?;
+
+        // an existing file is left alone when nothing came in, a failed first request
+        // doesn't wipe it
+        let count = fetched.len();
+        if count == 0 && file.path.exists() {
+            match outcome {
+                Ok(()) => println!("no new klines, {} left unchanged", file.path.display()),
+                Err(e) => {
+                    eprintln!("{}. {} left unchanged", e, file.path.display());
+                    failed.push(file.path.display().to_string());
+                }
             }
+            continue;
+        }
 
-            for kline in klines {
-                println!("{:?}", kline);
-                start_time = kline.close_time;
-                all_klines.push(kline);
+        // what came in before a failure is kept, an update picks up from there
+        let klines = kline_file::merge(stored, fetched);
+        file.write(&klines)?;
+        match outcome {
+            Ok(()) => println!(
+                "{} klines fetched, {} saved to {}",
+                count,
+                klines.len(),
+                file.path.display()
+            ),
+            Err(e) => {
+                eprintln!(
+                    "{}. {} klines saved to {}, run with --update to resume",
+                    e,
+                    klines.len(),
+                    file.path.display()
+                );
+                failed.push(file.path.display().to_string());
             }
-        } else {
-            println!("Error: {}", status);
-            println!("Response body: {}", text);
-            break;
         }
     }
-
-    // Serialize all klines to JSON and save to file
-    let json_data = serde_json::to_string_pretty(&all_klines)?;
-    fs::write(file_path, json_data)?;
-
-    println!("Data saved to assets/{}_{}.json", symbol, interval);
+    if !failed.is_empty() {
+        bail!("incomplete downloads: {}", failed.join(", "));
+    }
 
     Ok(())
 }
 
+// every closed kline from `start_time` on, with what came in before a failure
+async fn fetch(
+    rest: RestClient,
+    binance: Binance,
+    symbol: String,
+    interval: String,
+    mut start_time: u64,
+    end_time: Option<u64>,
+) -> (Vec<BinanceKlinesItem>, Result<(), FetchError>) {
+    let mut all_klines: Vec<BinanceKlinesItem> = Vec::new();
+    // the kline still forming is left out, the next update fetches it closed
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(u64::MAX);
+
+    let outcome = loop {
+        let klines = match binance
+            .klines(&rest, &symbol, &interval, start_time, end_time)
+            .await
+        {
+            Ok(klines) => klines,
+            Err(e) => break Err(e),
+        };
+        let klines: Vec<BinanceKlinesItem> =
+            klines.into_iter().filter(|k| k.close_time < now).collect();
+        let Some(last) = klines.last() else {
+            println!("{}: no more data available.", symbol);
+            break Ok(());
+        };
+
+        start_time = last.close_time;
+        println!(
+            "{}: {} klines up to {}",
+            symbol,
+            klines.len(),
+            DateTime::from_timestamp_millis(last.open_time as i64)
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default()
+        );
+        all_klines.extend(klines);
+    };
+    (all_klines, outcome)
+}
+
 // BTC/ETH: 1502942400000 - 2014-09-05T17:00:00Z
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use super::candle::Candle;
+
+// Which level a candle reached first when its range covers both the stop and the target.
+#[derive(Debug, Clone, Default)]
+pub enum AmbiguityPolicy {
+    // the stop, the only assumption that can't flatter a strategy
+    #[default]
+    Pessimistic,
+    // the target
+    Optimistic,
+    // the level closer to the candle's open
+    OpenProximity,
+    // replay the lower timeframe candles inside the candle, pessimistic when they don't tell
+    LowerTimeframe(Arc<Vec<Candle>>),
+}
@@ -1,6 +1,9 @@
 use std::fmt;
+use std::io::{self, Write};
 
-use rust_decimal::Decimal;
+use chrono::Duration;
+use rust_decimal::{Decimal, MathematicalOps};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use super::{trade::Trade, trade_result::TradeResult};
 
@@ -8,6 +11,21 @@ pub struct BacktestResult {
     pub trades: Vec<Trade>,
 }
 
+// the deepest peak-to-trough fall of the R equity curve
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Drawdown {
+    pub depth_in_r: Decimal,
+    // trades from the peak until the curve is back at the peak (or the last trade)
+    pub trades: usize,
+    // ISO-8601 duration, e.g. `PT14400S`
+    #[serde(serialize_with = "iso_duration")]
+    pub duration: Duration,
+}
+
+fn iso_duration<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(duration)
+}
+
 impl BacktestResult {
     pub fn number_of_trades(&self) -> usize {
         self.trades.len()
@@ -21,16 +39,201 @@ impl BacktestResult {
             .len()
     }
     pub fn profit_in_r(&self) -> Decimal {
-        self.trades
-            .clone()
-            .into_iter()
-            .map(|x| match x.result {
-                TradeResult::Winner => x.rr().0,
-                TradeResult::Expense => Decimal::from(-1),
-                TradeResult::BreakEven => Decimal::from(0),
+        self.trades.iter().map(|x| x.r_multiple()).sum()
+    }
+
+    // what the costs took off the gross result
+    pub fn costs_in_r(&self) -> Decimal {
+        self.trades.iter().map(|x| x.cost_in_r).sum()
+    }
+
+    // trades whose result depends on the ambiguity policy
+    pub fn ambiguous_trades(&self) -> usize {
+        self.trades.iter().filter(|x| x.ambiguous).count()
+    }
+
+    pub fn win_rate(&self) -> Option<Decimal> {
+        if self.trades.is_empty() {
+            return None;
+        }
+        Some(
+            Decimal::from(self.result(TradeResult::Winner))
+                / Decimal::from(self.number_of_trades()),
+        )
+    }
+
+    // average R per trade
+    pub fn expectancy(&self) -> Option<Decimal> {
+        if self.trades.is_empty() {
+            return None;
+        }
+        Some(self.profit_in_r() / Decimal::from(self.number_of_trades()))
+    }
+
+    pub fn profit_factor(&self) -> Option<Decimal> {
+        let gross_loss = self.gross_loss_in_r();
+        if gross_loss.is_zero() {
+            return None;
+        }
+        Some(self.gross_profit_in_r() / gross_loss)
+    }
+
+    pub fn average_win(&self) -> Option<Decimal> {
+        let wins = self
+            .r_multiples()
+            .filter(|r| r.is_sign_positive() && !r.is_zero());
+        average(wins)
+    }
+
+    // as a positive number of R
+    pub fn average_loss(&self) -> Option<Decimal> {
+        let losses = self
+            .r_multiples()
+            .filter(|r| r.is_sign_negative() && !r.is_zero());
+        average(losses).map(|r| r.abs())
+    }
+
+    pub fn max_consecutive_wins(&self) -> usize {
+        self.max_streak(TradeResult::Winner)
+    }
+
+    pub fn max_consecutive_losses(&self) -> usize {
+        self.max_streak(TradeResult::Expense)
+    }
+
+    // cumulative R after each trade
+    pub fn equity_curve(&self) -> Vec<Decimal> {
+        self.r_multiples()
+            .scan(Decimal::ZERO, |equity, r| {
+                *equity += r;
+                Some(*equity)
             })
-            .sum()
+            .collect()
+    }
+
+    pub fn max_drawdown(&self) -> Drawdown {
+        let curve = self.equity_curve();
+        // peak is the index of the peak trade, None while it is the starting equity
+        let mut peak: Option<usize> = None;
+        let mut peak_equity = Decimal::ZERO;
+        let mut deepest: Option<(Decimal, Option<usize>, Decimal)> = None;
+
+        for (ind, equity) in curve.iter().enumerate() {
+            if *equity >= peak_equity {
+                peak = Some(ind);
+                peak_equity = *equity;
+            } else if peak_equity - *equity > deepest.map(|d| d.0).unwrap_or(Decimal::ZERO) {
+                deepest = Some((peak_equity - *equity, peak, peak_equity));
+            }
+        }
+
+        match deepest {
+            None => Drawdown {
+                depth_in_r: Decimal::ZERO,
+                trades: 0,
+                duration: Duration::zero(),
+            },
+            Some((depth_in_r, peak, peak_equity)) => {
+                let first = peak.map_or(0, |ind| ind + 1);
+                let end = (first..curve.len())
+                    .find(|ind| curve[*ind] >= peak_equity)
+                    .unwrap_or(curve.len() - 1);
+                let start = match peak {
+                    Some(ind) => self.trades[ind].close_time,
+                    None => self.trades[0].open_time,
+                };
+                Drawdown {
+                    depth_in_r,
+                    trades: end + 1 - first,
+                    duration: self.trades[end].close_time - start,
+                }
+            }
+        }
+    }
+
+    // mean over sample standard deviation of the per trade R
+    pub fn sharpe_ratio(&self) -> Option<Decimal> {
+        if self.trades.len() < 2 {
+            return None;
+        }
+        let mean = self.expectancy()?;
+        let variance = self
+            .r_multiples()
+            .map(|r| (r - mean) * (r - mean))
+            .sum::<Decimal>()
+            / Decimal::from(self.trades.len() - 1);
+        let std_dev = variance.sqrt()?;
+        if std_dev.is_zero() {
+            return None;
+        }
+        Some(mean / std_dev)
+    }
+
+    fn r_multiples(&self) -> impl Iterator<Item = Decimal> + '_ {
+        self.trades.iter().map(|x| x.r_multiple())
+    }
+
+    fn gross_profit_in_r(&self) -> Decimal {
+        self.r_multiples().filter(|r| r.is_sign_positive()).sum()
+    }
+
+    fn gross_loss_in_r(&self) -> Decimal {
+        self.r_multiples()
+            .filter(|r| r.is_sign_negative())
+            .sum::<Decimal>()
+            .abs()
+    }
+
+    fn max_streak(&self, tr: TradeResult) -> usize {
+        let mut max = 0;
+        let mut actual = 0;
+        for trade in &self.trades {
+            if trade.result == tr {
+                actual += 1;
+                max = max.max(actual);
+            } else {
+                actual = 0;
+            }
+        }
+        max
+    }
+
+    // one csv row per trade, in the order they were taken
+    pub fn write_trade_log<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "direction,open_time,close_time,entry,sl,tp,rr,result,realized_r,gross_r,cost_in_r,r_multiple,ambiguous"
+        )?;
+        for trade in &self.trades {
+            writeln!(
+                writer,
+                "{:?},{},{},{},{},{},{},{:?},{},{},{},{},{}",
+                trade.direction,
+                trade.open_time.to_rfc3339(),
+                trade.close_time.to_rfc3339(),
+                trade.entry.0,
+                trade.sl.0,
+                trade.tp.0,
+                trade.rr().0,
+                trade.result,
+                // the size weighted R of a managed trade's exits, empty for a whole exit
+                trade.realized_r.map(|r| r.to_string()).unwrap_or_default(),
+                trade.gross_r(),
+                trade.cost_in_r,
+                trade.r_multiple(),
+                trade.ambiguous
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn average(values: impl Iterator<Item = Decimal>) -> Option<Decimal> {
+    let (sum, count) = values.fold((Decimal::ZERO, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        return None;
     }
+    Some(sum / Decimal::from(count))
 }
 
 impl fmt::Debug for BacktestResult {
@@ -42,6 +245,287 @@ impl fmt::Debug for BacktestResult {
             .field("expenses", &self.result(TradeResult::Expense))
             .field("break_evens", &self.result(TradeResult::BreakEven))
             .field("profit_in_r", &self.profit_in_r())
+            .field("costs_in_r", &self.costs_in_r())
+            .field("ambiguous_trades", &self.ambiguous_trades())
+            .field("win_rate", &self.win_rate())
+            .field("expectancy", &self.expectancy())
+            .field("profit_factor", &self.profit_factor())
+            .field("average_win", &self.average_win())
+            .field("average_loss", &self.average_loss())
+            .field("max_consecutive_wins", &self.max_consecutive_wins())
+            .field("max_consecutive_losses", &self.max_consecutive_losses())
+            .field("max_drawdown", &self.max_drawdown())
+            .field("sharpe_ratio", &self.sharpe_ratio())
             .finish()
     }
 }
+
+// the trades together with every derived statistic
+impl Serialize for BacktestResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("BacktestResult", 18)?;
+        s.serialize_field("trades", &self.trades)?;
+        s.serialize_field("number_of_trades", &self.number_of_trades())?;
+        s.serialize_field("winners", &self.result(TradeResult::Winner))?;
+        s.serialize_field("expenses", &self.result(TradeResult::Expense))?;
+        s.serialize_field("break_evens", &self.result(TradeResult::BreakEven))?;
+        s.serialize_field("profit_in_r", &self.profit_in_r())?;
+        s.serialize_field("costs_in_r", &self.costs_in_r())?;
+        s.serialize_field("ambiguous_trades", &self.ambiguous_trades())?;
+        s.serialize_field("win_rate", &self.win_rate())?;
+        s.serialize_field("expectancy", &self.expectancy())?;
+        s.serialize_field("profit_factor", &self.profit_factor())?;
+        s.serialize_field("average_win", &self.average_win())?;
+        s.serialize_field("average_loss", &self.average_loss())?;
+        s.serialize_field("max_consecutive_wins", &self.max_consecutive_wins())?;
+        s.serialize_field("max_consecutive_losses", &self.max_consecutive_losses())?;
+        s.serialize_field("max_drawdown", &self.max_drawdown())?;
+        s.serialize_field("sharpe_ratio", &self.sharpe_ratio())?;
+        s.serialize_field("equity_curve", &self.equity_curve())?;
+        s.end()
+    }
+}
+
+// one line summary without the trade list
+impl fmt::Display for BacktestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let optional = |v: Option<Decimal>| match v {
+            Some(v) => v.round_dp(2).normalize().to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "trades: {}, winners: {}, expenses: {}, break evens: {}, profit: {}R, \
+             win rate: {}, expectancy: {}R, profit factor: {}, max drawdown: {}R, sharpe: {}",
+            self.number_of_trades(),
+            self.result(TradeResult::Winner),
+            self.result(TradeResult::Expense),
+            self.result(TradeResult::BreakEven),
+            self.profit_in_r().round_dp(2).normalize(),
+            optional(self.win_rate()),
+            optional(self.expectancy()),
+            optional(self.profit_factor()),
+            self.max_drawdown().depth_in_r.round_dp(2).normalize(),
+            optional(self.sharpe_ratio()),
+        )?;
+        let costs = self.costs_in_r();
+        if !costs.is_zero() {
+            write!(f, ", costs: {}R", costs.round_dp(2).normalize())?;
+        }
+        let ambiguous = self.ambiguous_trades();
+        if ambiguous > 0 {
+            write!(f, ", ambiguous: {}", ambiguous)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::model::{
+        decimal::DecimalVec,
+        position::Position,
+        position_direction::PositionDirection,
+        trade::{FillKind, TradeExit},
+    };
+    use crate::parse_datetime;
+
+    // long trades risking 10 with the given target, each lasting an hour
+    fn trade(hour: i64, target: i32, result: TradeResult) -> Trade {
+        let open_time = parse_datetime("2022-09-30 00:00:00").unwrap() + Duration::hours(hour);
+        Trade::from_position(
+            Position {
+                direction: PositionDirection::Long,
+                open_time,
+                entry: DecimalVec::new(100),
+                sl: DecimalVec::new(90),
+                tp: DecimalVec::new(100 + target),
+                at_break_even: false,
+            },
+            open_time + Duration::hours(1),
+            result,
+        )
+    }
+
+    fn result(trades: Vec<(i32, TradeResult)>) -> BacktestResult {
+        BacktestResult {
+            trades: trades
+                .into_iter()
+                .enumerate()
+                .map(|(ind, (target, result))| trade(ind as i64, target, result))
+                .collect(),
+        }
+    }
+
+    fn sample() -> BacktestResult {
+        // R: +2, -1, -1, 0, +3, -1
+        result(vec![
+            (20, TradeResult::Winner),
+            (20, TradeResult::Expense),
+            (20, TradeResult::Expense),
+            (20, TradeResult::BreakEven),
+            (30, TradeResult::Winner),
+            (30, TradeResult::Expense),
+        ])
+    }
+
+    #[test]
+    fn test_empty_result() {
+        let r = result(vec![]);
+        assert_eq!(r.profit_in_r(), Decimal::ZERO);
+        assert_eq!(r.win_rate(), None);
+        assert_eq!(r.expectancy(), None);
+        assert_eq!(r.profit_factor(), None);
+        assert_eq!(r.sharpe_ratio(), None);
+        assert_eq!(r.max_drawdown().depth_in_r, Decimal::ZERO);
+        assert!(r.equity_curve().is_empty());
+    }
+
+    #[test]
+    fn test_win_rate_and_expectancy() {
+        let r = sample();
+        assert_eq!(r.win_rate(), Some(Decimal::from(2) / Decimal::from(6)));
+        assert_eq!(r.expectancy(), Some(Decimal::from(2) / Decimal::from(6)));
+    }
+
+    #[test]
+    fn test_profit_factor_and_averages() {
+        let r = sample();
+        assert_eq!(r.profit_factor(), Some(Decimal::from(5) / Decimal::from(3)));
+        assert_eq!(r.average_win(), Some("2.5".parse().unwrap()));
+        assert_eq!(r.average_loss(), Some(Decimal::from(1)));
+    }
+
+    #[test]
+    fn test_profit_factor_without_losses() {
+        let r = result(vec![(20, TradeResult::Winner)]);
+        assert_eq!(r.profit_factor(), None);
+        assert_eq!(r.average_loss(), None);
+    }
+
+    #[test]
+    fn test_consecutive_streaks() {
+        let r = sample();
+        assert_eq!(r.max_consecutive_wins(), 1);
+        assert_eq!(r.max_consecutive_losses(), 2);
+    }
+
+    #[test]
+    fn test_equity_curve() {
+        let curve = sample().equity_curve();
+        let expected: Vec<Decimal> = vec![2, 1, 0, 0, 3, 2]
+            .into_iter()
+            .map(Decimal::from)
+            .collect();
+        assert_eq!(curve, expected);
+    }
+
+    #[test]
+    fn test_max_drawdown_recovered() {
+        // peak 2 after the first trade, trough 0, back above the peak at the 5th trade
+        let dd = sample().max_drawdown();
+        assert_eq!(dd.depth_in_r, Decimal::from(2));
+        assert_eq!(dd.trades, 4);
+        assert_eq!(dd.duration, Duration::hours(4));
+    }
+
+    #[test]
+    fn test_max_drawdown_from_start_not_recovered() {
+        let r = result(vec![
+            (20, TradeResult::Expense),
+            (20, TradeResult::Expense),
+            (10, TradeResult::Winner),
+        ]);
+        let dd = r.max_drawdown();
+        assert_eq!(dd.depth_in_r, Decimal::from(2));
+        assert_eq!(dd.trades, 3);
+        assert_eq!(dd.duration, Duration::hours(3));
+    }
+
+    #[test]
+    fn test_sharpe_ratio() {
+        let r = result(vec![(20, TradeResult::Winner), (20, TradeResult::Expense)]);
+        // mean 0.5, sample std dev sqrt(4.5)
+        let sharpe = r.sharpe_ratio().unwrap();
+        assert_eq!(sharpe.round_dp(4), "0.2357".parse().unwrap());
+    }
+
+    #[test]
+    fn test_serialize_json() {
+        let r = result(vec![(20, TradeResult::Winner), (20, TradeResult::Expense)]);
+        let json = serde_json::to_value(&r).unwrap();
+
+        assert_eq!(json["trades"][0]["open_time"], "2022-09-30T00:00:00-04:00");
+        assert_eq!(json["trades"][0]["direction"], "Long");
+        assert_eq!(json["trades"][0]["result"], "Winner");
+        assert_eq!(json["trades"][1]["r_multiple"], "-1");
+        assert_eq!(json["number_of_trades"], 2);
+        assert_eq!(json["profit_in_r"], "1");
+        assert_eq!(json["profit_factor"], "2");
+        assert_eq!(json["max_drawdown"]["duration"], "PT3600S");
+        assert_eq!(json["equity_curve"], serde_json::json!(["2", "1"]));
+        assert_eq!(json["trades"][0]["realized_r"], serde_json::Value::Null);
+        assert_eq!(
+            json["trades"][1]["exits"],
+            serde_json::json!([{ "price": "90", "fraction": "1", "kind": "Stop" }])
+        );
+    }
+
+    #[test]
+    fn test_serialize_managed_exits() {
+        let mut r = result(vec![(20, TradeResult::Winner)]);
+        r.trades[0].realized_r = Some("0.75".parse().unwrap());
+        r.trades[0].exits = vec![
+            TradeExit {
+                price: DecimalVec::new(110),
+                fraction: "0.5".parse().unwrap(),
+                kind: FillKind::Limit,
+            },
+            TradeExit {
+                price: DecimalVec::new(105),
+                fraction: "0.5".parse().unwrap(),
+                kind: FillKind::Stop,
+            },
+        ];
+        let json = serde_json::to_value(&r).unwrap();
+
+        assert_eq!(json["trades"][0]["realized_r"], "0.75");
+        assert_eq!(json["trades"][0]["gross_r"], "0.75");
+        assert_eq!(
+            json["trades"][0]["exits"],
+            serde_json::json!([
+                { "price": "110", "fraction": "0.5", "kind": "Limit" },
+                { "price": "105", "fraction": "0.5", "kind": "Stop" },
+            ])
+        );
+
+        let mut out = vec![];
+        r.write_trade_log(&mut out).unwrap();
+        let log = String::from_utf8(out).unwrap();
+        assert_eq!(
+            log.lines().nth(1),
+            Some("Long,2022-09-30T00:00:00-04:00,2022-09-30T01:00:00-04:00,100,90,120,2,Winner,0.75,0.75,0,0.75,false")
+        );
+    }
+
+    #[test]
+    fn test_write_trade_log() {
+        let r = result(vec![(20, TradeResult::Winner), (20, TradeResult::Expense)]);
+        let mut out = vec![];
+        r.write_trade_log(&mut out).unwrap();
+
+        let log = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "direction,open_time,close_time,entry,sl,tp,rr,result,realized_r,gross_r,cost_in_r,r_multiple,ambiguous",
+                "Long,2022-09-30T00:00:00-04:00,2022-09-30T01:00:00-04:00,100,90,120,2,Winner,,2,0,2,false",
+                "Long,2022-09-30T01:00:00-04:00,2022-09-30T02:00:00-04:00,100,90,120,2,Expense,,-1,0,-1,false",
+            ]
+        );
+    }
+}
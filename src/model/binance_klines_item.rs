@@ -1,6 +1,7 @@
+use serde::ser::{Serialize, SerializeTuple, Serializer};
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub struct BinanceKlinesItem {
     pub open_time: u64,
@@ -8,11 +9,31 @@ pub struct BinanceKlinesItem {
     pub high: String,
     pub low: String,
     pub close: String,
-    volume: String,
+    pub volume: String,
     pub close_time: u64,
     quote_asset_volume: String,
-    number_of_trades: u64,
+    pub number_of_trades: u64,
     taker_buy_base_asset_volume: String,
     taker_buy_quote_asset_volume: String,
     ignore: String,
 }
+
+// the array binance sends, so written klines load like downloaded ones
+impl Serialize for BinanceKlinesItem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_tuple(12)?;
+        s.serialize_element(&self.open_time)?;
+        s.serialize_element(&self.open)?;
+        s.serialize_element(&self.high)?;
+        s.serialize_element(&self.low)?;
+        s.serialize_element(&self.close)?;
+        s.serialize_element(&self.volume)?;
+        s.serialize_element(&self.close_time)?;
+        s.serialize_element(&self.quote_asset_volume)?;
+        s.serialize_element(&self.number_of_trades)?;
+        s.serialize_element(&self.taker_buy_base_asset_volume)?;
+        s.serialize_element(&self.taker_buy_quote_asset_volume)?;
+        s.serialize_element(&self.ignore)?;
+        s.end()
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::{America::New_York, Tz};
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{
+    binance_klines_item::BinanceKlinesItem, candle_ny::CandleNY, candle_stick::CandleStick,
+    decimal::DecimalVec,
+};
+use crate::to_new_york_time;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Candle {
+    pub open_time: DateTime<Tz>,
+    pub close_time: DateTime<Tz>,
+    pub open: DecimalVec,
+    pub high: DecimalVec,
+    pub low: DecimalVec,
+    pub close: DecimalVec,
+    pub volume: DecimalVec,
+    pub number_of_trades: u64,
+}
+
+impl Candle {
+    pub fn bullish(self) -> bool {
+        self.close >= self.open
+    }
+    pub fn bearish(self) -> bool {
+        !self.bullish()
+    }
+}
+
+impl fmt::Debug for Candle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Candle")
+            .field(
+                "open_time",
+                &self.open_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            )
+            .field(
+                "close_time",
+                &self.close_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            )
+            .field("open", &self.open.0)
+            .field("close", &self.close.0)
+            .field("high", &self.high.0)
+            .field("low", &self.low.0)
+            .field("volume", &self.volume.0)
+            .finish()
+    }
+}
+
+fn millis_to_new_york_time(millis: u64) -> Result<DateTime<Tz>, Box<dyn Error>> {
+    DateTime::from_timestamp_millis(millis as i64)
+        .map(|t| t.with_timezone(&New_York))
+        .ok_or_else(|| Box::from(format!("Invalid timestamp: {}", millis)))
+}
+
+fn parse_decimal(s: &str) -> Result<DecimalVec, Box<dyn Error>> {
+    Ok(DecimalVec(Decimal::from_str(s)?))
+}
+
+impl TryFrom<&BinanceKlinesItem> for Candle {
+    type Error = Box<dyn Error>;
+
+    fn try_from(v: &BinanceKlinesItem) -> Result<Self, Self::Error> {
+        Ok(Candle {
+            open_time: millis_to_new_york_time(v.open_time)?,
+            close_time: millis_to_new_york_time(v.close_time)?,
+            open: parse_decimal(&v.open)?,
+            high: parse_decimal(&v.high)?,
+            low: parse_decimal(&v.low)?,
+            close: parse_decimal(&v.close)?,
+            volume: parse_decimal(&v.volume)?,
+            number_of_trades: v.number_of_trades,
+        })
+    }
+}
+
+impl From<CandleStick> for Candle {
+    fn from(c: CandleStick) -> Self {
+        Candle {
+            open_time: to_new_york_time(c.open_time),
+            close_time: to_new_york_time(c.close_time),
+            open: c.open,
+            high: c.high,
+            low: c.low,
+            close: c.close,
+            volume: DecimalVec::new(0),
+            number_of_trades: 0,
+        }
+    }
+}
+
+impl CandleNY {
+    // the csv rows only carry the open time, the close time is derived from the bar interval
+    pub fn to_candle(self, interval: Duration) -> Candle {
+        Candle {
+            open_time: self.open_time,
+            close_time: self.open_time + interval,
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            volume: DecimalVec::new(0),
+            number_of_trades: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_datetime;
+
+    #[test]
+    fn test_from_binance_klines_item() {
+        let item: BinanceKlinesItem = serde_json::from_str(
+            r#"[1713591000000, "3061.15", "3070.18", "3059.01", "3068.54", "2524.1539",
+                1713591899999, "7737222.04", 9274, "1095.87", "3359166.49", "0"]"#,
+        )
+        .unwrap();
+        let candle = Candle::try_from(&item).unwrap();
+
+        assert_eq!(
+            candle.open_time,
+            parse_datetime("2024-04-20 01:30:00").unwrap()
+        );
+        assert_eq!(
+            candle
+                .close_time
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string(),
+            "2024-04-20 01:44:59.999"
+        );
+        assert_eq!(
+            candle.open,
+            DecimalVec(Decimal::from_str("3061.15").unwrap())
+        );
+        assert_eq!(
+            candle.volume,
+            DecimalVec(Decimal::from_str("2524.1539").unwrap())
+        );
+        assert_eq!(candle.number_of_trades, 9274);
+    }
+
+    #[test]
+    fn test_from_binance_klines_item_invalid_price() {
+        let item: BinanceKlinesItem = serde_json::from_str(
+            r#"[1713591000000, "x", "3070.18", "3059.01", "3068.54", "2524.1539",
+                1713591899999, "7737222.04", 9274, "1095.87", "3359166.49", "0"]"#,
+        )
+        .unwrap();
+        assert!(Candle::try_from(&item).is_err());
+    }
+
+    #[test]
+    fn test_from_candle_ny() {
+        let open_time = parse_datetime("2022-09-30 09:30:00").unwrap();
+        let candle = CandleNY {
+            open_time,
+            open: DecimalVec::new(1),
+            high: DecimalVec::new(3),
+            low: DecimalVec::new(0),
+            close: DecimalVec::new(2),
+        }
+        .to_candle(Duration::minutes(1));
+
+        assert_eq!(candle.open_time, open_time);
+        assert_eq!(
+            candle.close_time,
+            parse_datetime("2022-09-30 09:31:00").unwrap()
+        );
+        assert!(candle.bullish());
+    }
+}
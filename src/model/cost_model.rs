@@ -0,0 +1,297 @@
+use anyhow::{bail, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{
+    position_direction::PositionDirection,
+    trade::{FillKind, Trade},
+};
+
+// What a round trip costs. Entries, stops and time exits fill as market orders (taker), take
+// profits as resting limit orders (maker). Every exit is charged at its own price and size, and
+// the ones filled as market orders, break even and trailing stops included, also slip.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CostModel {
+    // percent of the fill price, e.g. 0.02 for 0.02%, a negative maker fee is a rebate
+    pub maker_fee_pct: Decimal,
+    pub taker_fee_pct: Decimal,
+    // currency per contract per fill, charged on the entry and on every exit
+    pub commission: Decimal,
+    // currency per price point of one contract, turns the commission into price points
+    pub point_value: Decimal,
+    pub slippage: Option<Slippage>,
+    // percent of the entry price per 8 hour funding period, paid by longs and received by
+    // shorts when positive, charged pro rata on the holding time
+    pub funding_rate_pct: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Slippage {
+    Ticks { ticks: Decimal, tick_size: Decimal },
+    // percent of the stop price
+    Pct { pct: Decimal },
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            maker_fee_pct: Decimal::ZERO,
+            taker_fee_pct: Decimal::ZERO,
+            commission: Decimal::ZERO,
+            point_value: Decimal::ONE,
+            slippage: None,
+            funding_rate_pct: Decimal::ZERO,
+        }
+    }
+}
+
+impl CostModel {
+    pub fn validate(&self) -> Result<()> {
+        // maker fees may be negative, that's a rebate
+        if self.taker_fee_pct.is_sign_negative() || self.commission.is_sign_negative() {
+            bail!("taker fee and commission can't be negative");
+        }
+        if self.point_value <= Decimal::ZERO {
+            bail!("point_value must be positive");
+        }
+        match self.slippage {
+            Some(Slippage::Ticks { ticks, tick_size })
+                if ticks.is_sign_negative() || tick_size <= Decimal::ZERO =>
+            {
+                bail!("slippage needs non negative ticks and a positive tick_size")
+            }
+            Some(Slippage::Pct { pct }) if pct.is_sign_negative() => {
+                bail!("slippage can't be negative")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // fills the trade's cost_in_r from its entry, exits and risk
+    pub fn apply(&self, mut trade: Trade) -> Trade {
+        let risk = (trade.entry.0 - trade.sl.0).abs();
+        if risk.is_zero() {
+            return trade;
+        }
+
+        let hundred = Decimal::ONE_HUNDRED;
+        let entry_fee = trade.entry.0 * self.taker_fee_pct / hundred;
+        let exits = trade.exits();
+        let exit_cost: Decimal = exits
+            .iter()
+            .map(|exit| {
+                exit.fraction
+                    * match exit.kind {
+                        FillKind::Limit => exit.price.0 * self.maker_fee_pct / hundred,
+                        FillKind::Stop | FillKind::Market => {
+                            self.taker_cost(trade.direction, exit.price.0)
+                        }
+                    }
+            })
+            .sum();
+        let fills = Decimal::from(1 + exits.len());
+        let commission = fills * self.commission / self.point_value;
+        let periods = Decimal::from((trade.close_time - trade.open_time).num_seconds())
+            / Decimal::from(8 * 60 * 60);
+        let funding = match trade.direction {
+            PositionDirection::Long => Decimal::ONE,
+            PositionDirection::Short => Decimal::NEGATIVE_ONE,
+        } * trade.entry.0
+            * self.funding_rate_pct
+            / hundred
+            * periods;
+
+        trade.cost_in_r = (entry_fee + exit_cost + commission + funding) / risk;
+        trade
+    }
+
+    // slippage and fee of a stop or market exit at `price`, the fee is paid on the slipped fill
+    fn taker_cost(&self, direction: PositionDirection, price: Decimal) -> Decimal {
+        let slippage = match self.slippage {
+            Some(Slippage::Ticks { ticks, tick_size }) => ticks * tick_size,
+            Some(Slippage::Pct { pct }) => price * pct / Decimal::ONE_HUNDRED,
+            None => Decimal::ZERO,
+        };
+        let fill = match direction {
+            PositionDirection::Long => price - slippage,
+            PositionDirection::Short => price + slippage,
+        };
+        slippage + fill * self.taker_fee_pct / Decimal::ONE_HUNDRED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::model::{
+        decimal::DecimalVec, position::Position, trade::TradeExit, trade_result::TradeResult,
+    };
+    use crate::parse_datetime;
+
+    // risking 10 from an entry at 100, the target is 120 for a long and 80 for a short
+    fn trade(direction: PositionDirection, result: TradeResult, hours: i64) -> Trade {
+        let open_time = parse_datetime("2022-09-30 00:00:00").unwrap();
+        let (sl, tp) = match direction {
+            PositionDirection::Long => (90, 120),
+            PositionDirection::Short => (110, 80),
+        };
+        Trade::from_position(
+            Position {
+                direction,
+                open_time,
+                entry: DecimalVec::new(100),
+                sl: DecimalVec::new(sl),
+                tp: DecimalVec::new(tp),
+                at_break_even: false,
+            },
+            open_time + Duration::hours(hours),
+            result,
+        )
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_free_model_keeps_gross() {
+        let t = CostModel::default().apply(trade(PositionDirection::Long, TradeResult::Winner, 1));
+        assert_eq!(t.cost_in_r, Decimal::ZERO);
+        assert_eq!(t.net_r(), t.gross_r());
+    }
+
+    #[test]
+    fn test_fees_on_winner() {
+        let costs = CostModel {
+            maker_fee_pct: d("0.5"),
+            taker_fee_pct: d("1"),
+            ..CostModel::default()
+        };
+        let t = costs.apply(trade(PositionDirection::Long, TradeResult::Winner, 1));
+        // 1 taker fee on the entry at 100 and 0.6 maker fee on the exit at 120, risk is 10
+        assert_eq!(t.cost_in_r, d("0.16"));
+        assert_eq!(t.gross_r(), Decimal::from(2));
+        assert_eq!(t.net_r(), d("1.84"));
+        assert_eq!(t.r_multiple(), t.net_r());
+    }
+
+    #[test]
+    fn test_stop_slippage_and_commission() {
+        let costs = CostModel {
+            commission: d("5"),
+            point_value: d("10"),
+            slippage: Some(Slippage::Ticks {
+                ticks: d("4"),
+                tick_size: d("0.25"),
+            }),
+            ..CostModel::default()
+        };
+        let t = costs.apply(trade(PositionDirection::Short, TradeResult::Expense, 1));
+        // 1 point of slippage and 2 * 5 / 10 points of commission
+        assert_eq!(t.cost_in_r, d("0.2"));
+        assert_eq!(t.net_r(), d("-1.2"));
+
+        // take profits don't slip
+        let t = costs.apply(trade(PositionDirection::Short, TradeResult::Winner, 1));
+        assert_eq!(t.cost_in_r, d("0.1"));
+    }
+
+    #[test]
+    fn test_commission_per_fill() {
+        let costs = CostModel {
+            commission: d("5"),
+            point_value: d("10"),
+            ..CostModel::default()
+        };
+        let exit = |price| TradeExit {
+            price: DecimalVec::new(price),
+            fraction: d("0.5"),
+            kind: FillKind::Limit,
+        };
+        let mut t = trade(PositionDirection::Long, TradeResult::Winner, 1);
+        t.realized_r = Some(d("1.5"));
+        t.exits = vec![exit(110), exit(120)];
+        // the entry and two exits, 3 * 5 / 10 points
+        assert_eq!(costs.apply(t).cost_in_r, d("0.15"));
+    }
+
+    #[test]
+    fn test_percent_slippage_on_break_even() {
+        let costs = CostModel {
+            taker_fee_pct: d("0.1"),
+            slippage: Some(Slippage::Pct { pct: d("1") }),
+            ..CostModel::default()
+        };
+        let t = costs.apply(trade(PositionDirection::Long, TradeResult::BreakEven, 1));
+        // entry fee 0.1, the stop at 100 fills at 99 and pays 0.099
+        assert_eq!(t.cost_in_r, d("0.1199"));
+    }
+
+    #[test]
+    fn test_each_exit_at_its_own_price() {
+        let costs = CostModel {
+            maker_fee_pct: d("0.5"),
+            taker_fee_pct: d("1"),
+            slippage: Some(Slippage::Ticks {
+                ticks: Decimal::ONE,
+                tick_size: Decimal::ONE,
+            }),
+            ..CostModel::default()
+        };
+        let exit = |price, fraction: &str, kind| TradeExit {
+            price: DecimalVec::new(price),
+            fraction: d(fraction),
+            kind,
+        };
+        // half taken at 110, the rest stopped out by a trailing stop at 105
+        let mut t = trade(PositionDirection::Long, TradeResult::Winner, 1);
+        t.realized_r = Some(d("0.75"));
+        t.exits = vec![
+            exit(110, "0.5", FillKind::Limit),
+            exit(105, "0.5", FillKind::Stop),
+        ];
+        let t = costs.apply(t);
+        // the entry fee 1, half of the maker fee 0.55 and half of 1 slippage and the fee 1.04
+        // on the fill at 104
+        assert_eq!(t.cost_in_r, d("0.2295"));
+        assert_eq!(t.net_r(), d("0.5205"));
+
+        // a time exit fills as a market order and slips
+        let mut t = trade(PositionDirection::Long, TradeResult::Winner, 1);
+        t.realized_r = Some(d("0.3"));
+        t.exits = vec![exit(103, "1", FillKind::Market)];
+        assert_eq!(costs.apply(t).cost_in_r, d("0.302"));
+    }
+
+    #[test]
+    fn test_funding_by_holding_time() {
+        let costs = CostModel {
+            funding_rate_pct: d("0.01"),
+            ..CostModel::default()
+        };
+        // two funding periods of 0.01 on 100
+        let long = costs.apply(trade(PositionDirection::Long, TradeResult::Winner, 16));
+        assert_eq!(long.cost_in_r, d("0.002"));
+        let short = costs.apply(trade(PositionDirection::Short, TradeResult::Winner, 16));
+        assert_eq!(short.cost_in_r, d("-0.002"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(CostModel::default().validate().is_ok());
+        let rebate = CostModel {
+            maker_fee_pct: d("-0.01"),
+            ..CostModel::default()
+        };
+        assert!(rebate.validate().is_ok());
+        let no_point_value = CostModel {
+            point_value: Decimal::ZERO,
+            ..CostModel::default()
+        };
+        assert!(no_point_value.validate().is_err());
+    }
+}
@@ -3,9 +3,9 @@ use std::ops::{Add, Div, Sub};
 use charming::datatype::NumericValue;
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialOrd, PartialEq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialOrd, PartialEq)]
 pub struct DecimalVec(pub Decimal);
 
 impl DecimalVec {
@@ -1,10 +1,13 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+
 use super::position_direction::PositionDirection;
 use crate::model::decimal::DecimalVec;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Position {
     pub direction: PositionDirection,
-    pub open_time: i64,
+    pub open_time: DateTime<Tz>,
     pub entry: DecimalVec,
     pub sl: DecimalVec,
     pub tp: DecimalVec,
@@ -1,5 +1,6 @@
 use chrono::NaiveTime;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Session {
     pub start: NaiveTime,
     pub end: NaiveTime,
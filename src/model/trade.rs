@@ -1,19 +1,53 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
 use std::fmt;
 
 use crate::model::decimal::DecimalVec;
-use crate::to_new_york_time;
 
 use super::{position::Position, position_direction::PositionDirection, trade_result::TradeResult};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Trade {
-    direction: PositionDirection,
-    open_time: i64,
-    close_time: i64,
-    entry: DecimalVec,
-    sl: DecimalVec,
-    tp: DecimalVec,
+    pub direction: PositionDirection,
+    pub open_time: DateTime<Tz>,
+    pub close_time: DateTime<Tz>,
+    pub entry: DecimalVec,
+    pub sl: DecimalVec,
+    pub tp: DecimalVec,
     pub result: TradeResult,
+    // fees, commission, slippage and funding in R, zero until a cost model is applied
+    pub cost_in_r: Decimal,
+    // the exit candle reached both the stop and the target, the result depends on the
+    // ambiguity policy
+    pub ambiguous: bool,
+    // the size weighted R of all exits of a managed trade, None for a trade that exits whole
+    // at the stop, the target or break even
+    pub realized_r: Option<Decimal>,
+    // the fills that closed a managed trade, oldest first, empty for a trade that exits whole
+    // at the stop, the target or break even
+    pub exits: Vec<TradeExit>,
+}
+
+// how an exit filled, decides the fee and whether it slips
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum FillKind {
+    // a resting take profit
+    Limit,
+    Stop,
+    // a time exit at the close of a candle
+    Market,
+}
+
+// part of a position closed at one price
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TradeExit {
+    pub price: DecimalVec,
+    // part of the starting size
+    pub fraction: Decimal,
+    pub kind: FillKind,
 }
 
 impl Trade {
@@ -23,16 +57,51 @@ impl Trade {
             PositionDirection::Long => (self.tp - self.entry) / (self.entry - self.sl),
         }
     }
+
+    // the realized result in R before costs, at the exact entry, sl and tp prices unless
+    // trade management closed it elsewhere
+    pub fn gross_r(&self) -> Decimal {
+        if let Some(r) = self.realized_r {
+            return r;
+        }
+        match self.result {
+            TradeResult::Winner => self.rr().0,
+            TradeResult::Expense => Decimal::from(-1),
+            TradeResult::BreakEven => Decimal::from(0),
+        }
+    }
+
+    // the recorded exits, or the one the result implies
+    pub fn exits(&self) -> Vec<TradeExit> {
+        if !self.exits.is_empty() {
+            return self.exits.clone();
+        }
+        let (price, kind) = match self.result {
+            TradeResult::Winner => (self.tp, FillKind::Limit),
+            TradeResult::Expense => (self.sl, FillKind::Stop),
+            TradeResult::BreakEven => (self.entry, FillKind::Stop),
+        };
+        vec![TradeExit {
+            price,
+            fraction: Decimal::ONE,
+            kind,
+        }]
+    }
+
+    pub fn net_r(&self) -> Decimal {
+        self.gross_r() - self.cost_in_r
+    }
+
+    // the realized result in R after costs, what the statistics are built on
+    pub fn r_multiple(&self) -> Decimal {
+        self.net_r()
+    }
 }
 
 impl fmt::Debug for Trade {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let o = to_new_york_time(self.open_time.clone())
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
-        let c = to_new_york_time(self.close_time.clone())
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
+        let o = self.open_time.format("%Y-%m-%d %H:%M:%S").to_string();
+        let c = self.close_time.format("%Y-%m-%d %H:%M:%S").to_string();
 
         f.debug_struct("Trade")
             .field("direction", &self.direction)
@@ -43,12 +112,40 @@ impl fmt::Debug for Trade {
             .field("tp", &self.tp.0)
             .field("rr", &self.rr().0)
             .field("result", &self.result)
+            .field("cost_in_r", &self.cost_in_r)
+            .field("ambiguous", &self.ambiguous)
             .finish()
     }
 }
 
+// times as ISO-8601 with the New York offset, prices and R as decimal strings
+impl Serialize for Trade {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Trade", 14)?;
+        s.serialize_field("direction", &self.direction)?;
+        s.serialize_field("open_time", &self.open_time)?;
+        s.serialize_field("close_time", &self.close_time)?;
+        s.serialize_field("entry", &self.entry)?;
+        s.serialize_field("sl", &self.sl)?;
+        s.serialize_field("tp", &self.tp)?;
+        s.serialize_field("rr", &self.rr())?;
+        s.serialize_field("result", &self.result)?;
+        s.serialize_field("realized_r", &self.realized_r)?;
+        s.serialize_field("gross_r", &self.gross_r())?;
+        s.serialize_field("cost_in_r", &self.cost_in_r)?;
+        s.serialize_field("r_multiple", &self.r_multiple())?;
+        s.serialize_field("ambiguous", &self.ambiguous)?;
+        s.serialize_field("exits", &self.exits())?;
+        s.end()
+    }
+}
+
 impl Trade {
-    pub(crate) fn from_position(position: Position, close_time: i64, result: TradeResult) -> Trade {
+    pub(crate) fn from_position(
+        position: Position,
+        close_time: DateTime<Tz>,
+        result: TradeResult,
+    ) -> Trade {
         Trade {
             direction: position.direction,
             open_time: position.open_time,
@@ -57,6 +154,10 @@ impl Trade {
             sl: position.sl,
             tp: position.tp,
             result,
+            cost_in_r: Decimal::ZERO,
+            ambiguous: false,
+            realized_r: None,
+            exits: vec![],
         }
     }
 }
@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+// How an open position is managed after the entry. Stops only ever move towards the price.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct TradeManagement {
+    // move the stop to the entry once the price has run this many R
+    pub break_even_r: Option<Decimal>,
+    pub trailing: Option<Trailing>,
+    // partial exits on the way, what's left runs to the position's target
+    pub take_profits: Vec<TakeProfit>,
+    // close what's left at the close of the first candle this many minutes after the entry
+    pub time_exit_min: Option<i64>,
+}
+
+// `{ type = "atr", period = 14, multiple = 2 }`, `{ type = "swing" }` or `{ type = "fixed-r", r = 1 }`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Trailing {
+    // `multiple` average true ranges of `period` candles behind the best price
+    Atr { period: usize, multiple: Decimal },
+    // behind the last confirmed swing low of a long or swing high of a short
+    Swing,
+    // `r` R behind the best price
+    FixedR { r: Decimal },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TakeProfit {
+    // distance from the entry in R
+    pub r: Decimal,
+    // part of the starting size closed there, e.g. 0.5
+    pub fraction: Decimal,
+}
+
+impl TradeManagement {
+    pub fn validate(&self) -> Result<()> {
+        if self.break_even_r.is_some_and(|r| r <= Decimal::ZERO) {
+            bail!("break_even_r must be positive");
+        }
+        match self.trailing {
+            Some(Trailing::Atr { period, multiple })
+                if period == 0 || multiple <= Decimal::ZERO =>
+            {
+                bail!("atr trailing needs a positive period and multiple")
+            }
+            Some(Trailing::FixedR { r }) if r <= Decimal::ZERO => {
+                bail!("fixed-r trailing needs a positive r")
+            }
+            _ => {}
+        }
+        if self
+            .take_profits
+            .iter()
+            .any(|tp| tp.r <= Decimal::ZERO || tp.fraction <= Decimal::ZERO)
+        {
+            bail!("take profits need a positive r and fraction");
+        }
+        let fractions: Decimal = self.take_profits.iter().map(|tp| tp.fraction).sum();
+        if fractions > Decimal::ONE {
+            bail!("take profit fractions add up to more than 1");
+        }
+        if self.time_exit_min.is_some_and(|min| min <= 0) {
+            bail!("time_exit_min must be positive");
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,16 @@
 use super::backtest_result::BacktestResult;
+use crate::engine::{Lookahead, Runner};
 
 pub trait TradingModel {
     fn execute(&self) -> BacktestResult;
+
+    // replays every candle to find reads of future candles, None for models not on the engine
+    fn check_lookahead(&self) -> Option<Vec<Lookahead>> {
+        None
+    }
+
+    // the run a candle at a time, for sharing a timeline with other models
+    fn runner(&self) -> Option<Runner<'_>> {
+        None
+    }
 }
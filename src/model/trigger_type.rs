@@ -1,4 +1,7 @@
-#[derive(Copy, Clone, Debug)]
+use serde::Deserialize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum TriggerType {
     Close,
     Wick,
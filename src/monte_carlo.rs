@@ -0,0 +1,309 @@
+use anyhow::{bail, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::fmt;
+
+use crate::model::backtest_result::BacktestResult;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resampling {
+    // same trades in a random order, only the path (drawdowns) changes
+    Shuffle,
+    // draws with replacement, the final R and the expectancy change too
+    Bootstrap,
+}
+
+// fixed fractional sizing used for the risk of ruin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ruin {
+    // fraction of the account risked per trade, e.g. 0.01
+    pub risk_per_trade: Decimal,
+    // fraction of the starting account at which the account counts as ruined, e.g. 0.5
+    pub ruin_level: Decimal,
+}
+
+pub struct MonteCarlo {
+    pub iterations: usize,
+    pub resampling: Resampling,
+    pub seed: u64,
+    pub ruin: Option<Ruin>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Percentiles {
+    pub p5: Decimal,
+    pub p25: Decimal,
+    pub p50: Decimal,
+    pub p75: Decimal,
+    pub p95: Decimal,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MonteCarloResult {
+    pub iterations: usize,
+    pub max_drawdown: Percentiles,
+    pub final_r: Percentiles,
+    // 95% interval of the bootstrapped mean R per trade
+    pub expectancy: (Decimal, Decimal),
+    // share of the iterations that hit the ruin level
+    pub risk_of_ruin: Option<Decimal>,
+}
+
+impl MonteCarlo {
+    pub fn run(&self, result: &BacktestResult) -> Result<MonteCarloResult> {
+        if self.iterations == 0 {
+            bail!("iterations must be positive");
+        }
+        if result.trades.is_empty() {
+            bail!("no trades to resample");
+        }
+        if let Some(ruin) = self.ruin {
+            if ruin.risk_per_trade <= Decimal::ZERO || ruin.risk_per_trade >= Decimal::ONE {
+                bail!("risk per trade must be between 0 and 1");
+            }
+            if ruin.ruin_level <= Decimal::ZERO || ruin.ruin_level >= Decimal::ONE {
+                bail!("ruin level must be between 0 and 1");
+            }
+        }
+
+        let r: Vec<Decimal> = result.trades.iter().map(|t| t.r_multiple()).collect();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut drawdowns = Vec::with_capacity(self.iterations);
+        let mut finals = Vec::with_capacity(self.iterations);
+        let mut means = Vec::with_capacity(self.iterations);
+        let mut ruined = 0;
+        let mut sample = r.clone();
+
+        for _ in 0..self.iterations {
+            match self.resampling {
+                Resampling::Shuffle => sample.shuffle(&mut rng),
+                Resampling::Bootstrap => bootstrap(&r, &mut sample, &mut rng),
+            }
+            let (total, drawdown) = path(&sample);
+            drawdowns.push(drawdown);
+            finals.push(total);
+
+            // a reordering keeps the mean, so the interval always comes from a bootstrap
+            let mean = match self.resampling {
+                Resampling::Bootstrap => total / Decimal::from(sample.len()),
+                Resampling::Shuffle => {
+                    let mut draw = vec![Decimal::ZERO; r.len()];
+                    bootstrap(&r, &mut draw, &mut rng);
+                    draw.iter().sum::<Decimal>() / Decimal::from(draw.len())
+                }
+            };
+            means.push(mean);
+
+            if let Some(ruin) = self.ruin {
+                if is_ruined(&sample, ruin) {
+                    ruined += 1;
+                }
+            }
+        }
+
+        means.sort();
+        Ok(MonteCarloResult {
+            iterations: self.iterations,
+            max_drawdown: percentiles(drawdowns),
+            final_r: percentiles(finals),
+            expectancy: (percentile(&means, 2.5), percentile(&means, 97.5)),
+            risk_of_ruin: self
+                .ruin
+                .map(|_| Decimal::from(ruined) / Decimal::from(self.iterations)),
+        })
+    }
+}
+
+fn bootstrap(r: &[Decimal], sample: &mut [Decimal], rng: &mut StdRng) {
+    for x in sample.iter_mut() {
+        *x = r[rng.gen_range(0..r.len())];
+    }
+}
+
+// final R and max drawdown in R of a trade sequence
+fn path(r: &[Decimal]) -> (Decimal, Decimal) {
+    let mut equity = Decimal::ZERO;
+    let mut peak = Decimal::ZERO;
+    let mut drawdown = Decimal::ZERO;
+    for x in r {
+        equity += *x;
+        peak = peak.max(equity);
+        drawdown = drawdown.max(peak - equity);
+    }
+    (equity, drawdown)
+}
+
+fn is_ruined(r: &[Decimal], ruin: Ruin) -> bool {
+    let mut account = Decimal::ONE;
+    for x in r {
+        account += account * ruin.risk_per_trade * *x;
+        if account <= ruin.ruin_level {
+            return true;
+        }
+    }
+    false
+}
+
+// nearest rank on sorted values
+fn percentile(sorted: &[Decimal], p: f64) -> Decimal {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn percentiles(mut values: Vec<Decimal>) -> Percentiles {
+    values.sort();
+    Percentiles {
+        p5: percentile(&values, 5.0),
+        p25: percentile(&values, 25.0),
+        p50: percentile(&values, 50.0),
+        p75: percentile(&values, 75.0),
+        p95: percentile(&values, 95.0),
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = |d: Decimal| d.round_dp(2).normalize();
+        write!(
+            f,
+            "p5 {}R, p25 {}R, p50 {}R, p75 {}R, p95 {}R",
+            r(self.p5),
+            r(self.p25),
+            r(self.p50),
+            r(self.p75),
+            r(self.p95)
+        )
+    }
+}
+
+impl fmt::Display for MonteCarloResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "iterations: {}", self.iterations)?;
+        writeln!(f, "max drawdown: {}", self.max_drawdown)?;
+        writeln!(f, "final: {}", self.final_r)?;
+        write!(
+            f,
+            "expectancy 95%: {}R to {}R",
+            self.expectancy.0.round_dp(2).normalize(),
+            self.expectancy.1.round_dp(2).normalize()
+        )?;
+        if let Some(risk) = self.risk_of_ruin {
+            write!(
+                f,
+                "\nrisk of ruin: {}%",
+                (risk * Decimal::ONE_HUNDRED).round_dp(2).normalize()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::model::{
+        decimal::DecimalVec, position::Position, position_direction::PositionDirection,
+        trade::Trade, trade_result::TradeResult,
+    };
+    use crate::parse_datetime;
+
+    // R: +2 and -1 alternating, so 6R after 12 trades
+    fn result() -> BacktestResult {
+        let open_time = parse_datetime("2022-09-30 00:00:00").unwrap();
+        let trades = (0..12)
+            .map(|ind| {
+                Trade::from_position(
+                    Position {
+                        direction: PositionDirection::Long,
+                        open_time: open_time + Duration::hours(ind),
+                        entry: DecimalVec::new(100),
+                        sl: DecimalVec::new(90),
+                        tp: DecimalVec::new(120),
+                        at_break_even: false,
+                    },
+                    open_time + Duration::hours(ind + 1),
+                    if ind % 2 == 0 {
+                        TradeResult::Winner
+                    } else {
+                        TradeResult::Expense
+                    },
+                )
+            })
+            .collect();
+        BacktestResult { trades }
+    }
+
+    fn monte_carlo(resampling: Resampling) -> MonteCarlo {
+        MonteCarlo {
+            iterations: 500,
+            resampling,
+            seed: 7,
+            ruin: None,
+        }
+    }
+
+    #[test]
+    fn test_shuffle_keeps_final_r() {
+        let mc = monte_carlo(Resampling::Shuffle).run(&result()).unwrap();
+
+        assert_eq!(mc.final_r.p5, Decimal::from(6));
+        assert_eq!(mc.final_r.p95, Decimal::from(6));
+        // at best every loss follows a win, at worst all six losses are in a row
+        assert!(mc.max_drawdown.p5 >= Decimal::ONE);
+        assert!(mc.max_drawdown.p95 <= Decimal::from(6));
+        assert!(mc.max_drawdown.p5 <= mc.max_drawdown.p95);
+    }
+
+    #[test]
+    fn test_bootstrap_varies_final_r() {
+        let mc = monte_carlo(Resampling::Bootstrap).run(&result()).unwrap();
+
+        assert!(mc.final_r.p5 < Decimal::from(6));
+        assert!(mc.final_r.p95 > Decimal::from(6));
+        assert!(mc.expectancy.0 < Decimal::from_str_exact("0.5").unwrap());
+        assert!(mc.expectancy.1 > Decimal::from_str_exact("0.5").unwrap());
+    }
+
+    #[test]
+    fn test_seed_reproduces_run() {
+        let a = monte_carlo(Resampling::Bootstrap).run(&result()).unwrap();
+        let b = monte_carlo(Resampling::Bootstrap).run(&result()).unwrap();
+        assert_eq!(a.final_r, b.final_r);
+        assert_eq!(a.max_drawdown, b.max_drawdown);
+        assert_eq!(a.expectancy, b.expectancy);
+    }
+
+    #[test]
+    fn test_risk_of_ruin() {
+        let mut mc = monte_carlo(Resampling::Shuffle);
+        mc.ruin = Some(Ruin {
+            risk_per_trade: Decimal::from_str_exact("0.1").unwrap(),
+            ruin_level: Decimal::from_str_exact("0.6").unwrap(),
+        });
+        let risky = mc.run(&result()).unwrap().risk_of_ruin.unwrap();
+
+        mc.ruin = Some(Ruin {
+            risk_per_trade: Decimal::from_str_exact("0.01").unwrap(),
+            ruin_level: Decimal::from_str_exact("0.6").unwrap(),
+        });
+        let safe = mc.run(&result()).unwrap().risk_of_ruin.unwrap();
+
+        // six losses of 10% in a row leave 0.9^6 = 0.53 of the account
+        assert!(risky > Decimal::ZERO);
+        assert_eq!(safe, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let empty = BacktestResult { trades: vec![] };
+        assert!(monte_carlo(Resampling::Shuffle).run(&empty).is_err());
+
+        let mut mc = monte_carlo(Resampling::Shuffle);
+        mc.iterations = 0;
+        assert!(mc.run(&result()).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::engine::{self, CandleModel, CandleView, Lookahead, OrderBook, Runner};
+use crate::model::{
+    ambiguity_policy::AmbiguityPolicy, backtest_result::BacktestResult, candle::Candle,
+    cost_model::CostModel, decimal::DecimalVec, trade_management::TradeManagement,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MtfError {
+    // a higher timeframe bar without lower timeframe candles inside it
+    MissingLtf { htf_open_time: DateTime<Tz> },
+    // a lower timeframe candle that starts in one higher timeframe bar and ends in the next
+    Misaligned { ltf_open_time: DateTime<Tz> },
+    // no lower timeframe candle inside the bar matched
+    NoMatch { htf_open_time: DateTime<Tz> },
+}
+
+impl fmt::Display for MtfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = |t: &DateTime<Tz>| t.format("%Y-%m-%d %H:%M").to_string();
+        match self {
+            MtfError::MissingLtf { htf_open_time } => write!(
+                f,
+                "no lower timeframe candles in the bar opening at {}",
+                time(htf_open_time)
+            ),
+            MtfError::Misaligned { ltf_open_time } => write!(
+                f,
+                "the lower timeframe candle opening at {} crosses a higher timeframe bar",
+                time(ltf_open_time)
+            ),
+            MtfError::NoMatch { htf_open_time } => write!(
+                f,
+                "no matching lower timeframe candle in the bar opening at {}",
+                time(htf_open_time)
+            ),
+        }
+    }
+}
+
+impl Error for MtfError {}
+
+// What a strategy sees on every lower timeframe candle: only closed higher timeframe bars and
+// the lower timeframe candles of the current bar up to now.
+pub struct MtfView<'a> {
+    // closed higher timeframe bars, oldest first
+    pub htf: &'a [Candle],
+    // the current higher timeframe bar built from `ltf`
+    pub forming: Candle,
+    // lower timeframe candles of the current bar, the last one just closed
+    pub ltf: &'a [Candle],
+}
+
+impl MtfView<'_> {
+    pub fn actual(&self) -> Candle {
+        *self.ltf.last().expect("a view has at least one ltf candle")
+    }
+}
+
+pub trait MtfModel {
+    // called on every lower timeframe candle while flat to place or cancel orders
+    fn on_ltf(&mut self, view: &MtfView, orders: &mut OrderBook);
+
+    // called once for every higher timeframe bar without lower timeframe candles, before the
+    // first candle after it, flat or not. The bar is skipped, its candle is still in the
+    // closed bars of the views after it.
+    fn on_missing(&mut self, _error: &MtfError) {}
+}
+
+impl<M: MtfModel + ?Sized> MtfModel for &mut M {
+    fn on_ltf(&mut self, view: &MtfView, orders: &mut OrderBook) {
+        (**self).on_ltf(view, orders)
+    }
+
+    fn on_missing(&mut self, error: &MtfError) {
+        (**self).on_missing(error)
+    }
+}
+
+// a higher and a lower timeframe series aligned bar by bar
+pub struct Mtf {
+    htf: Vec<Candle>,
+    // shared by every model built on the same lower timeframe data
+    ltf: Arc<Vec<Candle>>,
+    // the lower timeframe candles of each higher timeframe bar, empty for a missing one
+    ranges: Vec<Range<usize>>,
+}
+
+impl Mtf {
+    // lower timeframe candles outside every higher timeframe bar are ignored, higher timeframe
+    // bars without lower timeframe candles are skipped and listed by `missing`
+    pub fn new(htf: Vec<Candle>, ltf: impl Into<Arc<Vec<Candle>>>) -> Result<Mtf, MtfError> {
+        let ltf = ltf.into();
+        let mut ranges = Vec::with_capacity(htf.len());
+        for bar in &htf {
+            let start = ltf.partition_point(|c| c.open_time < bar.open_time);
+            let end = ltf.partition_point(|c| c.open_time <= bar.close_time);
+            if let Some(c) = ltf[start..end]
+                .iter()
+                .find(|c| c.close_time > bar.close_time)
+            {
+                return Err(MtfError::Misaligned {
+                    ltf_open_time: c.open_time,
+                });
+            }
+            ranges.push(start..end);
+        }
+        Ok(Mtf { htf, ltf, ranges })
+    }
+
+    pub fn htf(&self) -> &[Candle] {
+        &self.htf
+    }
+
+    pub fn ltf(&self, htf_ind: usize) -> &[Candle] {
+        &self.ltf[self.ranges[htf_ind].clone()]
+    }
+
+    // the higher timeframe bars without lower timeframe candles, oldest first
+    pub fn missing(&self) -> Vec<MtfError> {
+        self.htf
+            .iter()
+            .zip(&self.ranges)
+            .filter(|(_, range)| range.is_empty())
+            .map(|(bar, _)| MtfError::MissingLtf {
+                htf_open_time: bar.open_time,
+            })
+            .collect()
+    }
+
+    // runs the model on the lower timeframe candles through the engine, one position at a time
+    pub fn run<M: MtfModel>(
+        &self,
+        model: &mut M,
+        management: &TradeManagement,
+        costs: &CostModel,
+        ambiguity: &AmbiguityPolicy,
+    ) -> BacktestResult {
+        let mut runner = self.runner(model, management, costs, ambiguity);
+        while runner.next_close().is_some() {
+            runner.step(&mut |_| true);
+        }
+        runner.finish()
+    }
+
+    // the same run a candle at a time
+    pub fn runner<'a, M: MtfModel + 'a>(
+        &'a self,
+        model: M,
+        management: &TradeManagement,
+        costs: &CostModel,
+        ambiguity: &AmbiguityPolicy,
+    ) -> Runner<'a> {
+        Runner::new(&self.ltf, self.on_ltf(model), management, costs, ambiguity)
+    }
+
+    // `engine::check_lookahead` on the lower timeframe candles, `build` gets the ones the model
+    // may know about and the higher timeframe bars reach it only once closed
+    pub fn check_lookahead<M: MtfModel>(
+        &self,
+        build: impl Fn(&[Candle]) -> M,
+        management: &TradeManagement,
+        costs: &CostModel,
+        ambiguity: &AmbiguityPolicy,
+    ) -> Vec<Lookahead> {
+        engine::check_lookahead(
+            &self.ltf,
+            |cut| self.on_ltf(build(cut)),
+            management,
+            costs,
+            ambiguity,
+        )
+    }
+
+    fn on_ltf<M: MtfModel>(&self, model: M) -> OnLtf<'_, M> {
+        let mut bars = vec![None; self.ltf.len()];
+        for (ind, range) in self.ranges.iter().enumerate() {
+            for bar in &mut bars[range.clone()] {
+                *bar = Some(ind);
+            }
+        }
+        OnLtf {
+            mtf: self,
+            model,
+            bars,
+            checked: 0,
+        }
+    }
+}
+
+// feeds an mtf model from the engine's lower timeframe candles
+struct OnLtf<'a, M> {
+    mtf: &'a Mtf,
+    model: M,
+    // the higher timeframe bar of each lower timeframe candle
+    bars: Vec<Option<usize>>,
+    // higher timeframe bars before this one were checked for missing candles
+    checked: usize,
+}
+
+impl<M: MtfModel> CandleModel for OnLtf<'_, M> {
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+        let end = view.candles.len() - 1;
+        let Some(ind) = self.bars[end] else {
+            return;
+        };
+        while self.checked < ind {
+            if self.mtf.ranges[self.checked].is_empty() {
+                self.model.on_missing(&MtfError::MissingLtf {
+                    htf_open_time: self.mtf.htf[self.checked].open_time,
+                });
+            }
+            self.checked += 1;
+        }
+        if !view.flat {
+            return;
+        }
+        let ltf = &view.candles[self.mtf.ranges[ind].start..];
+        let view = MtfView {
+            htf: &self.mtf.htf[..ind],
+            forming: forming(self.mtf.htf[ind].open_time, ltf),
+            ltf,
+        };
+        self.model.on_ltf(&view, orders);
+    }
+}
+
+fn forming(open_time: DateTime<Tz>, ltf: &[Candle]) -> Candle {
+    let first = ltf[0];
+    let last = ltf[ltf.len() - 1];
+    Candle {
+        open_time,
+        close_time: last.close_time,
+        open: first.open,
+        high: DecimalVec(ltf.iter().map(|c| c.high.0).max().unwrap_or(first.high.0)),
+        low: DecimalVec(ltf.iter().map(|c| c.low.0).min().unwrap_or(first.low.0)),
+        close: last.close,
+        volume: DecimalVec(ltf.iter().map(|c| c.volume.0).sum()),
+        number_of_trades: ltf.iter().map(|c| c.number_of_trades).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Order, OrderType};
+    use crate::model::position_direction::PositionDirection;
+    use crate::to_new_york_time;
+
+    fn candle(open_time: i64, minutes: i64, high: i32, low: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(open_time),
+            close_time: to_new_york_time(open_time + minutes * 60 - 1),
+            open: DecimalVec::new(low),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(high),
+            volume: DecimalVec::new(1),
+            number_of_trades: 1,
+        }
+    }
+
+    // two 15 minute bars of three 5 minute candles each
+    fn series() -> (Vec<Candle>, Vec<Candle>) {
+        let htf = vec![candle(0, 15, 103, 100), candle(900, 15, 106, 103)];
+        let ltf = (0..6)
+            .map(|ind| candle(ind * 300, 5, 101 + ind as i32, 100 + ind as i32))
+            .collect();
+        (htf, ltf)
+    }
+
+    // a model that records the missing bars it was told about and what it was shown
+    #[derive(Default)]
+    struct Gaps {
+        missing: Vec<MtfError>,
+        shown: Vec<usize>,
+    }
+
+    impl MtfModel for Gaps {
+        fn on_ltf(&mut self, view: &MtfView, _: &mut OrderBook) {
+            self.shown.push(view.htf.len());
+        }
+
+        fn on_missing(&mut self, error: &MtfError) {
+            self.missing.push(error.clone());
+        }
+    }
+
+    #[test]
+    fn test_missing_ltf() {
+        // the middle one of three bars has no lower timeframe candles
+        let (mut htf, ltf) = series();
+        htf.push(candle(1800, 15, 109, 106));
+        let ltf = [&ltf[..3], &[candle(1800, 5, 107, 106)]].concat();
+        let mtf = Mtf::new(htf.clone(), ltf).unwrap();
+        let missing = MtfError::MissingLtf {
+            htf_open_time: htf[1].open_time,
+        };
+        assert_eq!(mtf.missing(), vec![missing.clone()]);
+        assert!(mtf.ltf(1).is_empty());
+
+        let mut gaps = Gaps::default();
+        mtf.run(
+            &mut gaps,
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+        assert_eq!(gaps.missing, vec![missing]);
+        // the skipped bar still closes before the last one
+        assert_eq!(gaps.shown, vec![0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_misaligned_ltf() {
+        let (htf, mut ltf) = series();
+        ltf[2] = candle(600, 10, 103, 102);
+        assert_eq!(
+            Mtf::new(htf, ltf).err(),
+            Some(MtfError::Misaligned {
+                ltf_open_time: to_new_york_time(600)
+            })
+        );
+    }
+
+    // records what it was shown and goes long once from the second bar
+    struct Recorder {
+        views: Vec<(usize, usize, Candle)>,
+    }
+
+    impl MtfModel for Recorder {
+        fn on_ltf(&mut self, view: &MtfView, orders: &mut OrderBook) {
+            self.views
+                .push((view.htf.len(), view.ltf.len(), view.forming));
+            if view.htf.len() == 1 && self.views.len() == 4 {
+                orders.place(Order::market(
+                    PositionDirection::Long,
+                    DecimalVec::new(90),
+                    DecimalVec::new(105),
+                ));
+            }
+        }
+    }
+
+    // places a limit far below the price on every candle but the last of the lower timeframe
+    // series it was built with
+    struct BeforeTheEnd {
+        ltf: usize,
+        seen: usize,
+    }
+
+    impl MtfModel for BeforeTheEnd {
+        fn on_ltf(&mut self, _: &MtfView, orders: &mut OrderBook) {
+            self.seen += 1;
+            if self.seen < self.ltf {
+                orders.place(Order {
+                    order_type: OrderType::Limit(DecimalVec::new(1)),
+                    ..Order::market(
+                        PositionDirection::Long,
+                        DecimalVec::new(0),
+                        DecimalVec::new(105),
+                    )
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_lookahead() {
+        let (htf, ltf) = series();
+        let mtf = Mtf::new(htf, ltf).unwrap();
+        let check = |peek: bool| {
+            mtf.check_lookahead(
+                |cut| BeforeTheEnd {
+                    ltf: if peek { cut.len() } else { usize::MAX },
+                    seen: 0,
+                },
+                &TradeManagement::default(),
+                &CostModel::default(),
+                &AmbiguityPolicy::Pessimistic,
+            )
+        };
+        assert!(check(false).is_empty());
+        // every candle but the last reads a later one
+        assert_eq!(check(true).len(), 5);
+    }
+
+    #[test]
+    fn test_run_shows_only_the_past() {
+        let (htf, ltf) = series();
+        let mtf = Mtf::new(htf, ltf.clone()).unwrap();
+        let mut recorder = Recorder { views: vec![] };
+        let result = mtf.run(
+            &mut recorder,
+            &TradeManagement::default(),
+            &CostModel::default(),
+            &AmbiguityPolicy::Pessimistic,
+        );
+
+        // flat for the first four candles, then in the trade until the last one reaches the
+        // target and flat again once it closed
+        let shown: Vec<(usize, usize)> = recorder.views.iter().map(|v| (v.0, v.1)).collect();
+        assert_eq!(shown, vec![(0, 1), (0, 2), (0, 3), (1, 1), (1, 3)]);
+
+        let forming = recorder.views[2].2;
+        assert_eq!(forming.high, DecimalVec::new(103));
+        assert_eq!(forming.close_time, ltf[2].close_time);
+        assert_eq!(forming.volume, DecimalVec::new(3));
+
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].open_time, ltf[3].close_time);
+        assert_eq!(result.trades[0].close_time, ltf[5].close_time);
+    }
+}
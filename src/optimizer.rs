@@ -0,0 +1,345 @@
+use anyhow::{bail, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::model::{backtest_result::BacktestResult, candle::Candle, trading_model::TradingModel};
+
+// parameter name (a dotted path into the strategy config, e.g. `session.start`) to value
+pub type ParamSet = BTreeMap<String, Value>;
+
+pub trait ModelFactory: Sync {
+    fn build(&self, params: &ParamSet, candles: Vec<Candle>) -> Result<Box<dyn TradingModel>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ParamRange {
+    // inclusive on both ends
+    Steps {
+        start: Decimal,
+        end: Decimal,
+        step: Decimal,
+    },
+    Values(Vec<Value>),
+}
+
+impl ParamRange {
+    pub fn values(&self) -> Result<Vec<Value>> {
+        match self {
+            ParamRange::Steps { start, end, step } => {
+                if *step <= Decimal::ZERO {
+                    bail!("step must be positive");
+                }
+                let mut values = vec![];
+                let mut actual = *start;
+                while actual <= *end {
+                    values.push(decimal_value(actual));
+                    actual += *step;
+                }
+                Ok(values)
+            }
+            ParamRange::Values(values) => Ok(values.clone()),
+        }
+    }
+}
+
+// whole numbers stay integers so they can fill integer fields like `max_duration_min`
+fn decimal_value(d: Decimal) -> Value {
+    let d = d.normalize();
+    if d.scale() == 0 {
+        if let Ok(i) = i64::try_from(d) {
+            return Value::from(i);
+        }
+    }
+    Value::String(d.to_string())
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// `name=value` pairs, e.g. `rr_threshold=2.5 session.start=09:30`
+pub fn format_params(params: &ParamSet) -> String {
+    params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, format_value(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// every combination of the ranges
+pub fn grid(ranges: &BTreeMap<String, ParamRange>) -> Result<Vec<ParamSet>> {
+    let mut sets = vec![ParamSet::new()];
+    for (name, range) in ranges {
+        let values = range.values()?;
+        if values.is_empty() {
+            bail!("{} has no values", name);
+        }
+        sets = sets
+            .into_iter()
+            .flat_map(|set| {
+                values.iter().map(move |v| {
+                    let mut s = set.clone();
+                    s.insert(name.clone(), v.clone());
+                    s
+                })
+            })
+            .collect();
+    }
+    Ok(sets)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    ProfitInR,
+    Expectancy,
+    // profit over max drawdown, drawdowns under 1R count as 1R so a lucky streak doesn't win
+    DrawdownAdjusted,
+}
+
+impl Metric {
+    pub fn score(&self, result: &BacktestResult) -> Option<Decimal> {
+        match self {
+            Metric::ProfitInR => Some(result.profit_in_r()),
+            Metric::Expectancy => result.expectancy(),
+            Metric::DrawdownAdjusted => {
+                let drawdown = result.max_drawdown().depth_in_r.max(Decimal::ONE);
+                Some(result.profit_in_r() / drawdown)
+            }
+        }
+    }
+}
+
+pub struct RunSummary {
+    pub params: ParamSet,
+    pub result: BacktestResult,
+    pub score: Option<Decimal>,
+}
+
+pub struct Optimization {
+    // best first, runs without a score last
+    pub runs: Vec<RunSummary>,
+    // parameter sets the factory refused to build, with the reason
+    pub rejected: Vec<(ParamSet, String)>,
+}
+
+impl Optimization {
+    pub fn best(&self) -> Option<&RunSummary> {
+        self.runs.first()
+    }
+
+    // one csv row per run: the parameters, then the statistics
+    pub fn write_table<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let names: Vec<&String> = self
+            .runs
+            .first()
+            .map(|r| r.params.keys().collect())
+            .unwrap_or_default();
+
+        let mut header: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        header.extend(
+            [
+                "score",
+                "number_of_trades",
+                "profit_in_r",
+                "win_rate",
+                "expectancy",
+                "profit_factor",
+                "max_drawdown",
+                "sharpe_ratio",
+            ]
+            .map(String::from),
+        );
+        writeln!(writer, "{}", header.join(","))?;
+
+        let optional = |v: Option<Decimal>| v.map(|d| d.to_string()).unwrap_or_default();
+        for run in &self.runs {
+            let mut row: Vec<String> = names
+                .iter()
+                .map(|n| format_value(&run.params[*n]))
+                .collect();
+            row.push(optional(run.score));
+            row.push(run.result.number_of_trades().to_string());
+            row.push(run.result.profit_in_r().to_string());
+            row.push(optional(run.result.win_rate()));
+            row.push(optional(run.result.expectancy()));
+            row.push(optional(run.result.profit_factor()));
+            row.push(run.result.max_drawdown().depth_in_r.to_string());
+            row.push(optional(run.result.sharpe_ratio()));
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// runs every parameter set on `threads` worker threads (all cores when None)
+pub fn optimize<F: ModelFactory>(
+    factory: &F,
+    sets: Vec<ParamSet>,
+    candles: &[Candle],
+    metric: Metric,
+    threads: Option<usize>,
+) -> Optimization {
+    let threads = threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, sets.len().max(1));
+    let next = AtomicUsize::new(0);
+    let runs = Mutex::new(vec![]);
+    let rejected = Mutex::new(vec![]);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let ind = next.fetch_add(1, Ordering::Relaxed);
+                let Some(params) = sets.get(ind) else {
+                    break;
+                };
+                match factory.build(params, candles.to_vec()) {
+                    Ok(model) => {
+                        let result = model.execute();
+                        let summary = RunSummary {
+                            params: params.clone(),
+                            score: metric.score(&result),
+                            result,
+                        };
+                        runs.lock().unwrap().push((ind, summary));
+                    }
+                    Err(e) => rejected
+                        .lock()
+                        .unwrap()
+                        .push((ind, (params.clone(), format!("{:#}", e)))),
+                }
+            });
+        }
+    });
+
+    let mut runs = runs.into_inner().unwrap();
+    // ties keep the grid order so the outcome doesn't depend on thread timing
+    runs.sort_by(|(a_ind, a), (b_ind, b)| match (a.score, b.score) {
+        (Some(a_score), Some(b_score)) => b_score.cmp(&a_score).then(a_ind.cmp(b_ind)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a_ind.cmp(b_ind),
+    });
+    let mut rejected = rejected.into_inner().unwrap();
+    rejected.sort_by_key(|(ind, _)| *ind);
+
+    Optimization {
+        runs: runs.into_iter().map(|(_, r)| r).collect(),
+        rejected: rejected.into_iter().map(|(_, r)| r).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StrategyTemplate;
+    use crate::data::{BinanceJsonSource, CandleSource};
+    use serde_json::json;
+
+    fn candles() -> Vec<Candle> {
+        BinanceJsonSource::new("assets/eth15.json").load().unwrap()
+    }
+
+    #[test]
+    fn test_range_values() {
+        let range = ParamRange::Steps {
+            start: Decimal::from(2),
+            end: Decimal::from(3),
+            step: "0.5".parse().unwrap(),
+        };
+        assert_eq!(
+            range.values().unwrap(),
+            vec![json!(2), json!("2.5"), json!(3)]
+        );
+    }
+
+    #[test]
+    fn test_range_invalid_step() {
+        let range = ParamRange::Steps {
+            start: Decimal::from(2),
+            end: Decimal::from(3),
+            step: Decimal::ZERO,
+        };
+        assert!(range.values().is_err());
+    }
+
+    #[test]
+    fn test_grid_combinations() {
+        let ranges: BTreeMap<String, ParamRange> = serde_json::from_value(json!({
+            "rr_threshold": { "start": 1, "end": 3, "step": 1 },
+            "session.start": ["09:30", "09:50"],
+        }))
+        .unwrap();
+        let sets = grid(&ranges).unwrap();
+
+        assert_eq!(sets.len(), 6);
+        assert_eq!(sets[0]["session.start"], json!("09:30"));
+        assert_eq!(sets[0]["rr_threshold"], json!(1));
+        assert_eq!(sets[5]["session.start"], json!("09:50"));
+        assert_eq!(sets[5]["rr_threshold"], json!(3));
+    }
+
+    #[test]
+    fn test_optimize_ranks_by_metric() {
+        let template = StrategyTemplate::new(json!({ "type": "sfp", "rr_treshold": 1 }));
+        let ranges: BTreeMap<String, ParamRange> = serde_json::from_value(json!({
+            "rr_treshold": { "start": 1, "end": 3, "step": "0.5" },
+        }))
+        .unwrap();
+        let optimization = optimize(
+            &template,
+            grid(&ranges).unwrap(),
+            &candles(),
+            Metric::ProfitInR,
+            Some(3),
+        );
+
+        assert_eq!(optimization.runs.len(), 5);
+        assert!(optimization.rejected.is_empty());
+        let scores: Vec<Decimal> = optimization.runs.iter().map(|r| r.score.unwrap()).collect();
+        assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+
+        let mut table = vec![];
+        optimization.write_table(&mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.starts_with("rr_treshold,score,number_of_trades"));
+    }
+
+    #[test]
+    fn test_optimize_rejects_invalid_sets() {
+        let template = StrategyTemplate::new(json!({
+            "type": "macro-soup",
+            "rr_threshold": 2,
+            "max_duration_min": 30,
+            "session": { "start": "09:30", "end": "10:00" },
+        }));
+        let ranges: BTreeMap<String, ParamRange> = serde_json::from_value(json!({
+            "session.end": ["09:00", "10:00"],
+        }))
+        .unwrap();
+        let optimization = optimize(
+            &template,
+            grid(&ranges).unwrap(),
+            &candles(),
+            Metric::Expectancy,
+            None,
+        );
+
+        assert_eq!(optimization.runs.len(), 1);
+        assert_eq!(optimization.rejected.len(), 1);
+        assert_eq!(optimization.rejected[0].0["session.end"], json!("09:00"));
+    }
+}
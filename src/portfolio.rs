@@ -0,0 +1,520 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, MathematicalOps};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::account::{cagr, max_drawdown_pct, pnl_per_unit, AccountResult, EquityPoint};
+use crate::engine::Event;
+use crate::model::{
+    backtest_result::BacktestResult, position::Position, trade::Trade, trading_model::TradingModel,
+};
+
+// one strategy on one symbol
+pub struct Leg {
+    pub name: String,
+    pub symbol: String,
+    pub model: Box<dyn TradingModel>,
+}
+
+// Runs several legs on a shared timeline and a shared account. Every entry risks
+// `risk_fraction` of the balance at that moment, entries past a limit are skipped.
+pub struct Portfolio {
+    pub starting_balance: Decimal,
+    // fraction of the balance lost when the stop is hit, e.g. 0.01
+    pub risk_fraction: Decimal,
+    pub max_positions: Option<usize>,
+    // what all open positions together lose at their stops, as a fraction of the balance
+    pub max_total_risk: Option<Decimal>,
+}
+
+pub struct LegResult {
+    pub name: String,
+    pub symbol: String,
+    pub result: BacktestResult,
+    // entries skipped because of the limits
+    pub rejected: usize,
+}
+
+pub struct PortfolioResult {
+    // every trade of every leg by close time
+    pub combined: BacktestResult,
+    pub legs: Vec<LegResult>,
+    // the trades of the legs on each symbol, in the order the symbols first appear
+    pub symbols: Vec<(String, BacktestResult)>,
+    pub account: AccountResult,
+    // Pearson correlation of the legs' daily R, None where a leg's R never changes
+    pub correlation: Vec<Vec<Option<Decimal>>>,
+}
+
+// size and risk of an open position
+#[derive(Clone, Copy)]
+struct Open {
+    units: Decimal,
+    risk: Decimal,
+}
+
+impl Portfolio {
+    pub fn run(&self, legs: &[Leg]) -> Result<PortfolioResult> {
+        if self.starting_balance <= Decimal::ZERO {
+            bail!("starting balance must be positive");
+        }
+        if self.risk_fraction <= Decimal::ZERO || self.risk_fraction >= Decimal::ONE {
+            bail!("the fraction risked per trade must be between 0 and 1");
+        }
+        if self.max_positions == Some(0) {
+            bail!("max_positions must be positive");
+        }
+        if self.max_total_risk.is_some_and(|r| r <= Decimal::ZERO) {
+            bail!("max_total_risk must be positive");
+        }
+
+        let mut runners = legs
+            .iter()
+            .map(|leg| {
+                leg.model
+                    .runner()
+                    .ok_or_else(|| anyhow!("{} doesn't run on the candle engine", leg.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut balance = self.starting_balance;
+        let mut equity_curve = vec![];
+        let mut open: Vec<Option<Open>> = vec![None; legs.len()];
+        let mut rejected = vec![0; legs.len()];
+        // the earliest candle goes first, legs in order on a tie
+        while let Some(ind) = (0..runners.len())
+            .filter_map(|ind| runners[ind].next_close().map(|t| (t, ind)))
+            .min()
+            .map(|(_, ind)| ind)
+        {
+            let mut sizes = vec![];
+            let step = runners[ind].step(&mut |position| {
+                let size = self.admit(position, balance, &open);
+                if size.is_none() {
+                    rejected[ind] += 1;
+                }
+                sizes.push(size);
+                size.is_some()
+            });
+
+            let mut sizes = sizes.into_iter().flatten();
+            for event in step.events {
+                match event {
+                    Event::Opened(_) => open[ind] = sizes.next(),
+                    Event::Closed(trade) => {
+                        if let Some(position) = open[ind].take() {
+                            balance += position.units * pnl_per_unit(&trade);
+                            equity_curve.push(EquityPoint {
+                                time: trade.close_time,
+                                balance: balance.max(Decimal::ZERO),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let legs: Vec<LegResult> = legs
+            .iter()
+            .zip(runners)
+            .zip(rejected)
+            .map(|((leg, runner), rejected)| LegResult {
+                name: leg.name.clone(),
+                symbol: leg.symbol.clone(),
+                result: runner.finish(),
+                rejected,
+            })
+            .collect();
+
+        let combined = merge(legs.iter());
+        let mut symbols: Vec<(String, BacktestResult)> = vec![];
+        for leg in &legs {
+            if symbols.iter().all(|(symbol, _)| *symbol != leg.symbol) {
+                let result = merge(legs.iter().filter(|l| l.symbol == leg.symbol));
+                symbols.push((leg.symbol.clone(), result));
+            }
+        }
+        let cagr = match (
+            combined.trades.iter().map(|t| t.open_time).min(),
+            equity_curve.last(),
+        ) {
+            (Some(first), Some(last)) => {
+                cagr(self.starting_balance, last.balance, last.time - first)
+            }
+            _ => None,
+        };
+        let account = AccountResult {
+            starting_balance: self.starting_balance,
+            max_drawdown_pct: max_drawdown_pct(self.starting_balance, &equity_curve),
+            equity_curve,
+            cagr,
+        };
+        let correlation = correlation(&legs);
+        Ok(PortfolioResult {
+            combined,
+            legs,
+            symbols,
+            account,
+            correlation,
+        })
+    }
+
+    // the size of an entry the limits let through
+    fn admit(&self, position: &Position, balance: Decimal, open: &[Option<Open>]) -> Option<Open> {
+        let risk_per_unit = (position.entry.0 - position.sl.0).abs();
+        if balance <= Decimal::ZERO || risk_per_unit.is_zero() {
+            return None;
+        }
+        let risk = balance * self.risk_fraction;
+        let positions = open.iter().flatten().count();
+        if self.max_positions.is_some_and(|max| positions >= max) {
+            return None;
+        }
+        let open_risk: Decimal = open.iter().flatten().map(|o| o.risk).sum();
+        if self
+            .max_total_risk
+            .is_some_and(|max| (open_risk + risk) / balance > max)
+        {
+            return None;
+        }
+        Some(Open {
+            units: risk / risk_per_unit,
+            risk,
+        })
+    }
+}
+
+fn merge<'a>(legs: impl Iterator<Item = &'a LegResult>) -> BacktestResult {
+    let mut trades: Vec<Trade> = legs.flat_map(|leg| leg.result.trades.clone()).collect();
+    trades.sort_by_key(|t| (t.close_time, t.open_time));
+    BacktestResult { trades }
+}
+
+// every pair of legs over the days any leg closed a trade, a day without trades counts as 0R
+fn correlation(legs: &[LegResult]) -> Vec<Vec<Option<Decimal>>> {
+    let daily: Vec<BTreeMap<NaiveDate, Decimal>> = legs
+        .iter()
+        .map(|leg| {
+            let mut days = BTreeMap::new();
+            for trade in &leg.result.trades {
+                *days
+                    .entry(trade.close_time.date_naive())
+                    .or_insert(Decimal::ZERO) += trade.r_multiple();
+            }
+            days
+        })
+        .collect();
+    let mut days: Vec<NaiveDate> = daily.iter().flat_map(|d| d.keys().copied()).collect();
+    days.sort();
+    days.dedup();
+    let series: Vec<Vec<Decimal>> = daily
+        .iter()
+        .map(|d| {
+            days.iter()
+                .map(|day| d.get(day).copied().unwrap_or(Decimal::ZERO))
+                .collect()
+        })
+        .collect();
+
+    series
+        .iter()
+        .map(|x| series.iter().map(|y| pearson(x, y)).collect())
+        .collect()
+}
+
+fn pearson(x: &[Decimal], y: &[Decimal]) -> Option<Decimal> {
+    if x.is_empty() {
+        return None;
+    }
+    let n = Decimal::from(x.len());
+    let mean_x = x.iter().sum::<Decimal>() / n;
+    let mean_y = y.iter().sum::<Decimal>() / n;
+    let (mut cov, mut var_x, mut var_y) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    for (a, b) in x.iter().zip(y) {
+        cov += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x) * (a - mean_x);
+        var_y += (b - mean_y) * (b - mean_y);
+    }
+    let scale = (var_x * var_y).sqrt()?;
+    if scale.is_zero() {
+        return None;
+    }
+    Some(cov / scale)
+}
+
+impl fmt::Display for PortfolioResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for leg in &self.legs {
+            write!(f, "{} ({}): {}", leg.name, leg.symbol, leg.result)?;
+            if leg.rejected > 0 {
+                write!(f, ", rejected: {}", leg.rejected)?;
+            }
+            writeln!(f)?;
+        }
+        for (symbol, result) in &self.symbols {
+            writeln!(f, "{}: {}", symbol, result)?;
+        }
+        writeln!(f, "combined: {}", self.combined)?;
+        writeln!(f, "account: {}", self.account)?;
+        writeln!(f, "correlation:")?;
+        for (leg, row) in self.legs.iter().zip(&self.correlation) {
+            let row: Vec<String> = row
+                .iter()
+                .map(|c| match c {
+                    Some(c) => c.round_dp(2).normalize().to_string(),
+                    None => "-".to_string(),
+                })
+                .collect();
+            writeln!(f, "  {}: {}", leg.name, row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::engine::{self, CandleModel, CandleView, Order, OrderBook, Runner};
+    use crate::model::{
+        ambiguity_policy::AmbiguityPolicy, candle::Candle, cost_model::CostModel,
+        decimal::DecimalVec, position_direction::PositionDirection,
+        trade_management::TradeManagement,
+    };
+    use crate::parse_datetime;
+
+    // hourly candles trading between 99 and 101 apart from the listed highs and lows
+    fn hours(start: &str, moves: &[(usize, i32, i32)], count: usize) -> Vec<Candle> {
+        let start = parse_datetime(start).unwrap();
+        (0..count)
+            .map(|ind| {
+                let (high, low) = moves
+                    .iter()
+                    .find(|(i, _, _)| *i == ind)
+                    .map(|(_, high, low)| (*high, *low))
+                    .unwrap_or((101, 99));
+                Candle {
+                    open_time: start + Duration::hours(ind as i64),
+                    close_time: start + Duration::hours(ind as i64 + 1),
+                    open: DecimalVec::new(100),
+                    high: DecimalVec::new(high),
+                    low: DecimalVec::new(low),
+                    close: DecimalVec::new(100),
+                    volume: DecimalVec::new(1),
+                    number_of_trades: 1,
+                }
+            })
+            .collect()
+    }
+
+    // goes long at the close of the listed candles, stop at 90 and target at 120
+    struct Longs {
+        candles: Vec<Candle>,
+        entries: Vec<usize>,
+    }
+
+    struct Placer(Vec<usize>);
+
+    impl CandleModel for Placer {
+        fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+            if self.0.contains(&(view.candles.len() - 1)) {
+                orders.place(Order::market(
+                    PositionDirection::Long,
+                    DecimalVec::new(90),
+                    DecimalVec::new(120),
+                ));
+            }
+        }
+    }
+
+    impl TradingModel for Longs {
+        fn execute(&self) -> BacktestResult {
+            engine::run(
+                &self.candles,
+                &mut Placer(self.entries.clone()),
+                &TradeManagement::default(),
+                &CostModel::default(),
+                &AmbiguityPolicy::Pessimistic,
+            )
+        }
+
+        fn runner(&self) -> Option<Runner<'_>> {
+            Some(Runner::new(
+                &self.candles,
+                Placer(self.entries.clone()),
+                &TradeManagement::default(),
+                &CostModel::default(),
+                &AmbiguityPolicy::Pessimistic,
+            ))
+        }
+    }
+
+    fn leg(name: &str, symbol: &str, candles: Vec<Candle>, entries: Vec<usize>) -> Leg {
+        Leg {
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            model: Box::new(Longs { candles, entries }),
+        }
+    }
+
+    fn portfolio() -> Portfolio {
+        Portfolio {
+            starting_balance: Decimal::from(1000),
+            risk_fraction: "0.1".parse().unwrap(),
+            max_positions: None,
+            max_total_risk: None,
+        }
+    }
+
+    #[test]
+    fn test_shared_account_books_every_leg() {
+        // a wins at 3, b loses at 5, both entered at 0
+        let legs = [
+            leg(
+                "a",
+                "ETH",
+                hours("2024-04-22 00:00:00", &[(3, 125, 99)], 6),
+                vec![0],
+            ),
+            leg(
+                "b",
+                "BTC",
+                hours("2024-04-22 00:00:00", &[(5, 101, 85)], 6),
+                vec![0],
+            ),
+        ];
+        let result = portfolio().run(&legs).unwrap();
+
+        assert_eq!(result.legs[0].result.number_of_trades(), 1);
+        assert_eq!(result.legs[1].result.number_of_trades(), 1);
+        // without limits a leg trades the way it does on its own
+        assert_eq!(
+            result.legs[1].result.profit_in_r(),
+            legs[1].model.execute().profit_in_r()
+        );
+        assert_eq!(result.combined.profit_in_r(), Decimal::ONE);
+        // both risk 100 of the starting 1000, +200 then -100
+        let balances: Vec<Decimal> = result
+            .account
+            .equity_curve
+            .iter()
+            .map(|p| p.balance)
+            .collect();
+        assert_eq!(balances, vec![Decimal::from(1200), Decimal::from(1100)]);
+        assert_eq!(result.symbols.len(), 2);
+        assert_eq!(result.symbols[0].0, "ETH");
+    }
+
+    #[test]
+    fn test_max_positions_rejects_entries() {
+        let legs = [
+            leg(
+                "a",
+                "ETH",
+                hours("2024-04-22 00:00:00", &[(3, 125, 99)], 6),
+                vec![0],
+            ),
+            leg(
+                "b",
+                "ETH",
+                hours("2024-04-22 00:00:00", &[(5, 101, 85)], 6),
+                vec![1, 4],
+            ),
+        ];
+        let result = Portfolio {
+            max_positions: Some(1),
+            ..portfolio()
+        }
+        .run(&legs)
+        .unwrap();
+
+        // b's first entry comes while a is open, the second after a has closed
+        assert_eq!(result.legs[1].rejected, 1);
+        assert_eq!(result.legs[1].result.number_of_trades(), 1);
+        assert_eq!(
+            result.legs[1].result.trades[0].open_time,
+            parse_datetime("2024-04-22 05:00:00").unwrap()
+        );
+        assert_eq!(result.symbols.len(), 1);
+        assert_eq!(result.symbols[0].1.number_of_trades(), 2);
+    }
+
+    #[test]
+    fn test_max_total_risk_rejects_entries() {
+        let legs = [
+            leg(
+                "a",
+                "ETH",
+                hours("2024-04-22 00:00:00", &[(3, 125, 99)], 6),
+                vec![0],
+            ),
+            leg(
+                "b",
+                "BTC",
+                hours("2024-04-22 00:00:00", &[(5, 101, 85)], 6),
+                vec![0],
+            ),
+        ];
+        let result = Portfolio {
+            max_total_risk: Some("0.15".parse().unwrap()),
+            ..portfolio()
+        }
+        .run(&legs)
+        .unwrap();
+
+        assert_eq!(result.legs[0].result.number_of_trades(), 1);
+        assert_eq!(result.legs[1].rejected, 1);
+        assert_eq!(result.legs[1].result.number_of_trades(), 0);
+    }
+
+    #[test]
+    fn test_correlation_of_daily_r() {
+        // a and b win and lose on the same days, c the other way around
+        let moves = [(3, 125, 99), (27, 101, 85)];
+        let flipped = [(3, 101, 85), (27, 125, 99)];
+        let legs = [
+            leg(
+                "a",
+                "ETH",
+                hours("2024-04-22 00:00:00", &moves, 30),
+                vec![0, 24],
+            ),
+            leg(
+                "b",
+                "BTC",
+                hours("2024-04-22 00:00:00", &moves, 30),
+                vec![0, 24],
+            ),
+            leg(
+                "c",
+                "SOL",
+                hours("2024-04-22 00:00:00", &flipped, 30),
+                vec![0, 24],
+            ),
+        ];
+        let result = portfolio().run(&legs).unwrap();
+
+        let rounded = |c: Option<Decimal>| c.map(|c| c.round_dp(6));
+        assert_eq!(rounded(result.correlation[0][1]), Some(Decimal::ONE));
+        assert_eq!(rounded(result.correlation[0][2]), Some(-Decimal::ONE));
+        assert_eq!(rounded(result.correlation[2][2]), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn test_models_off_the_engine_are_refused() {
+        struct Plain;
+        impl TradingModel for Plain {
+            fn execute(&self) -> BacktestResult {
+                BacktestResult { trades: vec![] }
+            }
+        }
+        let legs = [Leg {
+            name: "plain".to_string(),
+            symbol: "ETH".to_string(),
+            model: Box::new(Plain),
+        }];
+        assert!(portfolio().run(&legs).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::{America::New_York, Tz};
+use std::str::FromStr;
+
+use crate::config::parse_time;
+use crate::model::{
+    candle::Candle, candle_ny::CandleNY, candle_stick::CandleStick, decimal::DecimalVec,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timeframe {
+    // fixed length bars aligned to the unix epoch, the way exchanges build 5m to 4h bars
+    Fixed(Duration),
+    // one bar per day starting at `start` New York time, e.g. 18:00 for CME futures
+    Daily { start: NaiveTime },
+    // one bar per day from `start` to `end` New York time, candles outside are dropped.
+    // A session ending before it starts runs over midnight.
+    Session { start: NaiveTime, end: NaiveTime },
+}
+
+// `15m`, `4h`, `1d` (midnight to midnight), `1d@18:00` or a session like `09:30-16:00`
+impl FromStr for Timeframe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((start, end)) = s.split_once('-') {
+            return Ok(Timeframe::Session {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            });
+        }
+        if let Some(start) = s.strip_prefix("1d@") {
+            return Ok(Timeframe::Daily {
+                start: parse_time(start)?,
+            });
+        }
+        let error = || format!("expected 15m, 4h, 1d, 1d@HH:MM or HH:MM-HH:MM, got {}", s);
+        let (amount, unit) = s.split_at(s.len().saturating_sub(1));
+        let amount = amount.parse::<i64>().map_err(|_| error())?;
+        if amount <= 0 {
+            return Err(error());
+        }
+        match unit {
+            "m" => Ok(Timeframe::Fixed(Duration::minutes(amount))),
+            "h" => Ok(Timeframe::Fixed(Duration::hours(amount))),
+            "d" if amount == 1 => Ok(Timeframe::Daily {
+                start: NaiveTime::MIN,
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub candle: Candle,
+    // false when source candles are missing from the bar, because of a gap or because the
+    // data ends before the bar does
+    pub complete: bool,
+}
+
+// start and end of a bar
+pub(crate) type Period = (DateTime<Tz>, DateTime<Tz>);
+
+// anything the resampler takes, `interval` is the length of one source candle
+pub trait IntoCandle {
+    fn into_candle(self, interval: Duration) -> Candle;
+}
+
+impl IntoCandle for Candle {
+    fn into_candle(self, _interval: Duration) -> Candle {
+        self
+    }
+}
+
+impl IntoCandle for CandleStick {
+    fn into_candle(self, _interval: Duration) -> Candle {
+        Candle::from(self)
+    }
+}
+
+impl IntoCandle for CandleNY {
+    fn into_candle(self, interval: Duration) -> Candle {
+        self.to_candle(interval)
+    }
+}
+
+// Aggregates candles sorted by open time into `timeframe` bars. Bars open at the start of
+// their period and close with their last candle. Periods without candles are skipped rather
+// than filled, so a gap never invents prices.
+pub fn resample<C: IntoCandle>(
+    candles: impl IntoIterator<Item = C>,
+    interval: Duration,
+    timeframe: Timeframe,
+) -> Vec<Bar> {
+    let mut bars = vec![];
+    // the current period and its candles
+    let mut current: Option<(Period, Vec<Candle>)> = None;
+
+    for candle in candles {
+        let candle = candle.into_candle(interval);
+        let Some(period) = period(timeframe, candle.open_time) else {
+            continue;
+        };
+        match &mut current {
+            Some((p, members)) if *p == period => members.push(candle),
+            _ => {
+                if let Some((p, members)) = current.take() {
+                    bars.push(bar(p, &members, interval));
+                }
+                current = Some((period, vec![candle]));
+            }
+        }
+    }
+    if let Some((p, members)) = current {
+        bars.push(bar(p, &members, interval));
+    }
+    bars
+}
+
+// the smallest positive distance between two open times, the interval of a series with gaps
+pub fn infer_interval(candles: &[Candle]) -> Option<Duration> {
+    candles
+        .windows(2)
+        .map(|w| w[1].open_time - w[0].open_time)
+        .filter(|d| *d > Duration::zero())
+        .min()
+}
+
+// start and end of the period `time` falls in, None outside a session
+pub(crate) fn period(timeframe: Timeframe, time: DateTime<Tz>) -> Option<Period> {
+    match timeframe {
+        Timeframe::Fixed(length) => {
+            let offset = time.timestamp().rem_euclid(length.num_seconds().max(1));
+            let start = time - Duration::seconds(offset);
+            Some((start, start + length))
+        }
+        Timeframe::Daily { start } => {
+            let local = time.naive_local();
+            let day = if local.time() >= start {
+                local.date()
+            } else {
+                local.date().pred_opt()?
+            };
+            Some((new_york(day, start), new_york(day.succ_opt()?, start)))
+        }
+        Timeframe::Session { start, end } => {
+            let local = time.naive_local();
+            let (day, end_day) = if start < end {
+                if local.time() < start || local.time() >= end {
+                    return None;
+                }
+                (local.date(), local.date())
+            } else if local.time() >= start {
+                (local.date(), local.date().succ_opt()?)
+            } else if local.time() < end {
+                (local.date().pred_opt()?, local.date())
+            } else {
+                return None;
+            };
+            Some((new_york(day, start), new_york(end_day, end)))
+        }
+    }
+}
+
+// a local time skipped by the switch to daylight saving time moves to the hour after
+fn new_york(day: NaiveDate, time: NaiveTime) -> DateTime<Tz> {
+    let local = day.and_time(time);
+    New_York
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            New_York
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .expect("only one hour a year is skipped")
+}
+
+fn bar((start, end): Period, members: &[Candle], interval: Duration) -> Bar {
+    let first = members[0];
+    let last = members[members.len() - 1];
+    let expected = (end - start).num_seconds() / interval.num_seconds().max(1);
+    Bar {
+        candle: Candle {
+            open_time: start,
+            close_time: last.close_time,
+            open: first.open,
+            high: DecimalVec(
+                members
+                    .iter()
+                    .map(|c| c.high.0)
+                    .max()
+                    .unwrap_or(first.high.0),
+            ),
+            low: DecimalVec(members.iter().map(|c| c.low.0).min().unwrap_or(first.low.0)),
+            close: last.close,
+            volume: DecimalVec(members.iter().map(|c| c.volume.0).sum()),
+            number_of_trades: members.iter().map(|c| c.number_of_trades).sum(),
+        },
+        complete: first.open_time == start
+            && members.len() as i64 == expected
+            && last.open_time + interval <= end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_datetime;
+
+    // one minute csv rows, the close climbs by one every minute
+    fn minutes(start: &str, count: usize) -> Vec<CandleNY> {
+        let start = parse_datetime(start).unwrap();
+        (0..count)
+            .map(|ind| CandleNY {
+                open_time: start + Duration::minutes(ind as i64),
+                open: DecimalVec::new(100 + ind as i32),
+                high: DecimalVec::new(102 + ind as i32),
+                low: DecimalVec::new(99 + ind as i32),
+                close: DecimalVec::new(101 + ind as i32),
+            })
+            .collect()
+    }
+
+    fn opens(bars: &[Bar]) -> Vec<String> {
+        bars.iter()
+            .map(|b| b.candle.open_time.format("%m-%d %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed_bars() {
+        let bars = resample(
+            minutes("2024-04-22 09:30:00", 10),
+            Duration::minutes(1),
+            Timeframe::Fixed(Duration::minutes(5)),
+        );
+
+        assert_eq!(opens(&bars), vec!["04-22 09:30", "04-22 09:35"]);
+        let candle = bars[0].candle;
+        assert_eq!(candle.open, DecimalVec::new(100));
+        assert_eq!(candle.high, DecimalVec::new(106));
+        assert_eq!(candle.low, DecimalVec::new(99));
+        assert_eq!(candle.close, DecimalVec::new(105));
+        assert_eq!(
+            candle.close_time,
+            parse_datetime("2024-04-22 09:35:00").unwrap()
+        );
+        assert!(bars.iter().all(|b| b.complete));
+    }
+
+    #[test]
+    fn test_gaps_are_skipped_and_tagged() {
+        // 09:30 - 09:32 and 09:43 - 09:47, nothing in the 09:35 bar
+        let mut candles = minutes("2024-04-22 09:30:00", 3);
+        candles.extend(minutes("2024-04-22 09:43:00", 5));
+        let bars = resample(
+            candles,
+            Duration::minutes(1),
+            Timeframe::Fixed(Duration::minutes(5)),
+        );
+
+        assert_eq!(
+            opens(&bars),
+            vec!["04-22 09:30", "04-22 09:40", "04-22 09:45"]
+        );
+        let complete: Vec<bool> = bars.iter().map(|b| b.complete).collect();
+        assert_eq!(complete, vec![false, false, false]);
+        // the 09:40 bar opens with its first candle at 09:43
+        assert_eq!(bars[1].candle.open, DecimalVec::new(100));
+    }
+
+    #[test]
+    fn test_daily_bars_anchor_to_new_york() {
+        // 16:00 to 20:00 on two days, the 18:00 open splits each evening
+        let mut candles = minutes("2024-04-22 16:00:00", 240);
+        candles.extend(minutes("2024-04-23 16:00:00", 240));
+        let bars = resample(candles, Duration::minutes(1), "1d@18:00".parse().unwrap());
+
+        assert_eq!(
+            opens(&bars),
+            vec!["04-21 18:00", "04-22 18:00", "04-23 18:00"]
+        );
+        assert_eq!(
+            bars[0].candle.close_time,
+            parse_datetime("2024-04-22 18:00:00").unwrap()
+        );
+        assert!(bars.iter().all(|b| !b.complete));
+    }
+
+    #[test]
+    fn test_session_bars_drop_the_rest() {
+        let bars = resample(
+            minutes("2024-04-22 09:00:00", 8 * 60),
+            Duration::minutes(1),
+            "09:30-16:00".parse().unwrap(),
+        );
+
+        assert_eq!(opens(&bars), vec!["04-22 09:30"]);
+        assert!(bars[0].complete);
+        assert_eq!(bars[0].candle.open, DecimalVec::new(130));
+        assert_eq!(bars[0].candle.close, DecimalVec::new(101 + 419));
+    }
+
+    #[test]
+    fn test_candle_stick_input() {
+        // 15 minute binance bars from 00:00 utc, the second hour misses its last bar
+        let sticks = (0..7).map(|ind: i64| CandleStick {
+            open_time: 1713571200 + ind * 900,
+            open: DecimalVec::new(1),
+            high: DecimalVec::new(2),
+            low: DecimalVec::new(1),
+            close: DecimalVec::new(2),
+            close_time: 1713571200 + ind * 900 + 899,
+        });
+        let bars = resample(
+            sticks,
+            Duration::minutes(15),
+            Timeframe::Fixed(Duration::hours(1)),
+        );
+
+        assert_eq!(bars.len(), 2);
+        assert!(bars[0].complete);
+        assert!(!bars[1].complete);
+        assert_eq!(opens(&bars), vec!["04-19 20:00", "04-19 21:00"]);
+    }
+
+    #[test]
+    fn test_parse_timeframe() {
+        assert_eq!(
+            "4h".parse::<Timeframe>(),
+            Ok(Timeframe::Fixed(Duration::hours(4)))
+        );
+        assert_eq!(
+            "1d".parse::<Timeframe>(),
+            Ok(Timeframe::Daily {
+                start: NaiveTime::MIN
+            })
+        );
+        assert_eq!(
+            "18:00-17:00".parse::<Timeframe>(),
+            Ok(Timeframe::Session {
+                start: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            })
+        );
+        assert!("2d".parse::<Timeframe>().is_err());
+        assert!("0m".parse::<Timeframe>().is_err());
+    }
+
+    #[test]
+    fn test_infer_interval() {
+        let candles: Vec<Candle> = minutes("2024-04-22 09:30:00", 3)
+            .into_iter()
+            .map(|c| c.to_candle(Duration::minutes(1)))
+            .collect();
+        assert_eq!(infer_interval(&candles), Some(Duration::minutes(1)));
+        assert_eq!(infer_interval(&candles[..1]), None);
+    }
+}
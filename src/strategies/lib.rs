@@ -1,45 +1,44 @@
+use anyhow::{bail, Result};
 use chrono::DateTime;
 use chrono_tz::Tz;
-use itertools::Itertools;
 use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::VecDeque;
 
 use crate::model::{
-    candle_stick::CandleStick, decimal::DecimalVec, position::Position,
-    position_direction::PositionDirection, session::Session, trade::Trade,
-    trade_result::TradeResult, trigger_type::TriggerType,
+    ambiguity_policy::AmbiguityPolicy, candle::Candle, decimal::DecimalVec, position::Position,
+    position_direction::PositionDirection, session::Session, trigger_type::TriggerType,
 };
+use crate::mtf::MtfError;
 
-pub fn is_swing_low(actual: CandleStick, previous: CandleStick, next: CandleStick) -> bool {
+pub fn is_swing_low(actual: Candle, previous: Candle, next: Candle) -> bool {
     actual.low < previous.low && actual.low < next.low
 }
 
-pub fn is_swing_high(actual: CandleStick, previous: CandleStick, next: CandleStick) -> bool {
+pub fn is_swing_high(actual: Candle, previous: Candle, next: Candle) -> bool {
     actual.high > previous.high && actual.high > next.high
 }
 
 pub fn add_to_swings(
-    swing_lows: &mut Vec<CandleStick>,
-    swing_highs: &mut Vec<CandleStick>,
-    actual: CandleStick,
-    previous: CandleStick,
-    next: CandleStick,
+    swing_lows: &mut Vec<Candle>,
+    swing_highs: &mut Vec<Candle>,
+    actual: Candle,
+    previous: Candle,
+    next: Candle,
 ) {
     if is_swing_high(actual, previous, next) {
         // remove previous highs with lower high
-        swing_highs.retain(|&c: &CandleStick| c.high >= actual.high);
+        swing_highs.retain(|&c: &Candle| c.high >= actual.high);
         swing_highs.push(actual);
     }
     if is_swing_low(actual, previous, next) {
         // remove previous lows with higher lows
-        swing_lows.retain(|&c: &CandleStick| c.low <= actual.low);
+        swing_lows.retain(|&c: &Candle| c.low <= actual.low);
         swing_lows.push(actual);
     }
 }
 
-pub fn first_swing(
-    candles: Vec<CandleStick>,
-    p: fn(CandleStick, CandleStick, CandleStick) -> bool,
-) -> Option<CandleStick> {
+pub fn first_swing(candles: Vec<Candle>, p: fn(Candle, Candle, Candle) -> bool) -> Option<Candle> {
     let mut ind = 0;
     while ind < candles.len() {
         if ind > 0 && ind < candles.len() - 1 {
@@ -55,146 +54,302 @@ pub fn first_swing(
     None
 }
 
+// Swings of `left` candles before and `right` candles after, a 5 bar fractal is 2 and 2.
+// Candles on the right within `equal_tolerance` of the swing count as equal highs or lows
+// and don't break it, without a tolerance every neighbour has to be strictly beyond.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SwingConfig {
+    pub left: usize,
+    pub right: usize,
+    pub equal_tolerance: Option<Decimal>,
+    pub pruning: SwingPruning,
+}
+
+// which older swings a tracker drops
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SwingPruning {
+    // a new swing high drops the older ones below it and a new swing low the ones above it,
+    // the way `add_to_swings` does
+    #[default]
+    Superseded,
+    // a swing is dropped once a candle closes beyond it, a wick through it keeps it
+    Broken,
+    // every swing stays
+    Keep,
+}
+
+// the 3 candle swings of `is_swing_high` and `is_swing_low`
+impl Default for SwingConfig {
+    fn default() -> Self {
+        SwingConfig {
+            left: 1,
+            right: 1,
+            equal_tolerance: None,
+            pruning: SwingPruning::Superseded,
+        }
+    }
+}
+
+impl SwingConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.left == 0 || self.right == 0 {
+            bail!("swings need at least one candle on each side");
+        }
+        if self.equal_tolerance.is_some_and(|t| t < Decimal::ZERO) {
+            bail!("equal_tolerance can't be negative");
+        }
+        Ok(())
+    }
+
+    // false without `left` candles before `ind` and `right` after it
+    pub fn is_swing_high(&self, candles: &[Candle], ind: usize) -> bool {
+        let Some((before, actual, after)) = self.around(candles, ind) else {
+            return false;
+        };
+        before.iter().all(|c| c.high < actual.high)
+            && after
+                .iter()
+                .all(|c| c.high < actual.high || self.equal(c.high, actual.high))
+    }
+
+    pub fn is_swing_low(&self, candles: &[Candle], ind: usize) -> bool {
+        let Some((before, actual, after)) = self.around(candles, ind) else {
+            return false;
+        };
+        before.iter().all(|c| c.low > actual.low)
+            && after
+                .iter()
+                .all(|c| c.low > actual.low || self.equal(c.low, actual.low))
+    }
+
+    fn around<'a>(
+        &self,
+        candles: &'a [Candle],
+        ind: usize,
+    ) -> Option<(&'a [Candle], Candle, &'a [Candle])> {
+        if ind < self.left || ind + self.right >= candles.len() {
+            return None;
+        }
+        Some((
+            &candles[ind - self.left..ind],
+            candles[ind],
+            &candles[ind + 1..=ind + self.right],
+        ))
+    }
+
+    fn equal(&self, a: DecimalVec, b: DecimalVec) -> bool {
+        self.equal_tolerance.is_some_and(|t| (a.0 - b.0).abs() <= t)
+    }
+}
+
+// Confirmed swings of a series fed one closed candle at a time. A swing only shows up once its
+// `right` candles have closed, so a model reading the tracker never sees the future.
+#[derive(Debug, Clone)]
+pub struct SwingTracker {
+    config: SwingConfig,
+    // the last `left + right + 1` candles, the swing candidate in the middle
+    window: VecDeque<Candle>,
+    highs: Vec<Candle>,
+    lows: Vec<Candle>,
+}
+
+impl SwingTracker {
+    pub fn new(config: SwingConfig) -> Self {
+        SwingTracker {
+            config,
+            window: VecDeque::with_capacity(config.left + config.right + 1),
+            highs: vec![],
+            lows: vec![],
+        }
+    }
+
+    // swing highs still standing, oldest first
+    pub fn highs(&self) -> &[Candle] {
+        &self.highs
+    }
+
+    pub fn lows(&self) -> &[Candle] {
+        &self.lows
+    }
+
+    // takes the next closed candle and confirms the candle `right` candles back
+    pub fn update(&mut self, candle: Candle) {
+        if self.config.pruning == SwingPruning::Broken {
+            self.highs.retain(|c| c.high >= candle.close);
+            self.lows.retain(|c| c.low <= candle.close);
+        }
+
+        self.window.push_back(candle);
+        let size = self.config.left + self.config.right + 1;
+        if self.window.len() > size {
+            self.window.pop_front();
+        }
+        if self.window.len() < size {
+            return;
+        }
+        let window = self.window.make_contiguous();
+        let ind = self.config.left;
+        let actual = window[ind];
+        let superseded = self.config.pruning == SwingPruning::Superseded;
+
+        if self.config.is_swing_high(window, ind) {
+            if superseded {
+                self.highs.retain(|c| c.high >= actual.high);
+            }
+            self.highs.push(actual);
+        }
+        if self.config.is_swing_low(window, ind) {
+            if superseded {
+                self.lows.retain(|c| c.low <= actual.low);
+            }
+            self.lows.push(actual);
+        }
+    }
+}
+
 // TODO: test these
-pub fn find_sfp_high(actual: CandleStick, swing_highs: &Vec<CandleStick>) -> Option<&CandleStick> {
+pub fn find_sfp_high(actual: Candle, swing_highs: &Vec<Candle>) -> Option<&Candle> {
     swing_highs
         .iter()
         .find(|x| x.close_time < actual.close_time && x.high < actual.high && x.high > actual.close)
 }
 
-pub fn find_sfp_low(actual: CandleStick, swing_lows: &Vec<CandleStick>) -> Option<&CandleStick> {
+pub fn find_sfp_low(actual: Candle, swing_lows: &Vec<Candle>) -> Option<&Candle> {
     swing_lows
         .iter()
         .find(|x| x.close_time < actual.close_time && x.low > actual.low && x.low < actual.close)
 }
 
+// the first lower timeframe candle inside `candle` matching `p`, missing lower timeframe data
+// is an error instead of a panic
 pub fn find_candle(
-    candle: CandleStick,
-    data: &Vec<CandleStick>,
-    p: fn(CandleStick, CandleStick) -> bool,
-) -> &CandleStick {
-    data.iter()
-        .find(|x| {
-            x.open_time >= candle.open_time && x.close_time <= candle.close_time && p(**x, candle)
-        })
-        .expect("Failed to find the matching candle in ltf_data")
+    candle: Candle,
+    data: &[Candle],
+    p: fn(Candle, Candle) -> bool,
+) -> Result<&Candle, MtfError> {
+    try_find_candle(candle, data, p).ok_or_else(|| {
+        if try_find_candle(candle, data, |_, _| true).is_none() {
+            MtfError::MissingLtf {
+                htf_open_time: candle.open_time,
+            }
+        } else {
+            MtfError::NoMatch {
+                htf_open_time: candle.open_time,
+            }
+        }
+    })
 }
 
+// the first lower timeframe candle inside `candle` matching `p`
+pub fn try_find_candle(
+    candle: Candle,
+    data: &[Candle],
+    p: impl Fn(Candle, Candle) -> bool,
+) -> Option<&Candle> {
+    data.iter().find(|x| {
+        x.open_time >= candle.open_time && x.close_time <= candle.close_time && p(**x, candle)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Triggered,
+    Invalidated,
+}
+
+// what `actual` does to a trade planned to enter past `trigger_level` unless price reaches
+// `invalidation_level` first, the trigger wins when the candle reaches both
 pub fn trigger_or_invalidation(
-    candles: Vec<CandleStick>,
+    actual: Candle,
     direction: PositionDirection,
     trigger_level: DecimalVec,
     invalidation_level: DecimalVec,
     trigger_type: TriggerType,
-) -> Option<CandleStick> {
-    for actual in candles {
-        match direction {
-            PositionDirection::Short => {
-                let trigger_comparision = match trigger_type {
-                    TriggerType::Close => actual.close,
-                    TriggerType::Wick => actual.low,
-                };
-                if trigger_comparision < trigger_level {
-                    return Some(actual);
-                }
-
-                if actual.high >= invalidation_level {
-                    return None;
-                }
-            }
-            PositionDirection::Long => {
-                let trigger_comparision = match trigger_type {
-                    TriggerType::Close => actual.close,
-                    TriggerType::Wick => actual.high,
-                };
-                if trigger_comparision > trigger_level {
-                    return Some(actual);
-                }
-                if actual.low <= invalidation_level {
-                    return None;
-                }
-            }
+) -> Option<Trigger> {
+    let (triggered, invalidated) = match direction {
+        PositionDirection::Short => {
+            let trigger = match trigger_type {
+                TriggerType::Close => actual.close,
+                TriggerType::Wick => actual.low,
+            };
+            (trigger < trigger_level, actual.high >= invalidation_level)
+        }
+        PositionDirection::Long => {
+            let trigger = match trigger_type {
+                TriggerType::Close => actual.close,
+                TriggerType::Wick => actual.high,
+            };
+            (trigger > trigger_level, actual.low <= invalidation_level)
         }
+    };
+    match (triggered, invalidated) {
+        (true, _) => Some(Trigger::Triggered),
+        (false, true) => Some(Trigger::Invalidated),
+        (false, false) => None,
     }
-    return None;
 }
 
-pub fn trigger_mayne(
-    direction: PositionDirection,
-    trigger_type: TriggerType,
-    trigger_level: DecimalVec,
-    sl: DecimalVec,
-    tp: DecimalVec,
-    rr_threshold: Decimal,
-    candles: Vec<CandleStick>,
-    trades: &mut Vec<Trade>,
-) {
-    let trigger_candle =
-        trigger_or_invalidation(candles.clone(), direction, trigger_level, sl, trigger_type);
-    if let Some(tc) = trigger_candle {
-        let position = Position {
-            direction,
-            open_time: tc.close_time,
-            entry: tc.close,
-            sl, // TODO: can we refine this? eg: previous swing high on ltf
-            tp,
-            at_break_even: false,
-        };
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    Stop,
+    Target,
+}
 
-        if position.rr().0 >= rr_threshold {
-            let candles_after_entry = candles
-                .iter()
-                .skip_while(|x| x.open_time <= tc.open_time)
-                .collect_vec();
-            let trade = run_trade(position, candles_after_entry);
-            if let Some(t) = trade {
-                trades.push(t)
-            }
-        }
+fn stop_hit(position: &Position, candle: Candle) -> bool {
+    match position.direction {
+        PositionDirection::Short => position.sl < candle.high,
+        PositionDirection::Long => position.sl > candle.low,
+    }
+}
+
+fn target_hit(position: &Position, candle: Candle) -> bool {
+    match position.direction {
+        PositionDirection::Short => position.tp > candle.low,
+        PositionDirection::Long => position.tp < candle.high,
+    }
+}
+
+// whether the candle closes the position, and if that depended on the ambiguity policy
+pub fn check_exit(
+    position: &Position,
+    candle: Candle,
+    ambiguity: &AmbiguityPolicy,
+) -> Option<(Exit, bool)> {
+    match (stop_hit(position, candle), target_hit(position, candle)) {
+        (false, false) => None,
+        (true, false) => Some((Exit::Stop, false)),
+        (false, true) => Some((Exit::Target, false)),
+        (true, true) => Some((resolve_ambiguity(position, candle, ambiguity), true)),
     }
 }
-// pub fn look_for_entry(candles: Vec<CandleStick>) {}
-
-pub fn run_trade(position: Position, candles: Vec<&CandleStick>) -> Option<Trade> {
-    for actual in candles {
-        match position.direction {
-            PositionDirection::Short => {
-                if position.sl < actual.high {
-                    return Some(Trade::from_position(
-                        position,
-                        actual.close_time,
-                        TradeResult::Expense,
-                    ));
-                }
-                if position.tp > actual.low {
-                    return Some(Trade::from_position(
-                        position,
-                        actual.close_time,
-                        TradeResult::Winner,
-                    ));
-                }
+
+fn resolve_ambiguity(position: &Position, candle: Candle, ambiguity: &AmbiguityPolicy) -> Exit {
+    match ambiguity {
+        AmbiguityPolicy::Pessimistic => Exit::Stop,
+        AmbiguityPolicy::Optimistic => Exit::Target,
+        AmbiguityPolicy::OpenProximity => {
+            if (candle.open - position.tp).0.abs() < (candle.open - position.sl).0.abs() {
+                Exit::Target
+            } else {
+                Exit::Stop
             }
-            PositionDirection::Long => {
-                if position.sl > actual.low {
-                    return Some(Trade::from_position(
-                        position,
-                        actual.close_time,
-                        TradeResult::Expense,
-                    ));
-                }
-                if position.tp < actual.high {
-                    return Some(Trade::from_position(
-                        position,
-                        actual.close_time,
-                        TradeResult::Winner,
-                    ));
-                }
+        }
+        AmbiguityPolicy::LowerTimeframe(ltf) => {
+            let first = try_find_candle(candle, ltf, |x, _| {
+                stop_hit(position, x) || target_hit(position, x)
+            });
+            match first {
+                Some(x) if !stop_hit(position, *x) => Exit::Target,
+                _ => Exit::Stop,
             }
         }
     }
-    return None;
 }
 
+// pub fn look_for_entry(candles: Vec<Candle>) {}
+
 pub fn in_session(session: &Session, open_time: DateTime<Tz>) -> bool {
     open_time.time() >= session.start && open_time.time() < session.end
 }
@@ -205,28 +360,32 @@ mod tests {
     use lazy_static::lazy_static;
 
     use super::*;
-    use crate::{model::candle_stick::CandleStick, parse_datetime};
+    use crate::{model::candle::Candle, parse_datetime, to_new_york_time};
     use rust_decimal::{prelude::FromPrimitive, Decimal};
 
-    fn candlestick(high: i32, low: i32) -> CandleStick {
-        CandleStick {
-            open_time: 0,
+    fn candlestick(high: i32, low: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(0),
             open: DecimalVec(Decimal::from(0)),
             high: DecimalVec(Decimal::from(high)),
             low: DecimalVec(Decimal::from(low)),
             close: DecimalVec(Decimal::from(0)),
-            close_time: 0,
+            close_time: to_new_york_time(0),
+            volume: DecimalVec(Decimal::from(0)),
+            number_of_trades: 0,
         }
     }
 
-    fn candlestick_high_close(close_time: i64, high: f32, close: f32) -> CandleStick {
-        CandleStick {
-            open_time: 0,
+    fn candlestick_high_close(close_time: i64, high: f32, close: f32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(0),
             open: DecimalVec(Decimal::from(0)),
             high: DecimalVec(Decimal::from_f32(high).unwrap()),
             low: DecimalVec(Decimal::from(0)),
             close: DecimalVec(Decimal::from_f32(close).unwrap()),
-            close_time,
+            close_time: to_new_york_time(close_time),
+            volume: DecimalVec(Decimal::from(0)),
+            number_of_trades: 0,
         }
     }
 
@@ -536,6 +695,119 @@ mod tests {
         assert_eq!(swing_highs, vec![candlestick(15, 3)]);
     }
 
+    fn highs(highs: &[i32]) -> Vec<Candle> {
+        highs.iter().map(|&high| candlestick(high, 0)).collect()
+    }
+
+    #[test]
+    fn test_five_bar_swing_high() {
+        let config = SwingConfig {
+            left: 2,
+            right: 2,
+            ..SwingConfig::default()
+        };
+        assert!(config.is_swing_high(&highs(&[1, 2, 5, 3, 4]), 2));
+        assert!(!config.is_swing_high(&highs(&[6, 2, 5, 3, 4]), 2));
+        assert!(!config.is_swing_high(&highs(&[1, 2, 5, 3, 6]), 2));
+        // not enough candles on the right yet
+        assert!(!config.is_swing_high(&highs(&[1, 2, 5, 3]), 2));
+        assert!(!config.is_swing_high(&highs(&[2, 5, 3, 4]), 1));
+    }
+
+    #[test]
+    fn test_five_bar_swing_low() {
+        let config = SwingConfig {
+            left: 2,
+            right: 2,
+            ..SwingConfig::default()
+        };
+        let lows = |lows: &[i32]| -> Vec<Candle> {
+            lows.iter().map(|&low| candlestick(20, low)).collect()
+        };
+        assert!(config.is_swing_low(&lows(&[5, 4, 1, 3, 2]), 2));
+        assert!(!config.is_swing_low(&lows(&[5, 0, 1, 3, 2]), 2));
+    }
+
+    #[test]
+    fn test_equal_highs_tolerance() {
+        let strict = SwingConfig::default();
+        let tolerant = SwingConfig {
+            equal_tolerance: Some(Decimal::ONE),
+            ..SwingConfig::default()
+        };
+        // a double top within the tolerance keeps the first top
+        assert!(!strict.is_swing_high(&highs(&[3, 10, 10]), 1));
+        assert!(tolerant.is_swing_high(&highs(&[3, 10, 10]), 1));
+        assert!(tolerant.is_swing_high(&highs(&[3, 10, 11]), 1));
+        assert!(!tolerant.is_swing_high(&highs(&[3, 10, 12]), 1));
+        // the second top has an equal high on its left
+        assert!(!tolerant.is_swing_high(&highs(&[10, 10, 3]), 1));
+    }
+
+    #[test]
+    fn test_tracker_confirms_after_right_closes() {
+        let mut tracker = SwingTracker::new(SwingConfig {
+            left: 1,
+            right: 2,
+            ..SwingConfig::default()
+        });
+        for candle in highs(&[1, 5, 3]) {
+            tracker.update(candle);
+        }
+        assert!(tracker.highs().is_empty());
+
+        tracker.update(candlestick(2, 0));
+        assert_eq!(tracker.highs(), &[candlestick(5, 0)]);
+    }
+
+    #[test]
+    fn test_tracker_default_matches_add_to_swings() {
+        let candles: Vec<Candle> = [(5, 3), (8, 4), (6, 2), (7, 5), (9, 3), (4, 1), (6, 2)]
+            .iter()
+            .map(|&(high, low)| candlestick(high, low))
+            .collect();
+        let mut swing_lows = vec![];
+        let mut swing_highs = vec![];
+        for ind in 1..candles.len() - 1 {
+            add_to_swings(
+                &mut swing_lows,
+                &mut swing_highs,
+                candles[ind],
+                candles[ind - 1],
+                candles[ind + 1],
+            );
+        }
+
+        let mut tracker = SwingTracker::new(SwingConfig::default());
+        for candle in &candles {
+            tracker.update(*candle);
+        }
+        assert_eq!(tracker.highs(), swing_highs.as_slice());
+        assert_eq!(tracker.lows(), swing_lows.as_slice());
+    }
+
+    #[test]
+    fn test_tracker_broken_pruning() {
+        let mut tracker = SwingTracker::new(SwingConfig {
+            pruning: SwingPruning::Broken,
+            ..SwingConfig::default()
+        });
+        let close = |high: i32, close: i32| Candle {
+            close: DecimalVec::new(close),
+            ..candlestick(high, 0)
+        };
+        for candle in [close(5, 4), close(10, 8), close(7, 6)] {
+            tracker.update(candle);
+        }
+        assert_eq!(tracker.highs(), &[close(10, 8)]);
+
+        // a wick through the swing keeps it, a close beyond drops it
+        tracker.update(close(12, 9));
+        assert_eq!(tracker.highs().len(), 1);
+        tracker.update(close(13, 11));
+        assert!(tracker.highs().is_empty());
+    }
+
     #[test]
     fn test_find_sfp_high_basic() {
         let actual = candlestick_high_close(10, 110.0, 105.0);
@@ -568,7 +840,7 @@ mod tests {
         let actual = candlestick_high_close(10, 110.0, 105.0);
 
         // Test with an empty vector
-        let swing_highs: Vec<CandleStick> = vec![];
+        let swing_highs: Vec<Candle> = vec![];
         let result = find_sfp_high(actual, &swing_highs);
         assert_eq!(result, None);
 
@@ -610,4 +882,133 @@ mod tests {
         let result = in_session(&SESSION, parse_datetime("2022-09-30 08:50:00").unwrap());
         assert!(result);
     }
+
+    // a long from 100 with the stop at 90 and the target at 120
+    fn long() -> Position {
+        Position {
+            direction: PositionDirection::Long,
+            open_time: to_new_york_time(0),
+            entry: DecimalVec::new(100),
+            sl: DecimalVec::new(90),
+            tp: DecimalVec::new(120),
+            at_break_even: false,
+        }
+    }
+
+    fn bar(open_time: i64, minutes: i64, open: i32, high: i32, low: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(open_time),
+            close_time: to_new_york_time(open_time + minutes * 60 - 1),
+            open: DecimalVec::new(open),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(open),
+            volume: DecimalVec::new(0),
+            number_of_trades: 0,
+        }
+    }
+
+    #[test]
+    fn test_find_candle() {
+        let candle = bar(0, 15, 100, 125, 85);
+        let ltf = vec![bar(0, 5, 100, 105, 95), bar(300, 5, 105, 125, 100)];
+
+        let found = find_candle(candle, &ltf, |x, htf| x.high == htf.high).unwrap();
+        assert_eq!(found, &ltf[1]);
+        assert_eq!(
+            find_candle(candle, &ltf, |x, htf| x.low == htf.low),
+            Err(MtfError::NoMatch {
+                htf_open_time: candle.open_time
+            })
+        );
+        assert_eq!(
+            find_candle(candle, &[], |_, _| true),
+            Err(MtfError::MissingLtf {
+                htf_open_time: candle.open_time
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_exit_single_level() {
+        let policy = AmbiguityPolicy::Optimistic;
+        assert_eq!(check_exit(&long(), bar(0, 15, 100, 110, 95), &policy), None);
+        assert_eq!(
+            check_exit(&long(), bar(0, 15, 100, 110, 85), &policy),
+            Some((Exit::Stop, false))
+        );
+        assert_eq!(
+            check_exit(&long(), bar(0, 15, 100, 125, 95), &policy),
+            Some((Exit::Target, false))
+        );
+    }
+
+    #[test]
+    fn test_check_exit_ambiguous_policies() {
+        // opens next to the target
+        let candle = bar(0, 15, 115, 125, 85);
+        let exit = |policy| check_exit(&long(), candle, &policy);
+
+        assert_eq!(exit(AmbiguityPolicy::Pessimistic), Some((Exit::Stop, true)));
+        assert_eq!(
+            exit(AmbiguityPolicy::Optimistic),
+            Some((Exit::Target, true))
+        );
+        assert_eq!(
+            exit(AmbiguityPolicy::OpenProximity),
+            Some((Exit::Target, true))
+        );
+    }
+
+    #[test]
+    fn test_check_exit_lower_timeframe() {
+        let candle = bar(0, 15, 100, 125, 85);
+        // the 5 minute candles reach the target before the stop
+        let ltf = vec![
+            bar(-300, 5, 100, 105, 95),
+            bar(0, 5, 100, 105, 95),
+            bar(300, 5, 105, 125, 100),
+            bar(600, 5, 110, 110, 85),
+        ];
+        let policy = AmbiguityPolicy::LowerTimeframe(std::sync::Arc::new(ltf));
+        assert_eq!(
+            check_exit(&long(), candle, &policy),
+            Some((Exit::Target, true))
+        );
+
+        // no lower timeframe data falls back to the stop
+        let policy = AmbiguityPolicy::LowerTimeframe(std::sync::Arc::new(vec![]));
+        assert_eq!(
+            check_exit(&long(), candle, &policy),
+            Some((Exit::Stop, true))
+        );
+    }
+
+    #[test]
+    fn test_trigger_or_invalidation() {
+        let short = |candle, trigger_type| {
+            trigger_or_invalidation(
+                candle,
+                PositionDirection::Short,
+                DecimalVec::new(100),
+                DecimalVec::new(110),
+                trigger_type,
+            )
+        };
+        assert_eq!(short(bar(0, 15, 103, 105, 101), TriggerType::Wick), None);
+        assert_eq!(
+            short(bar(0, 15, 101, 105, 99), TriggerType::Wick),
+            Some(Trigger::Triggered)
+        );
+        assert_eq!(short(bar(0, 15, 101, 105, 99), TriggerType::Close), None);
+        // the trigger wins over the invalidation on one candle
+        assert_eq!(
+            short(bar(0, 15, 105, 110, 99), TriggerType::Wick),
+            Some(Trigger::Triggered)
+        );
+        assert_eq!(
+            short(bar(0, 15, 105, 110, 99), TriggerType::Close),
+            Some(Trigger::Invalidated)
+        );
+    }
 }
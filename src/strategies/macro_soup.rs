@@ -1,31 +1,36 @@
 use chrono::Duration;
-use itertools::Itertools;
 use rust_decimal::Decimal;
 
-use crate::model::backtest_result::BacktestResult;
-use crate::model::candle_ny::CandleNY;
+use crate::engine::{CandleModel, CandleStrategy, CandleView, Order, OrderBook};
+use crate::model::ambiguity_policy::AmbiguityPolicy;
+use crate::model::candle::Candle;
+use crate::model::cost_model::CostModel;
 use crate::model::decimal::DecimalVec;
 use crate::model::position::Position;
 use crate::model::session::Session;
-use crate::model::trade::Trade;
-use crate::model::trade_result::TradeResult;
-use crate::model::trading_model::TradingModel;
+use crate::model::trade_management::TradeManagement;
 
 use super::lib::in_session;
 use crate::model::position_direction::PositionDirection;
 
+// Trades the first raid of the session range and close back inside it after every session.
+// One position at a time: a session's signal while the trade of an earlier session is still
+// open is dropped.
 pub struct MacroSoup {
     pub rr_threshold: Decimal,
     pub session: Session,
-    pub candles: Vec<CandleNY>,
     pub max_duration_min: i64,
+    // moves the stop to break even, the same as `management.break_even_r`, only one may be set
     pub be_threshold: Option<DecimalVec>,
+    pub management: TradeManagement,
+    pub costs: CostModel,
+    pub ambiguity: AmbiguityPolicy,
 }
 
 impl MacroSoup {
     // looking for candles out of the range
     pub fn trigger_or_invalidation(
-        candles: Vec<&CandleNY>,
+        candles: Vec<&Candle>,
         session_high: DecimalVec,
         session_low: DecimalVec,
         max_duration_min: i64,
@@ -55,7 +60,7 @@ impl MacroSoup {
                 if actual.close < session_high && actual.clone().bearish() {
                     return Some(Position {
                         direction: PositionDirection::Short,
-                        open_time: actual.open_time.timestamp(), // TODO: check with proper timezone
+                        open_time: actual.open_time,
                         entry: actual.close,
                         sl: max,
                         tp: session_low - (session_high - session_low), // stdv1,
@@ -76,7 +81,7 @@ impl MacroSoup {
                 if actual.close > session_low && actual.clone().bullish() {
                     return Some(Position {
                         direction: PositionDirection::Long,
-                        open_time: actual.open_time.timestamp(), // TODO: check with proper timezone
+                        open_time: actual.open_time,
                         entry: actual.close,
                         sl: min,
                         tp: session_high + (session_high - session_low), // stdv1
@@ -87,132 +92,105 @@ impl MacroSoup {
         }
         None
     }
+}
 
-    // TODO: test
-    pub fn run_trade(
-        position: Position,
-        candles: Vec<&CandleNY>,
-        be_threshold: Option<DecimalVec>,
-    ) -> Option<Trade> {
-        let mut p = position.clone();
-        for actual in candles {
-            match p.direction {
-                PositionDirection::Short => {
-                    if p.sl < actual.high {
-                        return Some(Trade::from_position(
-                            p,
-                            actual.open_time.timestamp(),
-                            if p.at_break_even {
-                                TradeResult::BreakEven
-                            } else {
-                                TradeResult::Expense
-                            },
-                        ));
-                    }
-                    if p.tp > actual.low {
-                        return Some(Trade::from_position(
-                            p,
-                            actual.open_time.timestamp(),
-                            TradeResult::Winner,
-                        ));
-                    }
-                    if let Some(bet) = be_threshold {
-                        if actual.low < p.entry && p.actual_rr(actual.low) > bet {
-                            p.move_to_break_even();
-                        }
-                    }
-                }
-                PositionDirection::Long => {
-                    if p.sl > actual.low {
-                        return Some(Trade::from_position(
-                            p,
-                            actual.open_time.timestamp(),
-                            if p.at_break_even {
-                                TradeResult::BreakEven
-                            } else {
-                                TradeResult::Expense
-                            },
-                        ));
-                    }
-                    if p.tp < actual.high {
-                        return Some(Trade::from_position(
-                            p,
-                            actual.open_time.timestamp(),
-                            TradeResult::Winner,
-                        ));
-                    }
-                    if let Some(bet) = be_threshold {
-                        if actual.high > p.entry && p.actual_rr(actual.high) > bet {
-                            p.move_to_break_even();
-                        }
-                    }
-                }
-            }
+impl CandleStrategy for MacroSoup {
+    type Model = Signals;
+
+    fn model(&self) -> Signals {
+        Signals {
+            rr_threshold: self.rr_threshold,
+            session: self.session,
+            max_duration_min: self.max_duration_min,
+            session_high: None,
+            session_low: None,
+            last_candle_in_session: false,
+            pending: None,
         }
-        return None;
+    }
+
+    fn management(&self) -> TradeManagement {
+        TradeManagement {
+            break_even_r: self
+                .management
+                .break_even_r
+                .or(self.be_threshold.map(|x| x.0)),
+            ..self.management.clone()
+        }
+    }
+
+    fn costs(&self) -> &CostModel {
+        &self.costs
+    }
+
+    fn ambiguity(&self) -> &AmbiguityPolicy {
+        &self.ambiguity
     }
 }
 
-impl TradingModel for MacroSoup {
-    fn execute(&self) -> BacktestResult {
-        let mut trades: Vec<Trade> = vec![];
-        let mut session_high: Option<DecimalVec> = None;
-        let mut session_low: Option<DecimalVec> = None;
-        let mut last_candle_in_session = false;
-
-        for actual in self.candles.clone() {
-            if in_session(&self.session, actual.open_time) {
-                match session_low {
-                    Some(s) => {
-                        if s > actual.low {
-                            session_low = Some(actual.low)
-                        }
-                    }
-                    None => session_low = Some(actual.low),
+pub struct Signals {
+    rr_threshold: Decimal,
+    session: Session,
+    max_duration_min: i64,
+    session_high: Option<DecimalVec>,
+    session_low: Option<DecimalVec>,
+    last_candle_in_session: bool,
+    // the range of the last session and the candles after it, until a trigger or the time limit
+    pending: Option<(DecimalVec, DecimalVec, Vec<Candle>)>,
+}
+
+impl CandleModel for Signals {
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+        let actual = view.actual();
+
+        if let Some((session_high, session_low, candles)) = &mut self.pending {
+            candles.push(actual);
+            let time_threshold = candles[0].open_time + Duration::minutes(self.max_duration_min);
+            if actual.open_time >= time_threshold {
+                self.pending = None;
+            } else if let Some(position) = MacroSoup::trigger_or_invalidation(
+                candles.iter().collect(),
+                *session_high,
+                *session_low,
+                self.max_duration_min,
+            ) {
+                self.pending = None;
+                if view.flat && position.rr().0 >= self.rr_threshold {
+                    orders.place(Order::market(position.direction, position.sl, position.tp));
                 }
-                match session_high {
-                    Some(s) => {
-                        if s < actual.high {
-                            session_high = Some(actual.high)
-                        }
+            }
+        }
+
+        if in_session(&self.session, actual.open_time) {
+            match self.session_low {
+                Some(s) => {
+                    if s > actual.low {
+                        self.session_low = Some(actual.low)
                     }
-                    None => session_high = Some(actual.high),
                 }
-                last_candle_in_session = true;
-            } else if last_candle_in_session {
-                let c = self.candles.clone();
-                let candles_after_session = c
-                    .iter()
-                    .skip_while(|x| x.open_time <= actual.open_time)
-                    .collect_vec();
-                // this is the first candle after the session ended
-                // find trigger + run trade
-                if let Some(position) = Self::trigger_or_invalidation(
-                    candles_after_session,
-                    session_high.unwrap(),
-                    session_low.unwrap(),
-                    self.max_duration_min,
-                ) {
-                    if position.rr().0 >= self.rr_threshold {
-                        let c = self.candles.clone();
-                        let candles_after_entry = c
-                            .iter()
-                            .skip_while(|x| x.open_time.timestamp() <= position.open_time)
-                            .collect_vec();
-                        let trade =
-                            Self::run_trade(position, candles_after_entry, self.be_threshold);
-                        if let Some(t) = trade {
-                            trades.push(t)
-                        }
+                None => self.session_low = Some(actual.low),
+            }
+            match self.session_high {
+                Some(s) => {
+                    if s < actual.high {
+                        self.session_high = Some(actual.high)
                     }
                 }
-                last_candle_in_session = false;
-                session_low = None;
-                session_high = None;
+                None => self.session_high = Some(actual.high),
             }
+            self.last_candle_in_session = true;
+        } else if self.last_candle_in_session {
+            // this is the first candle after the session ended, the trigger is looked for
+            // from the next one
+            self.pending = Some((
+                self.session_high.unwrap(),
+                self.session_low.unwrap(),
+                vec![],
+            ));
+            self.last_candle_in_session = false;
+            self.session_low = None;
+            self.session_high = None;
         }
-
-        BacktestResult { trades }
     }
 }
 
@@ -221,20 +199,26 @@ mod tests {
     use lazy_static::lazy_static;
 
     use super::*;
+    use crate::engine::OnCandles;
+    use crate::model::trading_model::TradingModel;
     use crate::parse_datetime;
+    use chrono::NaiveTime;
     use rust_decimal::Decimal;
 
     fn date(date_time: &str) -> chrono::DateTime<chrono_tz::Tz> {
         parse_datetime(date_time).unwrap()
     }
 
-    fn candlestick(duration: i64, open: i32, high: i32, low: i32, close: i32) -> CandleNY {
-        CandleNY {
+    fn candlestick(duration: i64, open: i32, high: i32, low: i32, close: i32) -> Candle {
+        Candle {
             open_time: date("2022-09-30 08:50:00") + Duration::minutes(duration),
+            close_time: date("2022-09-30 08:51:00") + Duration::minutes(duration),
             open: DecimalVec(Decimal::from(open)),
             high: DecimalVec(Decimal::from(high)),
             low: DecimalVec(Decimal::from(low)),
             close: DecimalVec(Decimal::from(close)),
+            volume: DecimalVec(Decimal::from(0)),
+            number_of_trades: 0,
         }
     }
 
@@ -243,10 +227,67 @@ mod tests {
         static ref SESSION_LOW: DecimalVec = DecimalVec(Decimal::from(60));
     }
 
-    fn trigger(candles: Vec<&CandleNY>) -> Option<Position> {
+    fn trigger(candles: Vec<&Candle>) -> Option<Position> {
         MacroSoup::trigger_or_invalidation(candles, *SESSION_HIGH, *SESSION_LOW, 4)
     }
 
+    // a short session at 09:50 on `day`, a raid above it at 09:53 that closes back inside
+    fn session_and_raid(day: &str, raid_high: i32) -> Vec<Candle> {
+        let bar = |time: &str, open, high, low, close| Candle {
+            open_time: date(&format!("{} {}", day, time)),
+            close_time: date(&format!("{} {}", day, time)) + Duration::minutes(1),
+            ..candlestick(0, open, high, low, close)
+        };
+        vec![
+            bar("09:50:00", 85, 100, 60, 80),
+            bar("09:51:00", 80, 95, 70, 85),
+            bar("09:52:00", 85, 90, 80, 85),
+            bar("09:53:00", 90, raid_high, 85, 88),
+        ]
+    }
+
+    fn macro_soup(candles: Vec<Candle>) -> OnCandles<MacroSoup> {
+        OnCandles {
+            candles,
+            strategy: MacroSoup {
+                rr_threshold: Decimal::from(2),
+                session: Session {
+                    start: NaiveTime::from_hms_opt(9, 50, 0).unwrap(),
+                    end: NaiveTime::from_hms_opt(9, 52, 0).unwrap(),
+                },
+                max_duration_min: 10,
+                be_threshold: None,
+                management: TradeManagement::default(),
+                costs: CostModel::default(),
+                ambiguity: AmbiguityPolicy::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_signal_while_in_a_trade_is_dropped() {
+        // the short of the first day, stopped at 110 with the target at 20, is still open when
+        // the second day raids its session
+        let first = session_and_raid("2022-09-29", 110);
+        let mut second = session_and_raid("2022-09-30", 105);
+        let target = Candle {
+            open_time: date("2022-09-30 09:54:00"),
+            close_time: date("2022-09-30 09:55:00"),
+            ..candlestick(0, 88, 89, 15, 19)
+        };
+        second.push(target);
+
+        let result = macro_soup([first.clone(), second.clone()].concat()).execute();
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].open_time, first[3].close_time);
+        assert_eq!(result.trades[0].close_time, target.close_time);
+
+        // flat, the second day trades
+        let result = macro_soup(second.clone()).execute();
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].open_time, second[3].close_time);
+    }
+
     #[test]
     fn test_trigger_or_invalidation_empty_candles() {
         assert!(trigger(vec![]).is_none());
@@ -270,7 +311,7 @@ mod tests {
         let result = trigger(vec![&candlestick(0, 90, 110, 80, 85)]);
         let expected = Position {
             direction: PositionDirection::Short,
-            open_time: date("2022-09-30 08:50:00").timestamp(),
+            open_time: date("2022-09-30 08:50:00"),
             entry: DecimalVec::new(85),
             sl: DecimalVec::new(110),
             tp: DecimalVec::new(20),
@@ -294,7 +335,7 @@ mod tests {
         ]);
         let expected = Position {
             direction: PositionDirection::Short,
-            open_time: date("2022-09-30 08:53:00").timestamp(),
+            open_time: date("2022-09-30 08:53:00"),
             entry: DecimalVec::new(95),
             sl: DecimalVec::new(120),
             tp: DecimalVec::new(20),
@@ -320,7 +361,7 @@ mod tests {
         let result = trigger(vec![&candlestick(0, 70, 75, 50, 80)]);
         let expected = Position {
             direction: PositionDirection::Long,
-            open_time: date("2022-09-30 08:50:00").timestamp(),
+            open_time: date("2022-09-30 08:50:00"),
             entry: DecimalVec::new(80),
             sl: DecimalVec::new(50),
             tp: DecimalVec::new(140),
@@ -344,7 +385,7 @@ mod tests {
         ]);
         let expected = Position {
             direction: PositionDirection::Long,
-            open_time: date("2022-09-30 08:53:00").timestamp(),
+            open_time: date("2022-09-30 08:53:00"),
             entry: DecimalVec::new(65),
             sl: DecimalVec::new(45),
             tp: DecimalVec::new(140),
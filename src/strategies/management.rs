@@ -0,0 +1,503 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::model::{
+    ambiguity_policy::AmbiguityPolicy,
+    candle::Candle,
+    decimal::DecimalVec,
+    position::Position,
+    position_direction::PositionDirection,
+    trade::{FillKind, Trade, TradeExit},
+    trade_management::{TakeProfit, TradeManagement, Trailing},
+    trade_result::TradeResult,
+};
+
+use crate::indicators::{Atr, Indicator};
+
+use super::lib::{check_exit, is_swing_high, is_swing_low, Exit};
+
+// An open position fed one candle at a time. Exits are checked against the stop and the
+// levels as they were when the candle opened, the stop moves after the candle closed.
+pub struct ManagedPosition {
+    position: Position,
+    management: TradeManagement,
+    // where what's left of the position is stopped out
+    stop: Decimal,
+    // the furthest price reached in the trade's favour
+    best: Decimal,
+    // part of the starting size still open
+    remaining: Decimal,
+    // take profits not reached yet, nearest first
+    take_profits: Vec<TakeProfit>,
+    // R of the exits so far, weighted by their size
+    realized: Decimal,
+    exits: Vec<TradeExit>,
+    ambiguous: bool,
+    // the last three candles for the swings
+    recent: Vec<Candle>,
+    atr: Option<Atr>,
+}
+
+impl ManagedPosition {
+    // `history` are the candles up to the entry, they warm up the atr and the swings
+    pub fn new(position: Position, management: &TradeManagement, history: &[Candle]) -> Self {
+        // short of the target, compared without dividing by a risk that may be zero
+        let risk = (position.entry.0 - position.sl.0).abs();
+        let reward = match position.direction {
+            PositionDirection::Long => position.tp.0 - position.entry.0,
+            PositionDirection::Short => position.entry.0 - position.tp.0,
+        };
+        let mut take_profits: Vec<TakeProfit> = management
+            .take_profits
+            .iter()
+            .filter(|tp| tp.r * risk < reward)
+            .copied()
+            .collect();
+        take_profits.sort_by_key(|tp| tp.r);
+
+        let mut managed = ManagedPosition {
+            position,
+            management: management.clone(),
+            stop: position.sl.0,
+            best: position.entry.0,
+            remaining: Decimal::ONE,
+            take_profits,
+            realized: Decimal::ZERO,
+            exits: vec![],
+            ambiguous: false,
+            recent: history[history.len().saturating_sub(3)..].to_vec(),
+            atr: None,
+        };
+        if let Some(Trailing::Atr { period, .. }) = management.trailing {
+            // long enough for Wilder's smoothing to forget where it started
+            let warm_up = &history[history.len().saturating_sub(10 * period)..];
+            let mut atr = Atr::new(period);
+            for candle in warm_up {
+                atr.next(candle);
+            }
+            managed.atr = Some(atr);
+        }
+        managed
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn stop(&self) -> DecimalVec {
+        DecimalVec(self.stop)
+    }
+
+    // A position filled inside `candle` can't tell whether the rest of the candle came before
+    // or after the fill, so a stop within the candle's range is taken as hit and a target isn't.
+    pub fn stopped_on_entry(&mut self, candle: Candle) -> Option<Trade> {
+        let hit = match self.position.direction {
+            PositionDirection::Long => candle.low.0 < self.stop,
+            PositionDirection::Short => candle.high.0 > self.stop,
+        };
+        if !hit {
+            return None;
+        }
+        self.close(self.remaining, self.stop, FillKind::Stop);
+        Some(self.trade(candle.close_time))
+    }
+
+    // the closed trade, without costs, once nothing is left of the position
+    pub fn update(&mut self, candle: Candle, ambiguity: &AmbiguityPolicy) -> Option<Trade> {
+        // the nearest level first, a target hit together with the stop is resolved by the
+        // ambiguity policy and the rest is stopped out on the same candle
+        while !self.remaining.is_zero() {
+            let (price, fraction) = match self.take_profits.first() {
+                Some(tp) => (self.price_at(tp.r), tp.fraction.min(self.remaining)),
+                None => (self.position.tp.0, self.remaining),
+            };
+            let level = Position {
+                sl: DecimalVec(self.stop),
+                tp: DecimalVec(price),
+                ..self.position
+            };
+            let Some((exit, ambiguous)) = check_exit(&level, candle, ambiguity) else {
+                break;
+            };
+            self.ambiguous |= ambiguous;
+            match exit {
+                Exit::Target => {
+                    self.close(fraction, price, FillKind::Limit);
+                    if !self.take_profits.is_empty() {
+                        self.take_profits.remove(0);
+                    }
+                }
+                Exit::Stop => self.close(self.remaining, self.stop, FillKind::Stop),
+            }
+        }
+
+        let time_exit = self.management.time_exit_min.is_some_and(|min| {
+            candle.close_time - self.position.open_time >= Duration::minutes(min)
+        });
+        if time_exit && !self.remaining.is_zero() {
+            self.close(self.remaining, candle.close.0, FillKind::Market);
+        }
+        if self.remaining.is_zero() {
+            return Some(self.trade(candle.close_time));
+        }
+
+        self.recent.push(candle);
+        if self.recent.len() > 3 {
+            self.recent.remove(0);
+        }
+        let atr = self.atr.as_mut().and_then(|atr| atr.next(&candle));
+        self.move_stop(candle, atr);
+        None
+    }
+
+    fn move_stop(&mut self, candle: Candle, atr: Option<Decimal>) {
+        let (favourable, direction) = match self.position.direction {
+            PositionDirection::Long => (candle.high.0, Decimal::ONE),
+            PositionDirection::Short => (candle.low.0, Decimal::NEGATIVE_ONE),
+        };
+        self.best = self.tighter(self.best, favourable);
+        let risk = self.risk();
+
+        let mut stop = self.stop;
+        if let Some(r) = self.management.break_even_r {
+            if self.r_at(self.best) >= r {
+                stop = self.tighter(stop, self.position.entry.0);
+            }
+        }
+        match self.management.trailing {
+            Some(Trailing::FixedR { r }) => {
+                stop = self.tighter(stop, self.best - direction * r * risk);
+            }
+            Some(Trailing::Atr { multiple, .. }) => {
+                if let Some(atr) = atr {
+                    stop = self.tighter(stop, self.best - direction * multiple * atr);
+                }
+            }
+            Some(Trailing::Swing) => {
+                if let [.., previous, actual, next] = self.recent[..] {
+                    match self.position.direction {
+                        PositionDirection::Long if is_swing_low(actual, previous, next) => {
+                            stop = self.tighter(stop, actual.low.0);
+                        }
+                        PositionDirection::Short if is_swing_high(actual, previous, next) => {
+                            stop = self.tighter(stop, actual.high.0);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            None => {}
+        }
+
+        self.stop = stop;
+        if self.r_at(stop) >= Decimal::ZERO {
+            self.position.move_to_break_even();
+        }
+    }
+
+    fn close(&mut self, fraction: Decimal, price: Decimal, kind: FillKind) {
+        self.realized += fraction * self.r_at(price);
+        self.remaining -= fraction;
+        self.exits.push(TradeExit {
+            price: DecimalVec(price),
+            fraction,
+            kind,
+        });
+    }
+
+    fn trade(&self, close_time: DateTime<Tz>) -> Trade {
+        let result = if self.realized > Decimal::ZERO {
+            TradeResult::Winner
+        } else if self.realized < Decimal::ZERO {
+            TradeResult::Expense
+        } else {
+            TradeResult::BreakEven
+        };
+        let mut trade = Trade::from_position(self.position, close_time, result);
+        trade.ambiguous = self.ambiguous;
+        trade.realized_r = Some(self.realized);
+        trade.exits = self.exits.clone();
+        trade
+    }
+
+    fn risk(&self) -> Decimal {
+        (self.position.entry.0 - self.position.sl.0).abs()
+    }
+
+    fn r_at(&self, price: Decimal) -> Decimal {
+        let risk = self.risk();
+        if risk.is_zero() {
+            return Decimal::ZERO;
+        }
+        match self.position.direction {
+            PositionDirection::Long => (price - self.position.entry.0) / risk,
+            PositionDirection::Short => (self.position.entry.0 - price) / risk,
+        }
+    }
+
+    fn price_at(&self, r: Decimal) -> Decimal {
+        match self.position.direction {
+            PositionDirection::Long => self.position.entry.0 + r * self.risk(),
+            PositionDirection::Short => self.position.entry.0 - r * self.risk(),
+        }
+    }
+
+    // the one of two stops closer to the price
+    fn tighter(&self, a: Decimal, b: Decimal) -> Decimal {
+        match self.position.direction {
+            PositionDirection::Long => a.max(b),
+            PositionDirection::Short => a.min(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_new_york_time;
+
+    // long from 100 at the close of the candle before `bar(0, ..)`, stopped at 90, the
+    // target at 130
+    fn long() -> Position {
+        Position {
+            direction: PositionDirection::Long,
+            open_time: to_new_york_time(-1),
+            entry: DecimalVec::new(100),
+            sl: DecimalVec::new(90),
+            tp: DecimalVec::new(130),
+            at_break_even: false,
+        }
+    }
+
+    // 15 minute candles after the entry, `ind` 0 opens at the entry
+    fn bar(ind: i64, high: i32, low: i32, close: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(ind * 900),
+            close_time: to_new_york_time(ind * 900 + 899),
+            open: DecimalVec::new(close),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(close),
+            volume: DecimalVec::new(0),
+            number_of_trades: 0,
+        }
+    }
+
+    fn run(management: TradeManagement, history: &[Candle], candles: &[Candle]) -> Option<Trade> {
+        let mut managed = ManagedPosition::new(long(), &management, history);
+        candles
+            .iter()
+            .find_map(|c| managed.update(*c, &AmbiguityPolicy::Pessimistic))
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_unmanaged_trade() {
+        let trade = run(
+            TradeManagement::default(),
+            &[],
+            &[bar(0, 110, 95, 105), bar(1, 131, 104, 125)],
+        )
+        .unwrap();
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.gross_r(), Decimal::from(3));
+        assert_eq!(trade.close_time, bar(1, 0, 0, 0).close_time);
+    }
+
+    #[test]
+    fn test_break_even() {
+        let management = TradeManagement {
+            break_even_r: Some(Decimal::ONE),
+            ..TradeManagement::default()
+        };
+        let mut managed = ManagedPosition::new(long(), &management, &[]);
+        let policy = AmbiguityPolicy::Pessimistic;
+
+        assert!(managed.update(bar(0, 109, 95, 105), &policy).is_none());
+        assert_eq!(managed.stop(), DecimalVec::new(90));
+        assert!(managed.update(bar(1, 111, 104, 108), &policy).is_none());
+        assert_eq!(managed.stop(), DecimalVec::new(100));
+        assert!(managed.position().at_break_even);
+
+        let trade = managed.update(bar(2, 108, 95, 96), &policy).unwrap();
+        assert_eq!(trade.result, TradeResult::BreakEven);
+        assert_eq!(trade.gross_r(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_take_profits_blend() {
+        let management = TradeManagement {
+            take_profits: vec![
+                TakeProfit {
+                    r: Decimal::TWO,
+                    fraction: d("0.25"),
+                },
+                TakeProfit {
+                    r: Decimal::ONE,
+                    fraction: d("0.5"),
+                },
+            ],
+            ..TradeManagement::default()
+        };
+
+        // half at 1R, a quarter at 2R and the rest at the 3R target
+        let trade = run(
+            management.clone(),
+            &[],
+            &[bar(0, 111, 95, 108), bar(1, 131, 105, 125)],
+        )
+        .unwrap();
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.gross_r(), d("1.75"));
+        assert_eq!(trade.realized_r, Some(d("1.75")));
+        let exits: Vec<_> = trade.exits.iter().map(|e| (e.price, e.kind)).collect();
+        assert_eq!(
+            exits,
+            vec![
+                (DecimalVec::new(110), FillKind::Limit),
+                (DecimalVec::new(120), FillKind::Limit),
+                (DecimalVec::new(130), FillKind::Limit)
+            ]
+        );
+
+        // half at 1R, the rest stopped out
+        let trade = run(
+            management,
+            &[],
+            &[bar(0, 111, 95, 108), bar(1, 108, 85, 88)],
+        )
+        .unwrap();
+        assert_eq!(trade.result, TradeResult::BreakEven);
+        assert_eq!(trade.gross_r(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_take_profit_and_stop_on_one_candle() {
+        let management = TradeManagement {
+            take_profits: vec![TakeProfit {
+                r: Decimal::ONE,
+                fraction: d("0.5"),
+            }],
+            ..TradeManagement::default()
+        };
+        let candle = bar(0, 111, 85, 95);
+
+        let mut managed = ManagedPosition::new(long(), &management, &[]);
+        let trade = managed
+            .update(candle, &AmbiguityPolicy::Optimistic)
+            .unwrap();
+        assert!(trade.ambiguous);
+        assert_eq!(trade.gross_r(), Decimal::ZERO);
+
+        let mut managed = ManagedPosition::new(long(), &management, &[]);
+        let trade = managed
+            .update(candle, &AmbiguityPolicy::Pessimistic)
+            .unwrap();
+        assert!(trade.ambiguous);
+        assert_eq!(trade.gross_r(), Decimal::NEGATIVE_ONE);
+    }
+
+    #[test]
+    fn test_fixed_r_trailing() {
+        let management = TradeManagement {
+            trailing: Some(Trailing::FixedR { r: Decimal::ONE }),
+            ..TradeManagement::default()
+        };
+        let trade = run(
+            management,
+            &[],
+            &[
+                bar(0, 105, 95, 104),
+                bar(1, 120, 103, 118),
+                // the stop doesn't move back down
+                bar(2, 119, 112, 113),
+                bar(3, 114, 105, 106),
+            ],
+        )
+        .unwrap();
+        assert_eq!(trade.gross_r(), Decimal::ONE);
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.close_time, bar(3, 0, 0, 0).close_time);
+        assert_eq!(
+            trade.exits,
+            vec![TradeExit {
+                price: DecimalVec::new(110),
+                fraction: Decimal::ONE,
+                kind: FillKind::Stop,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_atr_trailing() {
+        let management = TradeManagement {
+            trailing: Some(Trailing::Atr {
+                period: 2,
+                multiple: Decimal::ONE,
+            }),
+            ..TradeManagement::default()
+        };
+        // true ranges of 4 before the entry
+        let history = [
+            bar(-3, 102, 98, 100),
+            bar(-2, 102, 98, 100),
+            bar(-1, 102, 98, 100),
+        ];
+        let mut managed = ManagedPosition::new(long(), &management, &history);
+        let policy = AmbiguityPolicy::Pessimistic;
+
+        assert!(managed.update(bar(0, 116, 112, 114), &policy).is_none());
+        // wilder's average of 4 and a true range of 16, the best price is 116
+        assert_eq!(managed.stop(), DecimalVec::new(106));
+        let trade = managed.update(bar(1, 115, 105, 107), &policy).unwrap();
+        assert_eq!(trade.gross_r(), d("0.6"));
+    }
+
+    #[test]
+    fn test_swing_trailing() {
+        let management = TradeManagement {
+            trailing: Some(Trailing::Swing),
+            ..TradeManagement::default()
+        };
+        let mut managed = ManagedPosition::new(long(), &management, &[]);
+        let policy = AmbiguityPolicy::Pessimistic;
+
+        managed.update(bar(0, 106, 101, 105), &policy);
+        managed.update(bar(1, 108, 103, 107), &policy);
+        managed.update(bar(2, 110, 105, 109), &policy);
+        assert_eq!(managed.stop(), DecimalVec::new(90));
+        // 103 after a swing low at 102
+        managed.update(bar(3, 109, 102, 104), &policy);
+        managed.update(bar(4, 112, 104, 111), &policy);
+        assert_eq!(managed.stop(), DecimalVec::new(102));
+
+        let trade = managed.update(bar(5, 111, 99, 100), &policy).unwrap();
+        assert_eq!(trade.gross_r(), d("0.2"));
+    }
+
+    #[test]
+    fn test_time_exit() {
+        let management = TradeManagement {
+            time_exit_min: Some(30),
+            ..TradeManagement::default()
+        };
+        let trade = run(
+            management,
+            &[],
+            &[
+                bar(0, 105, 95, 104),
+                bar(1, 108, 103, 107),
+                bar(2, 131, 103, 125),
+            ],
+        )
+        .unwrap();
+        // the second candle closes 30 minutes after the entry
+        assert_eq!(trade.close_time, bar(1, 0, 0, 0).close_time);
+        assert_eq!(trade.gross_r(), d("0.7"));
+        assert_eq!(trade.exits[0].kind, FillKind::Market);
+    }
+}
@@ -0,0 +1,277 @@
+use rust_decimal::Decimal;
+
+use crate::engine::{CandleModel, CandleStrategy, CandleView, Order, OrderBook};
+use crate::model::ambiguity_policy::AmbiguityPolicy;
+use crate::model::cost_model::CostModel;
+use crate::model::decimal::DecimalVec;
+use crate::model::position_direction::PositionDirection;
+use crate::model::trade_management::TradeManagement;
+use crate::model::trigger_type::TriggerType;
+
+use super::lib::{trigger_or_invalidation, Trigger};
+
+// A planned trade: enter once price breaks the trigger level in `direction`, unless it reaches
+// the invalidation level first. The invalidation level is the stop. `Plan` is the trigger path,
+// it replaces `trigger_mayne` on the candle engine.
+pub struct Mayne {
+    pub direction: PositionDirection,
+    pub trigger_type: TriggerType,
+    pub trigger_level: DecimalVec,
+    pub invalidation_level: DecimalVec,
+    pub tp: DecimalVec,
+    pub rr_threshold: Decimal,
+    pub management: TradeManagement,
+    pub costs: CostModel,
+    pub ambiguity: AmbiguityPolicy,
+}
+
+impl CandleStrategy for Mayne {
+    type Model = Plan;
+
+    fn model(&self) -> Plan {
+        Plan {
+            direction: self.direction,
+            trigger_type: self.trigger_type,
+            trigger_level: self.trigger_level,
+            invalidation_level: self.invalidation_level,
+            tp: self.tp,
+            rr_threshold: self.rr_threshold,
+            done: false,
+        }
+    }
+
+    fn management(&self) -> TradeManagement {
+        self.management.clone()
+    }
+
+    fn costs(&self) -> &CostModel {
+        &self.costs
+    }
+
+    fn ambiguity(&self) -> &AmbiguityPolicy {
+        &self.ambiguity
+    }
+}
+
+// What `trigger_mayne` did on the whole series, a candle at a time: the first candle to
+// trigger or invalidate decides, and a trigger enters at its close when the RR from there
+// reaches the threshold. The market order takes the place of `run_trade`, the engine fills it
+// at that close and manages the position from the next candle with the history up to the
+// trigger, costs applied on the close.
+pub struct Plan {
+    direction: PositionDirection,
+    trigger_type: TriggerType,
+    trigger_level: DecimalVec,
+    invalidation_level: DecimalVec,
+    tp: DecimalVec,
+    rr_threshold: Decimal,
+    // triggered or invalidated, the plan is one trade at most
+    done: bool,
+}
+
+impl CandleModel for Plan {
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+        if self.done {
+            return;
+        }
+        let actual = view.actual();
+        match trigger_or_invalidation(
+            actual,
+            self.direction,
+            self.trigger_level,
+            self.invalidation_level,
+            self.trigger_type,
+        ) {
+            None => {}
+            Some(Trigger::Invalidated) => self.done = true,
+            Some(Trigger::Triggered) => {
+                self.done = true;
+                // a wick trigger can close right at the stop, that's no trade
+                let order = Order::market(self.direction, self.invalidation_level, self.tp);
+                if order
+                    .rr(actual.close)
+                    .is_some_and(|rr| rr.0 >= self.rr_threshold)
+                {
+                    orders.place(order);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::engine::OnCandles;
+    use crate::model::candle::Candle;
+    use crate::model::trade_result::TradeResult;
+    use crate::model::trading_model::TradingModel;
+    use crate::parse_datetime;
+
+    fn date(date_time: &str) -> chrono::DateTime<chrono_tz::Tz> {
+        parse_datetime(date_time).unwrap()
+    }
+
+    fn candlestick(duration: i64, open: i32, high: i32, low: i32, close: i32) -> Candle {
+        Candle {
+            open_time: date("2022-09-30 08:50:00") + Duration::minutes(duration),
+            close_time: date("2022-09-30 08:51:00") + Duration::minutes(duration),
+            open: DecimalVec::new(open),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(close),
+            volume: DecimalVec::new(0),
+            number_of_trades: 0,
+        }
+    }
+
+    // short below 100 with the stop at 110 and the target at 70
+    fn short(trigger_type: TriggerType, candles: Vec<Candle>) -> OnCandles<Mayne> {
+        OnCandles {
+            candles,
+            strategy: Mayne {
+                direction: PositionDirection::Short,
+                trigger_type,
+                trigger_level: DecimalVec::new(100),
+                invalidation_level: DecimalVec::new(110),
+                tp: DecimalVec::new(70),
+                rr_threshold: Decimal::from(2),
+                management: TradeManagement::default(),
+                costs: CostModel::default(),
+                ambiguity: AmbiguityPolicy::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_no_trigger() {
+        let result = short(
+            TriggerType::Close,
+            vec![
+                candlestick(0, 102, 105, 101, 103),
+                candlestick(1, 103, 104, 100, 101),
+            ],
+        )
+        .execute();
+        assert!(result.trades.is_empty());
+    }
+
+    #[test]
+    fn test_invalidated_before_trigger() {
+        let result = short(
+            TriggerType::Close,
+            vec![
+                candlestick(0, 102, 110, 101, 103),
+                candlestick(1, 103, 104, 90, 95),
+                candlestick(2, 95, 96, 60, 65),
+            ],
+        )
+        .execute();
+        assert!(result.trades.is_empty());
+    }
+
+    #[test]
+    fn test_close_trigger_to_target() {
+        let result = short(
+            TriggerType::Close,
+            vec![
+                // the wick below 100 doesn't trigger on close
+                candlestick(0, 102, 105, 99, 101),
+                candlestick(1, 101, 102, 96, 97),
+                candlestick(2, 97, 98, 80, 85),
+                candlestick(3, 85, 86, 69, 72),
+            ],
+        )
+        .execute();
+
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.direction, PositionDirection::Short);
+        assert_eq!(trade.open_time, date("2022-09-30 08:52:00"));
+        assert_eq!(trade.entry, DecimalVec::new(97));
+        assert_eq!(trade.sl, DecimalVec::new(110));
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.close_time, date("2022-09-30 08:54:00"));
+    }
+
+    #[test]
+    fn test_wick_trigger_to_stop() {
+        let result = short(
+            TriggerType::Wick,
+            vec![
+                candlestick(0, 102, 105, 99, 101),
+                candlestick(1, 101, 108, 100, 107),
+                candlestick(2, 107, 111, 104, 109),
+            ],
+        )
+        .execute();
+
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.open_time, date("2022-09-30 08:51:00"));
+        assert_eq!(trade.entry, DecimalVec::new(101));
+        assert_eq!(trade.result, TradeResult::Expense);
+        assert_eq!(trade.close_time, date("2022-09-30 08:53:00"));
+    }
+
+    #[test]
+    fn test_wick_trigger_closing_at_the_stop() {
+        let result = short(
+            TriggerType::Wick,
+            vec![
+                candlestick(0, 102, 105, 101, 103),
+                candlestick(1, 103, 110, 99, 110),
+                candlestick(2, 110, 111, 60, 65),
+            ],
+        )
+        .execute();
+        assert!(result.trades.is_empty());
+    }
+
+    #[test]
+    fn test_rr_threshold() {
+        let mut model = short(
+            TriggerType::Close,
+            vec![
+                candlestick(0, 102, 105, 95, 96),
+                candlestick(1, 96, 97, 55, 65),
+            ],
+        );
+        // 96 risking 14 to make 26 is below 2R
+        model.strategy.tp = DecimalVec::new(70);
+        assert!(model.execute().trades.is_empty());
+
+        model.strategy.tp = DecimalVec::new(60);
+        assert_eq!(model.execute().trades.len(), 1);
+    }
+
+    #[test]
+    fn test_long() {
+        let result = OnCandles {
+            candles: vec![
+                candlestick(0, 98, 99, 95, 97),
+                candlestick(1, 97, 102, 96, 101),
+                candlestick(2, 101, 125, 100, 120),
+            ],
+            strategy: Mayne {
+                direction: PositionDirection::Long,
+                trigger_type: TriggerType::Close,
+                trigger_level: DecimalVec::new(100),
+                invalidation_level: DecimalVec::new(94),
+                tp: DecimalVec::new(120),
+                rr_threshold: Decimal::from(2),
+                management: TradeManagement::default(),
+                costs: CostModel::default(),
+                ambiguity: AmbiguityPolicy::default(),
+            },
+        }
+        .execute();
+
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].direction, PositionDirection::Long);
+        assert_eq!(result.trades[0].entry, DecimalVec::new(101));
+        assert_eq!(result.trades[0].result, TradeResult::Winner);
+    }
+}
@@ -1,4 +1,6 @@
 pub mod lib;
 pub mod macro_soup;
+pub mod management;
 pub mod mayne;
 pub mod sfp;
+pub mod sfp_ltf;
@@ -1,130 +1,219 @@
 use rust_decimal::Decimal;
 
-use crate::model::backtest_result::BacktestResult;
-use crate::model::candle_stick::CandleStick;
-use crate::model::position::Position;
+use crate::engine::{CandleModel, CandleStrategy, CandleView, Order, OrderBook};
+use crate::model::ambiguity_policy::AmbiguityPolicy;
+use crate::model::cost_model::CostModel;
 use crate::model::position_direction::PositionDirection;
-use crate::model::trade::Trade;
-use crate::model::trade_result::TradeResult;
-use crate::model::trading_model::TradingModel;
+use crate::model::trade_management::TradeManagement;
 
-use super::lib::add_to_swings;
+use super::lib::{SwingConfig, SwingTracker};
 
 pub struct Sfp {
     pub rr_treshold: Decimal,
-    pub data: Vec<CandleStick>,
+    pub swings: SwingConfig,
+    pub management: TradeManagement,
+    pub costs: CostModel,
+    pub ambiguity: AmbiguityPolicy,
 }
 
-impl TradingModel for Sfp {
-    fn execute(&self) -> BacktestResult {
-        let mut swing_lows: Vec<CandleStick> = vec![];
-        let mut swing_highs: Vec<CandleStick> = vec![];
-        let mut position: Option<Position> = None;
-        let mut trades: Vec<Trade> = vec![];
-
-        let mut ind = 0;
-        while ind < self.data.len() {
-            if ind > 0 && ind < self.data.len() - 1 {
-                let actual = self.data[ind];
-                let previous = self.data[ind - 1];
-                let next = self.data[ind + 1];
-
-                if position.is_some() {
-                    // we are in a trade
-                    let trade = position.unwrap();
-                    match trade.direction {
-                        PositionDirection::Short => {
-                            if trade.sl < actual.high {
-                                // TODO: handle BE
-                                trades.push(Trade::from_position(
-                                    trade,
-                                    actual.close_time,
-                                    TradeResult::Expense,
-                                ));
-                                position = None;
-                            }
-                            if trade.tp > actual.low {
-                                trades.push(Trade::from_position(
-                                    trade,
-                                    actual.close_time,
-                                    TradeResult::Winner,
-                                ));
-                                position = None;
-                            }
-                        }
-                        PositionDirection::Long => {
-                            if trade.sl > actual.low {
-                                trades.push(Trade::from_position(
-                                    trade,
-                                    actual.close_time,
-                                    TradeResult::Expense,
-                                ));
-                                position = None;
-                            }
-                            if trade.tp < actual.high {
-                                trades.push(Trade::from_position(
-                                    trade,
-                                    actual.close_time,
-                                    TradeResult::Winner,
-                                ));
-                                position = None;
-                            }
-                        }
-                    }
-                }
-
-                // trade
-                let sfp_high = swing_highs
-                    .iter()
-                    .find(|x| {
-                        x.close_time < actual.close_time
-                            && x.high < actual.high
-                            && x.high > actual.close
-                    })
-                    .is_some();
-                let prev_low = swing_lows.iter().last();
-                if sfp_high && position.is_none() && prev_low.is_some() {
-                    let position_candidate = Position {
-                        direction: PositionDirection::Short,
-                        open_time: actual.close_time,
-                        entry: actual.close,
-                        sl: actual.high,
-                        tp: prev_low.unwrap().low,
-                        at_break_even: false,
-                    };
-                    if position_candidate.rr().0 >= self.rr_treshold {
-                        position = Some(position_candidate);
-                    }
-                }
-
-                let sfp_low = swing_lows
-                    .iter()
-                    .find(|x| {
-                        x.close_time < actual.close_time
-                            && x.low > actual.low
-                            && x.low < actual.close
-                    })
-                    .is_some();
-                let prev_high = swing_highs.iter().last();
-                if sfp_low && position.is_none() && prev_high.is_some() {
-                    let position_candidate = Position {
-                        direction: PositionDirection::Long,
-                        open_time: actual.close_time,
-                        entry: actual.close,
-                        sl: actual.low,
-                        tp: prev_high.unwrap().high,
-                        at_break_even: false,
-                    };
-                    if position_candidate.rr().0 >= self.rr_treshold {
-                        position = Some(position_candidate);
-                    }
-                }
-
-                add_to_swings(&mut swing_lows, &mut swing_highs, actual, previous, next)
+impl CandleStrategy for Sfp {
+    type Model = Signals;
+
+    fn model(&self) -> Signals {
+        Signals {
+            rr_treshold: self.rr_treshold,
+            swings: SwingTracker::new(self.swings),
+        }
+    }
+
+    fn management(&self) -> TradeManagement {
+        self.management.clone()
+    }
+
+    fn costs(&self) -> &CostModel {
+        &self.costs
+    }
+
+    fn ambiguity(&self) -> &AmbiguityPolicy {
+        &self.ambiguity
+    }
+}
+
+pub struct Signals {
+    rr_treshold: Decimal,
+    swings: SwingTracker,
+}
+
+impl CandleModel for Signals {
+    fn on_candle(&mut self, view: &CandleView, orders: &mut OrderBook) {
+        let actual = view.actual();
+        // confirms the swings whose right side closes with this candle
+        self.swings.update(actual);
+        if !view.flat {
+            return;
+        }
+
+        let sfp_high = self.swings.highs().iter().any(|x| {
+            x.close_time < actual.close_time && x.high < actual.high && x.high > actual.close
+        });
+        if let (true, Some(prev_low)) = (sfp_high, self.swings.lows().last()) {
+            let order = Order::market(PositionDirection::Short, actual.high, prev_low.low);
+            if order
+                .rr(actual.close)
+                .is_some_and(|rr| rr.0 >= self.rr_treshold)
+            {
+                orders.place(order);
+                return;
+            }
+        }
+
+        let sfp_low = self.swings.lows().iter().any(|x| {
+            x.close_time < actual.close_time && x.low > actual.low && x.low < actual.close
+        });
+        if let (true, Some(prev_high)) = (sfp_low, self.swings.highs().last()) {
+            let order = Order::market(PositionDirection::Long, actual.low, prev_high.high);
+            if order
+                .rr(actual.close)
+                .is_some_and(|rr| rr.0 >= self.rr_treshold)
+            {
+                orders.place(order);
             }
-            ind = ind + 1;
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::OnCandles;
+    use crate::model::{
+        candle::Candle, decimal::DecimalVec, trade_result::TradeResult, trading_model::TradingModel,
+    };
+    use crate::to_new_york_time;
+
+    fn bar(ind: i64, open: i32, high: i32, low: i32, close: i32) -> Candle {
+        Candle {
+            open_time: to_new_york_time(ind * 60),
+            close_time: to_new_york_time(ind * 60 + 59),
+            open: DecimalVec::new(open),
+            high: DecimalVec::new(high),
+            low: DecimalVec::new(low),
+            close: DecimalVec::new(close),
+            volume: DecimalVec::new(1),
+            number_of_trades: 1,
+        }
+    }
+
+    // a swing high at 110 and a swing low at 80, then a sweep of 110 closing back below it and
+    // a run to 80
+    fn series() -> Vec<Candle> {
+        vec![
+            bar(0, 100, 102, 98, 101),
+            bar(1, 101, 110, 100, 105),
+            bar(2, 105, 106, 80, 90),
+            bar(3, 90, 104, 85, 103),
+            bar(4, 103, 112, 101, 106),
+            bar(5, 106, 107, 78, 79),
+        ]
+    }
+
+    fn sfp() -> Sfp {
+        Sfp {
+            rr_treshold: Decimal::from(2),
+            swings: SwingConfig::default(),
+            management: TradeManagement::default(),
+            costs: CostModel::default(),
+            ambiguity: AmbiguityPolicy::Pessimistic,
+        }
+    }
+
+    // the orders the model leaves on every candle, `flat` while it is
+    fn orders(candles: &[Candle], flat: bool) -> Vec<Vec<Order>> {
+        let mut model = sfp().model();
+        (1..=candles.len())
+            .map(|end| {
+                let mut orders = OrderBook::default();
+                let view = CandleView {
+                    candles: &candles[..end],
+                    flat,
+                };
+                model.on_candle(&view, &mut orders);
+                orders.working().to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_swing_confirmed_by_its_right_candle() {
+        let candles = series();
+        let mut model = sfp().model();
+        let mut feed = |end: usize| {
+            let view = CandleView {
+                candles: &candles[..end],
+                flat: true,
+            };
+            model.on_candle(&view, &mut OrderBook::default());
+            model.swings.highs().to_vec()
+        };
+        assert!(feed(1).is_empty());
+        assert!(feed(2).is_empty());
+        assert_eq!(feed(3), vec![candles[1]]);
+    }
+
+    #[test]
+    fn test_sfp_places_a_market_order() {
+        let orders = orders(&series(), true);
+        let short = Order::market(
+            PositionDirection::Short,
+            DecimalVec::new(112),
+            DecimalVec::new(80),
+        );
+        assert_eq!(orders[4], vec![short]);
+        // nothing before the sweep
+        assert!(orders[..4].iter().all(|o| o.is_empty()));
+    }
+
+    #[test]
+    fn test_sfp_below_the_rr_threshold() {
+        let mut candles = series();
+        // closing at 104 risks 8 to make 24
+        candles[4].close = DecimalVec::new(104);
+        let mut model = sfp();
+        model.rr_treshold = Decimal::from(4);
+        let mut signals = model.model();
+        let mut orders = OrderBook::default();
+        for end in 1..=5 {
+            let view = CandleView {
+                candles: &candles[..end],
+                flat: true,
+            };
+            signals.on_candle(&view, &mut orders);
+        }
+        assert!(orders.working().is_empty());
+    }
+
+    #[test]
+    fn test_no_signal_in_a_position() {
+        assert!(orders(&series(), false).iter().all(|o| o.is_empty()));
+    }
+
+    #[test]
+    fn test_sfp_trade() {
+        let candles = series();
+        let result = OnCandles {
+            candles: candles.clone(),
+            strategy: sfp(),
+        }
+        .execute();
 
-        BacktestResult { trades }
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.direction, PositionDirection::Short);
+        assert_eq!(trade.open_time, candles[4].close_time);
+        assert_eq!(trade.entry, DecimalVec::new(106));
+        assert_eq!(trade.sl, DecimalVec::new(112));
+        assert_eq!(trade.tp, DecimalVec::new(80));
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.close_time, candles[5].close_time);
     }
 }
@@ -0,0 +1,210 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::engine::{Lookahead, Order, OrderBook, Runner};
+use crate::model::ambiguity_policy::AmbiguityPolicy;
+use crate::model::backtest_result::BacktestResult;
+use crate::model::cost_model::CostModel;
+use crate::model::position_direction::PositionDirection;
+use crate::model::trade_management::TradeManagement;
+use crate::model::trading_model::TradingModel;
+use crate::mtf::{Mtf, MtfModel, MtfView};
+
+use super::lib::{SwingConfig, SwingTracker};
+
+// Sfp with the swings on the higher timeframe and the entry on the lower timeframe: once the
+// forming bar has swept a swing, the first lower timeframe close back inside enters.
+pub struct SfpLtf {
+    pub rr_treshold: Decimal,
+    pub mtf: Mtf,
+    // swings of the higher timeframe bars
+    pub swings: SwingConfig,
+    pub management: TradeManagement,
+    pub costs: CostModel,
+    pub ambiguity: AmbiguityPolicy,
+}
+
+impl SfpLtf {
+    fn signals(&self) -> Signals {
+        Signals {
+            rr_treshold: self.rr_treshold,
+            swings: SwingTracker::new(self.swings),
+            closed: 0,
+            last_entry: None,
+        }
+    }
+}
+
+impl TradingModel for SfpLtf {
+    fn execute(&self) -> BacktestResult {
+        self.mtf.run(
+            &mut self.signals(),
+            &self.management,
+            &self.costs,
+            &self.ambiguity,
+        )
+    }
+
+    fn check_lookahead(&self) -> Option<Vec<Lookahead>> {
+        Some(self.mtf.check_lookahead(
+            |_| self.signals(),
+            &self.management,
+            &self.costs,
+            &self.ambiguity,
+        ))
+    }
+
+    fn runner(&self) -> Option<Runner<'_>> {
+        Some(self.mtf.runner(
+            self.signals(),
+            &self.management,
+            &self.costs,
+            &self.ambiguity,
+        ))
+    }
+}
+
+struct Signals {
+    rr_treshold: Decimal,
+    swings: SwingTracker,
+    // closed higher timeframe bars already fed to the swings
+    closed: usize,
+    // open time of the higher timeframe bar of the last entry, one entry per bar
+    last_entry: Option<DateTime<Tz>>,
+}
+
+impl MtfModel for Signals {
+    fn on_ltf(&mut self, view: &MtfView, orders: &mut OrderBook) {
+        while self.closed < view.htf.len() {
+            self.swings.update(view.htf[self.closed]);
+            self.closed += 1;
+        }
+        if self.last_entry == Some(view.forming.open_time) {
+            return;
+        }
+
+        let actual = view.actual();
+        let swept_high = self
+            .swings
+            .highs()
+            .iter()
+            .any(|x| x.high < view.forming.high && x.high > actual.close);
+        let swept_low = self
+            .swings
+            .lows()
+            .iter()
+            .any(|x| x.low > view.forming.low && x.low < actual.close);
+
+        let candidate =
+            match (swept_high, swept_low) {
+                (true, false) => {
+                    self.swings.lows().last().map(|low| {
+                        Order::market(PositionDirection::Short, view.forming.high, low.low)
+                    })
+                }
+                (false, true) => self.swings.highs().last().map(|high| {
+                    Order::market(PositionDirection::Long, view.forming.low, high.high)
+                }),
+                // both sides swept in one bar, no bias
+                _ => None,
+            };
+
+        if let Some(order) = candidate.filter(|o| {
+            o.rr(actual.close)
+                .is_some_and(|rr| rr.0 >= self.rr_treshold)
+        }) {
+            self.last_entry = Some(view.forming.open_time);
+            orders.place(order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{candle::Candle, decimal::DecimalVec, trade_result::TradeResult};
+    use crate::to_new_york_time;
+
+    // 5 minute candles, every three make a 15 minute bar
+    fn ltf(prices: &[(i32, i32, i32, i32)]) -> Vec<Candle> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(ind, (open, high, low, close))| Candle {
+                open_time: to_new_york_time(ind as i64 * 300),
+                close_time: to_new_york_time(ind as i64 * 300 + 299),
+                open: DecimalVec::new(*open),
+                high: DecimalVec::new(*high),
+                low: DecimalVec::new(*low),
+                close: DecimalVec::new(*close),
+                volume: DecimalVec::new(0),
+                number_of_trades: 0,
+            })
+            .collect()
+    }
+
+    fn htf(ltf: &[Candle]) -> Vec<Candle> {
+        ltf.chunks(3)
+            .map(|c| Candle {
+                open_time: c[0].open_time,
+                close_time: c[2].close_time,
+                open: c[0].open,
+                high: DecimalVec(c.iter().map(|x| x.high.0).max().unwrap()),
+                low: DecimalVec(c.iter().map(|x| x.low.0).min().unwrap()),
+                close: c[2].close,
+                volume: DecimalVec::new(0),
+                number_of_trades: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_enters_on_ltf_close_back_below_swept_high() {
+        let ltf = ltf(&[
+            (100, 105, 100, 104),
+            (104, 105, 101, 103),
+            (103, 104, 101, 102),
+            // swing high at 110
+            (102, 110, 102, 108),
+            (108, 109, 104, 105),
+            (105, 106, 103, 104),
+            // swing low at 95
+            (104, 106, 95, 97),
+            (97, 100, 96, 99),
+            (99, 101, 98, 100),
+            (100, 104, 98, 103),
+            (103, 104, 100, 101),
+            (101, 102, 99, 102),
+            // sweeps 110, the first close doesn't get back below it, the second does
+            (102, 108, 102, 107),
+            (107, 112, 106, 111),
+            (111, 111, 108, 109),
+            // runs to the swing low
+            (109, 110, 100, 101),
+            (101, 102, 94, 95),
+            (95, 96, 94, 95),
+        ]);
+        let model = SfpLtf {
+            rr_treshold: Decimal::from(2),
+            mtf: Mtf::new(htf(&ltf), ltf.clone()).unwrap(),
+            swings: SwingConfig::default(),
+            management: TradeManagement::default(),
+            costs: CostModel::default(),
+            ambiguity: AmbiguityPolicy::Pessimistic,
+        };
+        let result = model.execute();
+
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.direction, PositionDirection::Short);
+        assert_eq!(trade.open_time, ltf[14].close_time);
+        assert_eq!(trade.entry, DecimalVec::new(109));
+        assert_eq!(trade.sl, DecimalVec::new(112));
+        assert_eq!(trade.tp, DecimalVec::new(95));
+        assert_eq!(trade.result, TradeResult::Winner);
+        assert_eq!(trade.close_time, ltf[16].close_time);
+
+        assert_eq!(model.check_lookahead(), Some(vec![]));
+    }
+}
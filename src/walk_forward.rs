@@ -0,0 +1,231 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use std::io::{self, Write};
+
+use crate::model::{backtest_result::BacktestResult, candle::Candle};
+use crate::optimizer::{format_params, optimize, Metric, ModelFactory, ParamSet};
+
+pub struct WalkForward {
+    pub in_sample: Duration,
+    pub out_of_sample: Duration,
+}
+
+pub struct Window {
+    pub in_sample_start: DateTime<Tz>,
+    pub out_of_sample_start: DateTime<Tz>,
+    pub out_of_sample_end: DateTime<Tz>,
+}
+
+pub struct WindowResult {
+    pub window: Window,
+    // None when no parameter set produced a score in sample
+    pub params: Option<ParamSet>,
+    pub in_sample_score: Option<Decimal>,
+    pub out_of_sample: BacktestResult,
+}
+
+pub struct WalkForwardResult {
+    pub windows: Vec<WindowResult>,
+    // every out of sample trade, in window order
+    pub result: BacktestResult,
+}
+
+impl WalkForward {
+    // rolling windows, each one moved forward by the out of sample length
+    pub fn windows(&self, candles: &[Candle]) -> Result<Vec<Window>> {
+        if self.in_sample <= Duration::zero() || self.out_of_sample <= Duration::zero() {
+            bail!("in sample and out of sample lengths must be positive");
+        }
+        let (first, last) = match (candles.first(), candles.last()) {
+            (Some(first), Some(last)) => (first.open_time, last.open_time),
+            _ => return Ok(vec![]),
+        };
+
+        let mut windows = vec![];
+        let mut start = first;
+        while start + self.in_sample <= last {
+            windows.push(Window {
+                in_sample_start: start,
+                out_of_sample_start: start + self.in_sample,
+                out_of_sample_end: start + self.in_sample + self.out_of_sample,
+            });
+            start += self.out_of_sample;
+        }
+        Ok(windows)
+    }
+
+    // optimizes on each in sample window and trades the best parameters on the following out
+    // of sample window; trades still open at the end of a window are dropped, not carried over
+    pub fn run<F: ModelFactory>(
+        &self,
+        factory: &F,
+        sets: &[ParamSet],
+        candles: &[Candle],
+        metric: Metric,
+        threads: Option<usize>,
+    ) -> Result<WalkForwardResult> {
+        let mut windows = vec![];
+        let mut trades = vec![];
+
+        for window in self.windows(candles)? {
+            let in_sample = slice(candles, window.in_sample_start, window.out_of_sample_start);
+            let out_of_sample = slice(
+                candles,
+                window.out_of_sample_start,
+                window.out_of_sample_end,
+            );
+
+            let optimization = optimize(factory, sets.to_vec(), in_sample, metric, threads);
+            let best = optimization.best().filter(|r| r.score.is_some());
+
+            let out_of_sample = match best {
+                Some(best) => factory
+                    .build(&best.params, out_of_sample.to_vec())?
+                    .execute(),
+                None => BacktestResult { trades: vec![] },
+            };
+            trades.extend(out_of_sample.trades.iter().cloned());
+
+            windows.push(WindowResult {
+                window,
+                params: best.map(|r| r.params.clone()),
+                in_sample_score: best.and_then(|r| r.score),
+                out_of_sample,
+            });
+        }
+
+        Ok(WalkForwardResult {
+            windows,
+            result: BacktestResult { trades },
+        })
+    }
+}
+
+fn slice(candles: &[Candle], from: DateTime<Tz>, to: DateTime<Tz>) -> &[Candle] {
+    let start = candles.partition_point(|c| c.open_time < from);
+    let end = candles.partition_point(|c| c.open_time < to);
+    &candles[start..end]
+}
+
+impl WalkForwardResult {
+    // one csv row per window with the chosen parameters and the out of sample statistics
+    pub fn write_table<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "in_sample_start,out_of_sample_start,out_of_sample_end,params,in_sample_score,\
+             number_of_trades,profit_in_r,expectancy,max_drawdown"
+        )?;
+        for w in &self.windows {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                w.window.in_sample_start.to_rfc3339(),
+                w.window.out_of_sample_start.to_rfc3339(),
+                w.window.out_of_sample_end.to_rfc3339(),
+                w.params.as_ref().map(format_params).unwrap_or_default(),
+                w.in_sample_score.map(|s| s.to_string()).unwrap_or_default(),
+                w.out_of_sample.number_of_trades(),
+                w.out_of_sample.profit_in_r(),
+                w.out_of_sample
+                    .expectancy()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                w.out_of_sample.max_drawdown().depth_in_r,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::StrategyTemplate;
+    use crate::data::{BinanceJsonSource, CandleSource};
+    use crate::optimizer::{grid, ParamRange};
+    use serde_json::json;
+
+    fn candles() -> Vec<Candle> {
+        BinanceJsonSource::new("assets/eth15.json").load().unwrap()
+    }
+
+    fn walk_forward() -> WalkForward {
+        WalkForward {
+            in_sample: Duration::hours(8),
+            out_of_sample: Duration::hours(4),
+        }
+    }
+
+    #[test]
+    fn test_windows_roll_by_out_of_sample() {
+        let candles = candles();
+        let windows = walk_forward().windows(&candles).unwrap();
+
+        // the last of 129 candles of 15 minutes opens 32 hours after the first
+        assert_eq!(windows.len(), 7);
+        assert_eq!(windows[0].in_sample_start, candles[0].open_time);
+        assert_eq!(
+            windows[1].in_sample_start,
+            candles[0].open_time + Duration::hours(4)
+        );
+        for w in &windows {
+            assert_eq!(
+                w.out_of_sample_start - w.in_sample_start,
+                Duration::hours(8)
+            );
+            assert_eq!(
+                w.out_of_sample_end - w.out_of_sample_start,
+                Duration::hours(4)
+            );
+        }
+    }
+
+    #[test]
+    fn test_windows_invalid_lengths() {
+        let wf = WalkForward {
+            in_sample: Duration::zero(),
+            out_of_sample: Duration::hours(4),
+        };
+        assert!(wf.windows(&candles()).is_err());
+    }
+
+    #[test]
+    fn test_run_stitches_out_of_sample_trades() {
+        let candles = candles();
+        let template = StrategyTemplate::new(json!({ "type": "sfp", "rr_treshold": 1 }));
+        let ranges: BTreeMap<String, ParamRange> =
+            serde_json::from_value(json!({ "rr_treshold": [1, 2, 3] })).unwrap();
+
+        let result = walk_forward()
+            .run(
+                &template,
+                &grid(&ranges).unwrap(),
+                &candles,
+                Metric::ProfitInR,
+                Some(2),
+            )
+            .unwrap();
+
+        assert_eq!(result.windows.len(), 7);
+        let window_trades: usize = result
+            .windows
+            .iter()
+            .map(|w| w.out_of_sample.number_of_trades())
+            .sum();
+        assert_eq!(result.result.number_of_trades(), window_trades);
+        for w in &result.windows {
+            for trade in &w.out_of_sample.trades {
+                assert!(trade.open_time >= w.window.out_of_sample_start);
+                assert!(trade.close_time <= w.window.out_of_sample_end);
+            }
+        }
+
+        let mut table = vec![];
+        result.write_table(&mut table).unwrap();
+        assert_eq!(String::from_utf8(table).unwrap().lines().count(), 8);
+    }
+}